@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use crate::time::format_compact_timestamp;
+
+/// How many earlier versions of the global store are kept unless its settings say otherwise.
+pub const DEFAULT_BACKUP_COUNT: usize = 10;
+
+/// A copy of an alias file from before it was overwritten.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Backup {
+  /// When the copy was made, as a compact UTC timestamp, followed by `-2`, `-3` and so on if more than one copy was
+  /// made in the same second.
+  pub id: String,
+  pub path: PathBuf,
+}
+
+/// The directory the backups of the alias file at config_path are kept in, next to the file itself.
+pub fn backup_directory(config_path: &Path) -> PathBuf {
+  config_path.parent().unwrap_or(Path::new("")).join("backups")
+}
+
+/// The order of backups by id, oldest first. None if id is not the id of a backup.
+fn sort_key(id: &str) -> Option<(&str, u32)> {
+  let (timestamp, number) = match id.get(15..) {
+    Some("") => (id, 1),
+    Some(rest) => (&id[..15], rest.strip_prefix('-')?.parse().ok().filter(|number| *number > 1)?),
+    None => return None,
+  };
+  let is_timestamp = timestamp.char_indices().all(|(index, c)| if index == 8 { c == '-' } else { c.is_ascii_digit() });
+  is_timestamp.then_some((timestamp, number))
+}
+
+/// Lists the backups of the alias file at config_path, newest first. There are none if the directory they are kept in
+/// does not exist yet.
+pub fn list_backups(config_path: &Path) -> io::Result<Vec<Backup>> {
+  let prefix = format!("{}.", config_path.file_name().unwrap_or_default().to_string_lossy());
+  let entries = match fs::read_dir(backup_directory(config_path)) {
+    Ok(entries) => entries,
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(e),
+  };
+  let mut backups = Vec::new();
+  for entry in entries {
+    let entry = entry?;
+    let file_name = entry.file_name().to_string_lossy().to_string();
+    if let Some(id) = file_name.strip_prefix(&prefix).filter(|id| sort_key(id).is_some()) {
+      backups.push(Backup { id: id.to_string(), path: entry.path() });
+    }
+  }
+  backups.sort_by(|a, b| sort_key(&b.id).cmp(&sort_key(&a.id)));
+  Ok(backups)
+}
+
+pub fn find_backup(config_path: &Path, id: &str) -> io::Result<Option<Backup>> {
+  Ok(list_backups(config_path)?.into_iter().find(|backup| backup.id == id))
+}
+
+/// Copies the alias file at config_path into its backups directory before it is overwritten, then removes the oldest
+/// backups so that only keep of them are left. Nothing is copied if the file does not exist yet, and keep being 0 turns
+/// backups off.
+pub fn create_backup(config_path: &Path, keep: usize, time: u64) -> io::Result<Option<Backup>> {
+  if keep == 0 || !config_path.exists() {
+    return Ok(None);
+  }
+  let directory = backup_directory(config_path);
+  fs::create_dir_all(&directory)?;
+  let file_name = config_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+  let timestamp = format_compact_timestamp(time);
+  let id = (1..).map(|number| if number == 1 { timestamp.clone() } else { format!("{timestamp}-{number}") })
+    .find(|id| !directory.join(format!("{file_name}.{id}")).exists())
+    .unwrap_or(timestamp);
+  let path = directory.join(format!("{file_name}.{id}"));
+  // Copying keeps the permissions of the file, which matters as aliases may hold secrets.
+  fs::copy(config_path, &path)?;
+  for old in list_backups(config_path)?.into_iter().skip(keep) {
+    fs::remove_file(old.path)?;
+  }
+  Ok(Some(Backup { id, path }))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use super::{backup_directory, create_backup, find_backup, list_backups, sort_key};
+
+  #[test]
+  fn test_sort_key() {
+    assert_eq!(sort_key("20220301-133700"), Some(("20220301-133700", 1)));
+    assert_eq!(sort_key("20220301-133700-12"), Some(("20220301-133700", 12)));
+    assert_eq!(sort_key("20220301-133700-1"), None);
+    assert_eq!(sort_key("20220301-13370x"), None);
+    assert_eq!(sort_key("20220301"), None);
+    assert_eq!(sort_key("20220301-133700.tmp"), None);
+  }
+
+  #[test]
+  fn test_rotation() {
+    let directory = std::env::temp_dir().join(format!("alia-test-backups-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("cfg.alia");
+    assert_eq!(create_backup(&path, 2, 0).unwrap(), None);
+    fs::write(&path, "one").unwrap();
+    assert_eq!(create_backup(&path, 2, 0).unwrap().map(|backup| backup.id), Some("19700101-000000".to_string()));
+    fs::write(&path, "two").unwrap();
+    assert_eq!(create_backup(&path, 2, 0).unwrap().map(|backup| backup.id), Some("19700101-000000-2".to_string()));
+    fs::write(&path, "three").unwrap();
+    create_backup(&path, 2, 60).unwrap();
+    fs::write(directory.join("backups").join("other.alia.19700101-000000"), "").unwrap();
+    let ids: Vec<String> = list_backups(&path).unwrap().into_iter().map(|backup| backup.id).collect();
+    assert_eq!(ids, ["19700101-000100", "19700101-000000-2"]);
+    let backup = find_backup(&path, "19700101-000000-2").unwrap().unwrap();
+    assert_eq!(fs::read_to_string(backup.path).unwrap(), "two");
+    assert_eq!(find_backup(&path, "19700101-000000").unwrap(), None);
+    assert_eq!(create_backup(&path, 0, 120).unwrap(), None);
+    assert_eq!(list_backups(&path).unwrap().len(), 2);
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(backup_directory(std::path::Path::new("cfg.alia")), std::path::Path::new("backups"));
+  }
+}
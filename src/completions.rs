@@ -0,0 +1,267 @@
+use crate::process::Interpreter;
+use crate::shells::{script_from_lines, Shell};
+use crate::store::AliasStore;
+
+/// What an argument of a command or the value of a flag can be completed with.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Arg {
+  /// The name of an alias that is in effect.
+  Alias,
+  /// Free text such as a new name, a value or a path, which Alia cannot suggest anything for.
+  Text,
+  OneOf(&'static [&'static str]),
+  /// A tag that one of the aliases in effect has.
+  Tag,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Flag {
+  pub name: &'static str,
+  /// The value the flag expects, or None if it does not take one.
+  pub value: Option<Arg>,
+}
+
+/// Everything completion needs to know about a command. This has to be kept in sync with `parse_arg`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct CommandInfo {
+  /// Every name of the command, ending with the one that is suggested.
+  pub names: &'static [&'static str],
+  pub flags: &'static [Flag],
+  pub args: &'static [Arg],
+  /// Whether any args after the ones in args are passed on instead of being parsed as the next command.
+  pub forwards_args: bool,
+  /// Hidden commands are used by the scripts Alia generates and are never suggested.
+  pub hidden: bool,
+  /// Whether the command may change an alias file.
+  pub changes_aliases: bool,
+}
+
+const fn flag(name: &'static str) -> Flag {
+  Flag { name, value: None }
+}
+
+const fn flag_with_value(name: &'static str, value: Arg) -> Flag {
+  Flag { name, value: Some(value) }
+}
+
+const fn command(names: &'static [&'static str], flags: &'static [Flag], args: &'static [Arg]) -> CommandInfo {
+  CommandInfo { names, flags, args, forwards_args: false, hidden: false, changes_aliases: false }
+}
+
+const LAYER_FLAGS: &[Flag] = &[flag("--global"), flag("--local"), flag_with_value("--file", Arg::Text)];
+const COPY_FLAGS: &[Flag] = &[flag("--global"), flag("--local"), flag_with_value("--file", Arg::Text), flag("--force")];
+const ADD_FLAGS: &[Flag] = &[flag("--global"), flag("--local"), flag_with_value("--file", Arg::Text), flag_with_value("--desc", Arg::Text), flag_with_value("--tag", Arg::Tag), flag_with_value("--cwd", Arg::Text), flag_with_value("--env", Arg::Text), flag_with_value("--unset-env", Arg::Text), flag_with_value("--shell", Arg::OneOf(Interpreter::NAMES))];
+const REMOVE_FLAGS: &[Flag] = &[flag("--global"), flag("--local"), flag_with_value("--file", Arg::Text), flag("--yes")];
+const GLOBAL_FLAGS: &[Flag] = &[flag_with_value("--config", Arg::Text), flag("--strict"), flag("--dry-run"), flag_with_value("--lock-timeout", Arg::Text)];
+
+pub const INIT_SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+pub const COMPLETION_SHELLS: &[&str] = &["bash", "zsh", "fish", "elvish", "powershell"];
+
+pub const COMMANDS: &[CommandInfo] = &[
+  CommandInfo { changes_aliases: true, ..command(&["a", "add"], ADD_FLAGS, &[Arg::Text, Arg::Text]) },
+  CommandInfo { changes_aliases: true, ..command(&["r", "remove"], REMOVE_FLAGS, &[Arg::Alias]) },
+  CommandInfo { forwards_args: true, ..command(&["e", "execute"], &[flag("--detach"), flag("--exec")], &[Arg::Alias]) },
+  CommandInfo { changes_aliases: true, ..command(&["c", "change"], LAYER_FLAGS, &[Arg::Alias, Arg::Text]) },
+  CommandInfo { changes_aliases: true, ..command(&["rename"], COPY_FLAGS, &[Arg::Alias, Arg::Text]) },
+  CommandInfo { changes_aliases: true, ..command(&["copy"], COPY_FLAGS, &[Arg::Alias, Arg::Text]) },
+  CommandInfo { changes_aliases: true, ..command(&["edit"], LAYER_FLAGS, &[Arg::Alias]) },
+  CommandInfo { changes_aliases: true, ..command(&["undo"], &[], &[Arg::Text]) },
+  CommandInfo { changes_aliases: true, ..command(&["redo"], &[], &[Arg::Text]) },
+  command(&["history"], &[], &[Arg::Text]),
+  CommandInfo { changes_aliases: true, ..command(&["backup"], &[], &[Arg::OneOf(&["list", "restore", "diff"]), Arg::Text]) },
+  command(&["l", "list"], &[flag("--origin"), flag("--json"), flag_with_value("--sort", Arg::OneOf(&["name", "recent", "usage"])), flag_with_value("--regex", Arg::Text), flag_with_value("--tag", Arg::Tag)], &[Arg::Text]),
+  command(&["show"], &[flag("--json")], &[Arg::Alias]),
+  CommandInfo { forwards_args: true, ..command(&["expand"], &[], &[Arg::Alias]) },
+  command(&["where"], &[flag("--all")], &[]),
+  command(&["doctor"], &[], &[]),
+  command(&["init"], &[], &[Arg::OneOf(INIT_SHELLS)]),
+  command(&["completions"], &[], &[Arg::OneOf(COMPLETION_SHELLS)]),
+  command(&["h", "help"], &[], &[Arg::Alias]),
+  CommandInfo { hidden: true, ..command(&["__fingerprint"], &[], &[]) },
+  CommandInfo { hidden: true, forwards_args: true, ..command(&["__complete"], &[flag_with_value("--position", Arg::Text)], &[]) },
+];
+
+/// The names of the commands people use, leaving out short forms such as `a` for add.
+pub fn command_names() -> impl Iterator<Item = &'static str> {
+  COMMANDS.iter().filter(|info| !info.hidden).filter_map(|info| info.names.last().copied())
+}
+
+/// Whether word ends the args that are passed on to an alias. Only the full names of commands do, so that short forms
+/// such as `r` or `c` can be passed on, as in `alia e grep -r a .`.
+pub fn ends_forwarded_args(word: &str) -> bool {
+  command_names().any(|name| name == word)
+}
+
+pub fn find_command(name: &str) -> Option<&'static CommandInfo> {
+  COMMANDS.iter().find(|command| command.names.contains(&name))
+}
+
+/// Works out what the last of words, the words typed after `alia`, can be completed with. The last word is the one
+/// being completed and may be empty.
+pub fn complete(cfg: &AliasStore, words: &[String]) -> Vec<String> {
+  let (current, before) = match words.split_last() {
+    Some((current, before)) => (current.as_str(), before),
+    None => ("", words),
+  };
+  let mut command: Option<&CommandInfo> = None;
+  let mut positional = 0;
+  let mut pending_flag: Option<&Flag> = None;
+  let mut forwarding_rest = false;
+  for word in before {
+    if forwarding_rest {
+      continue;
+    }
+    if pending_flag.take().is_some() {
+      continue;
+    }
+    let next_command = find_command(word);
+    match command {
+      Some(info) if info.forwards_args && positional >= info.args.len() => {
+        if word == "--" {
+          forwarding_rest = true;
+        } else if ends_forwarded_args(word) {
+          command = next_command;
+          positional = 0;
+        }
+        continue;
+      }
+      Some(info) if positional < info.args.len() || next_command.is_none() => {}
+      _ if next_command.is_some() => {
+        command = next_command;
+        positional = 0;
+        continue;
+      }
+      _ => {}
+    }
+    if word.starts_with("--") {
+      let flags = command.map_or(GLOBAL_FLAGS, |info| info.flags);
+      pending_flag = flags.iter().find(|flag| flag.name == word).filter(|flag| flag.value.is_some());
+      continue;
+    }
+    positional += 1;
+  }
+  let candidates = if forwarding_rest {
+    Vec::new()
+  } else if let Some(flag) = pending_flag {
+    flag.value.map_or_else(Vec::new, |value| arg_candidates(cfg, value))
+  } else if current.starts_with('-') {
+    command.map_or(GLOBAL_FLAGS, |info| info.flags).iter().map(|flag| flag.name.to_string()).collect()
+  } else {
+    match command {
+      Some(info) if positional < info.args.len() => arg_candidates(cfg, info.args[positional]),
+      // The rest of the args go to the alias, so let the shell fall back to completing file names.
+      Some(info) if info.forwards_args => Vec::new(),
+      _ => command_names().map(str::to_string).collect(),
+    }
+  };
+  candidates.into_iter().filter(|candidate| candidate.starts_with(current)).collect()
+}
+
+fn arg_candidates(cfg: &AliasStore, arg: Arg) -> Vec<String> {
+  match arg {
+    Arg::Alias => {
+      let mut names: Vec<String> = cfg.merged().into_keys().map(|name| name.to_string()).collect();
+      names.sort_unstable();
+      names
+    }
+    Arg::Text => Vec::new(),
+    Arg::OneOf(values) => values.iter().map(|value| value.to_string()).collect(),
+    Arg::Tag => {
+      let mut tags: Vec<String> = cfg.merged().into_values().flat_map(|(alias, _)| alias.tags.iter().cloned()).collect();
+      tags.sort_unstable();
+      tags.dedup();
+      tags
+    }
+  }
+}
+
+/// Builds the script `alia completions <shell>` prints. Each script asks `alia __complete` what to suggest, so the
+/// alias names it offers are always the ones currently in effect.
+pub fn completion_script(shell: Shell) -> String {
+  let script = match shell {
+    Shell::Bash => "\
+_alia() {
+  local IFS=$'\\n'
+  COMPREPLY=($(alia __complete -- \"${COMP_WORDS[@]:1:COMP_CWORD}\"))
+}
+complete -o default -F _alia alia",
+    Shell::Zsh => "\
+#compdef alia
+_alia() {
+  local -a candidates
+  candidates=(${(f)\"$(alia __complete -- \"${(@)words[2,CURRENT]}\")\"})
+  if (( ${#candidates} )); then
+    compadd -a candidates
+  else
+    _files
+  fi
+}
+if [ \"${funcstack[1]}\" = \"_alia\" ]; then
+  _alia \"$@\"
+else
+  compdef _alia alia
+fi",
+    Shell::Fish => "\
+function __alia_complete
+  set -l tokens (commandline -opc)
+  alia __complete -- $tokens[2..-1] (commandline -ct | string collect --allow-empty)
+end
+complete -c alia -f -a '(__alia_complete)'",
+    Shell::Elvish => "\
+set edit:completion:arg-completer[alia] = {|@words|
+  alia __complete -- $@words[1..]
+}",
+    // Windows PowerShell drops empty args on their way to native commands, so the position of the word being completed
+    // is passed along with the words.
+    Shell::Powershell => "\
+Register-ArgumentCompleter -Native -CommandName alia -ScriptBlock {
+  param($wordToComplete, $commandAst, $cursorPosition)
+  $words = @($commandAst.CommandElements | Where-Object { $_.Extent.EndOffset -lt $cursorPosition } | ForEach-Object { $_.Extent.Text })
+  $position = $words.Count - 1
+  & alia __complete --position $position '--' @($words | Select-Object -Skip 1) $wordToComplete | ForEach-Object {
+    [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+  }
+}",
+  };
+  script_from_lines(&[script])
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+  use crate::store::{Alias, AliasStore, Layer, LayerKind};
+  use super::complete;
+
+  fn complete_words<const NUM_OF_WORDS: usize>(words: [&str; NUM_OF_WORDS]) -> Vec<String> {
+    let mut aliases: std::collections::HashMap<String, Alias> = [("build", "cargo build"), ("bench", "cargo bench"), ("test", "cargo test")].map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).into();
+    aliases.get_mut("build").unwrap().tags = vec!["rust".to_string(), "ci".to_string()];
+    aliases.get_mut("test").unwrap().tags = vec!["ci".to_string()];
+    let cfg = AliasStore::new(Layer::new(PathBuf::new(), LayerKind::Global, aliases));
+    complete(&cfg, &words.map(|x| x.to_string()))
+  }
+
+  #[test]
+  fn test_complete() {
+    assert_eq!(complete_words(["ch"]), ["change"]);
+    assert_eq!(complete_words(["e", ""]), ["bench", "build", "test"]);
+    assert_eq!(complete_words(["execute", "b"]), ["bench", "build"]);
+    assert_eq!(complete_words(["e", "--detach", "bu"]), ["build"]);
+    assert_eq!(complete_words(["e", "--"]), ["--detach", "--exec"]);
+    assert_eq!(complete_words(["e", "build", ""]), Vec::<String>::new());
+    assert_eq!(complete_words(["e", "build", "x", "remove", "t"]), ["test"]);
+    assert_eq!(complete_words(["e", "build", "x", "r", "t"]), Vec::<String>::new());
+    assert_eq!(complete_words(["e", "build", "--", "r", "t"]), Vec::<String>::new());
+    assert_eq!(complete_words(["r", "--file", "x", ""]), ["bench", "build", "test"]);
+    assert_eq!(complete_words(["r", "--file", ""]), Vec::<String>::new());
+    assert_eq!(complete_words(["a", "e", ""]), Vec::<String>::new());
+    assert_eq!(complete_words(["a", "name", "value", "s"]), ["show"]);
+    assert_eq!(complete_words(["list", "--sort", "r"]), ["recent"]);
+    assert_eq!(complete_words(["list", "--tag", ""]), ["ci", "rust"]);
+    assert_eq!(complete_words(["a", "--tag", "r"]), ["rust"]);
+    assert_eq!(complete_words(["help", "t"]), ["test"]);
+    assert_eq!(complete_words(["completions", "p"]), ["powershell"]);
+    assert_eq!(complete_words(["--config", "x", "wh"]), ["where"]);
+    assert_eq!(complete_words(["--c"]), ["--config"]);
+    assert_eq!(complete_words(["__"]), Vec::<String>::new());
+  }
+}
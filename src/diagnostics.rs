@@ -0,0 +1,157 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::path::Path;
+
+/// A range of bytes in the text of a file.
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone, Copy)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Self {
+    Self { start, end }
+  }
+}
+
+impl From<Range<usize>> for Span {
+  fn from(range: Range<usize>) -> Self {
+    Self::new(range.start, range.end)
+  }
+}
+
+/// The line a span starts on, as it is shown under a diagnostic.
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub struct Snippet {
+  /// The number of the line, starting at 1.
+  pub line: usize,
+  /// The number of the char the span starts at within the line, starting at 1.
+  pub column: usize,
+  /// The text of the line, with tabs expanded so the carets line up.
+  pub text: String,
+  /// How far into text the carets start.
+  pub indent: usize,
+  /// How many carets to draw under text.
+  pub width: usize,
+}
+
+/// An error in a file that points at where in the file it is, and is displayed the way rustc displays its errors:
+///
+/// ```text
+/// error: Expected an equal sign after the alias name.
+///  --> ~/.config/alia/cfg.alia:3:9
+///   |
+/// 3 | "build" cargo build
+///   |         ^
+///   = help: names and values are separated by an equal sign, as in "name" = "value"
+/// ```
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub struct Diagnostic {
+  pub message: String,
+  pub path: String,
+  pub span: Option<Span>,
+  pub snippet: Option<Snippet>,
+  pub help: Option<String>,
+}
+
+const TAB_WIDTH: usize = 4;
+
+impl Diagnostic {
+  /// Builds a diagnostic for an error in source, the text of the file at path. Without a span it points at the whole
+  /// file.
+  pub fn new(message: String, path: &Path, source: &str, span: Option<Span>, help: Option<String>) -> Self {
+    Self { message, path: path.display().to_string(), span, snippet: span.map(|span| snippet(source, span)), help }
+  }
+}
+
+/// Finds the line span starts on and works out where on it the carets go. A span that is empty or runs past the end
+/// of the line still gets a single caret, and one that points past the end of the file points at its last line.
+fn snippet(source: &str, span: Span) -> Snippet {
+  let mut start = span.start.min(source.len());
+  while !source.is_char_boundary(start) {
+    start -= 1;
+  }
+  // Past the end of the file there is nothing to point at, so point just after the last thing on the last line.
+  if start == source.len() {
+    start = source.trim_end().len();
+  }
+  let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+  let line_end = source[start..].find('\n').map_or(source.len(), |index| start + index);
+  let line = source[..line_start].matches('\n').count() + 1;
+  let line_text = source[line_start..line_end].trim_end_matches('\r');
+  let before = &line_text[..(start - line_start).min(line_text.len())];
+  let end = span.end.clamp(start, line_start + line_text.len());
+  Snippet {
+    line,
+    column: before.chars().count() + 1,
+    text: line_text.replace('\t', &" ".repeat(TAB_WIDTH)),
+    indent: display_width(before),
+    width: display_width(&source[start..end]).max(1),
+  }
+}
+
+impl Diagnostic {
+  /// Renders the diagnostic with severity, such as `warning`, in place of `error`.
+  pub fn render(&self, severity: &str) -> String {
+    Rendered(self, severity).to_string()
+  }
+}
+
+impl Display for Diagnostic {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", Rendered(self, "error"))
+  }
+}
+
+/// A diagnostic along with the severity it is shown with.
+struct Rendered<'a>(&'a Diagnostic, &'a str);
+
+impl Display for Rendered<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let Rendered(diagnostic, severity) = self;
+    writeln!(f, "{severity}: {}", diagnostic.message)?;
+    let Some(snippet) = &diagnostic.snippet else {
+      write!(f, " --> {}", diagnostic.path)?;
+      if let Some(help) = &diagnostic.help {
+        write!(f, "\n  = help: {help}")?;
+      }
+      return Ok(());
+    };
+    let gutter = " ".repeat(snippet.line.to_string().len());
+    writeln!(f, "{gutter}--> {}:{}:{}", diagnostic.path, snippet.line, snippet.column)?;
+    writeln!(f, "{gutter} |")?;
+    writeln!(f, "{} | {}", snippet.line, snippet.text)?;
+    write!(f, "{gutter} | {}{}", " ".repeat(snippet.indent), "^".repeat(snippet.width))?;
+    if let Some(help) = &diagnostic.help {
+      write!(f, "\n{gutter} = help: {help}")?;
+    }
+    Ok(())
+  }
+}
+
+fn display_width(text: &str) -> usize {
+  text.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+  use super::{Diagnostic, Span};
+
+  fn render(source: &str, span: Option<Span>, help: Option<&str>) -> String {
+    Diagnostic::new("Something is wrong.".to_string(), Path::new("cfg.alia"), source, span, help.map(str::to_string)).to_string()
+  }
+
+  #[test]
+  fn test_render() {
+    let source = "one\ntwo three\n";
+    assert_eq!(render(source, Some(Span::new(8, 13)), Some("fix it")), "error: Something is wrong.\n --> cfg.alia:2:5\n  |\n2 | two three\n  |     ^^^^^\n  = help: fix it");
+    // A span running past the end of its line is cut off there, and one past the end of the file points after its end.
+    assert_eq!(render(source, Some(Span::new(4, 100)), None), "error: Something is wrong.\n --> cfg.alia:2:1\n  |\n2 | two three\n  | ^^^^^^^^^");
+    assert_eq!(render(source, Some(Span::new(14, 14)), None), "error: Something is wrong.\n --> cfg.alia:2:10\n  |\n2 | two three\n  |          ^");
+    assert_eq!(render("\tx = ü\r\n", Some(Span::new(5, 7)), None), "error: Something is wrong.\n --> cfg.alia:1:6\n  |\n1 |     x = ü\n  |         ^");
+    assert_eq!(render(&"\n".repeat(11), Some(Span::new(10, 10)), None), "error: Something is wrong.\n  --> cfg.alia:11:1\n   |\n11 | \n   | ^");
+    assert_eq!(render(source, None, Some("fix it")), "error: Something is wrong.\n --> cfg.alia\n  = help: fix it");
+  }
+}
@@ -0,0 +1,95 @@
+/// How many unchanged lines are shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Line<'a> {
+  Same(&'a str),
+  Removed(&'a str),
+  Added(&'a str),
+}
+
+/// Works out the shortest way of turning old into new by removing and adding lines, using their longest common
+/// subsequence. Alias files are small, so the quadratic table this needs is not a problem.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+  // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+  let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+  for i in (0..old.len()).rev() {
+    for j in (0..new.len()).rev() {
+      common[i][j] = if old[i] == new[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+    }
+  }
+  let mut result = Vec::new();
+  let (mut i, mut j) = (0, 0);
+  while i < old.len() && j < new.len() {
+    if old[i] == new[j] {
+      result.push(Line::Same(old[i]));
+      i += 1;
+      j += 1;
+    } else if common[i + 1][j] >= common[i][j + 1] {
+      result.push(Line::Removed(old[i]));
+      i += 1;
+    } else {
+      result.push(Line::Added(new[j]));
+      j += 1;
+    }
+  }
+  result.extend(old[i..].iter().map(|line| Line::Removed(line)));
+  result.extend(new[j..].iter().map(|line| Line::Added(line)));
+  result
+}
+
+/// Renders the differences between old and new as a unified diff, the format `diff -u` and git use, with old_label
+/// and new_label naming the two sides. Returns an empty string if there are no differences.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+  let old_lines: Vec<&str> = old.lines().collect();
+  let new_lines: Vec<&str> = new.lines().collect();
+  let lines = diff_lines(&old_lines, &new_lines);
+  let changed: Vec<usize> = lines.iter().enumerate().filter(|(_, line)| !matches!(line, Line::Same(_))).map(|(index, _)| index).collect();
+  if changed.is_empty() {
+    return String::new();
+  }
+  // Groups the changes into hunks, merging the ones whose context would overlap.
+  let mut hunks: Vec<(usize, usize)> = Vec::new();
+  for index in changed {
+    let start = index.saturating_sub(CONTEXT_LINES);
+    let end = (index + CONTEXT_LINES + 1).min(lines.len());
+    match hunks.last_mut() {
+      Some((_, last_end)) if start <= *last_end => *last_end = end,
+      _ => hunks.push((start, end)),
+    }
+  }
+  let mut result = format!("--- {old_label}\n+++ {new_label}\n");
+  for (start, end) in hunks {
+    let old_before = lines[..start].iter().filter(|line| !matches!(line, Line::Added(_))).count();
+    let new_before = lines[..start].iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+    let hunk = &lines[start..end];
+    let old_count = hunk.iter().filter(|line| !matches!(line, Line::Added(_))).count();
+    let new_count = hunk.iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+    // A side without any lines in the hunk is numbered by the line before it, as diff does.
+    let first_line = |before: usize, count: usize| if count == 0 { before } else { before + 1 };
+    result.push_str(&format!("@@ -{},{old_count} +{},{new_count} @@\n", first_line(old_before, old_count), first_line(new_before, new_count)));
+    for line in hunk {
+      match line {
+        Line::Same(text) => result.push_str(&format!(" {text}\n")),
+        Line::Removed(text) => result.push_str(&format!("-{text}\n")),
+        Line::Added(text) => result.push_str(&format!("+{text}\n")),
+      }
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::unified_diff;
+
+  #[test]
+  fn test_unified_diff() {
+    assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+    assert_eq!(unified_diff("a\nb\nc\n", "a\nx\nc\n", "old", "new"), "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n");
+    assert_eq!(unified_diff("", "a\n", "old", "new"), "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n");
+    let old: String = (1..=20).map(|n| format!("{n}\n")).collect();
+    let new: String = (1..=20).filter(|n| *n != 19).map(|n| if n == 2 { "two\n".to_string() } else { format!("{n}\n") }).collect();
+    assert_eq!(unified_diff(&old, &new, "old", "new"), "--- old\n+++ new\n@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n@@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n");
+  }
+}
@@ -0,0 +1,139 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use crate::process::wait_for_child;
+use crate::shell_words;
+
+/// The editor used when neither `$VISUAL` nor `$EDITOR` is set.
+pub const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Editors that open a file at line n when passed `+n` before it.
+const EDITORS_WITH_LINE_ARG: &[&str] = &["vi", "vim", "nvim", "gvim", "nano", "pico", "emacs", "emacsclient", "micro", "kak", "joe", "jed", "mg", "ne"];
+
+/// The editor to open files in, as set by `$VISUAL` or else `$EDITOR`. It may include arguments, as in `code --wait`.
+pub fn editor_command() -> String {
+  choose_editor(env::var("VISUAL").ok(), env::var("EDITOR").ok())
+}
+
+fn choose_editor(visual: Option<String>, editor: Option<String>) -> String {
+  [visual, editor].into_iter().flatten().find(|editor| !editor.trim().is_empty()).unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Builds the command that opens path in editor, at line if the editor is known to support that.
+fn editor_invocation(editor: &str, path: &Path, line: Option<usize>) -> Result<Command, String> {
+  let words = shell_words::split(editor).map_err(|e| e.to_string())?;
+  let mut command = Command::new(&words[0]);
+  command.args(&words[1..]);
+  let program = Path::new(&words[0]).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+  if let Some(line) = line.filter(|_| EDITORS_WITH_LINE_ARG.contains(&program)) {
+    command.arg(format!("+{line}"));
+  }
+  command.arg(path);
+  Ok(command)
+}
+
+/// Opens path in editor and waits for it to be closed. Returns why editing failed if the editor could not be started
+/// or did not exit successfully.
+pub fn edit_file(editor: &str, path: &Path, line: Option<usize>) -> Result<(), String> {
+  let child = editor_invocation(editor, path, line)?.spawn().map_err(|e| e.to_string())?;
+  match wait_for_child(child) {
+    Ok(Ok(())) => Ok(()),
+    Ok(Err(failure)) => Err(format!("The editor {failure}.")),
+    Err(e) => Err(e.to_string()),
+  }
+}
+
+/// Writes contents to a new file in the temporary directory that only the current user can read, since aliases may
+/// hold secrets. The name is made of the process id and name, with anything but letters, digits, `-`, `_` and `.`
+/// replaced, so editors can pick their syntax highlighting from its extension.
+pub fn create_temp_file(name: &str, contents: &str) -> io::Result<PathBuf> {
+  let name: String = name.chars().map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' }).collect();
+  let path = env::temp_dir().join(format!("alia-{}-{name}", process::id()));
+  let mut options = OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+  let _ = fs::remove_file(&path);
+  options.open(&path)?.write_all(contents.as_bytes())?;
+  Ok(path)
+}
+
+/// The text a new alias starts out with in the editor. Its lines are removed again before the alias is saved.
+pub fn alias_template(name: &str) -> String {
+  format!("# Write the command for the alias {name} below. It can span several lines.\n# These two lines are removed when you save, and leaving the file empty cancels.\n")
+}
+
+/// Turns the text of a file an alias was edited in back into its command. The lines of the template are removed, along
+/// with the line break editors add at the end of the file.
+pub fn edited_command(text: &str, name: &str) -> String {
+  let template = alias_template(name);
+  let command: Vec<&str> = text.lines().filter(|line| !template.lines().any(|template_line| template_line == *line)).collect();
+  command.join("\n").trim_end_matches(['\n', '\r']).to_string()
+}
+
+/// Asks question on the terminal and returns whether the answer was yes. An empty answer counts as default. Returns
+/// false without asking if there is no terminal to ask on.
+pub fn confirm(question: &str, default: bool) -> bool {
+  use std::io::IsTerminal;
+  if !io::stdin().is_terminal() {
+    return false;
+  }
+  eprint!("{question} {} ", if default { "[Y/n]" } else { "[y/N]" });
+  let _ = io::stderr().flush();
+  let mut answer = String::new();
+  if io::stdin().read_line(&mut answer).is_err() {
+    return false;
+  }
+  match answer.trim().to_lowercase().as_str() {
+    "" => default,
+    answer => matches!(answer, "y" | "yes"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::path::Path;
+  use super::{alias_template, choose_editor, create_temp_file, edited_command, editor_invocation};
+
+  #[test]
+  fn test_choose_editor() {
+    assert_eq!(choose_editor(Some("code --wait".to_string()), Some("nano".to_string())), "code --wait");
+    assert_eq!(choose_editor(Some(" ".to_string()), Some("nano".to_string())), "nano");
+    assert_eq!(choose_editor(None, None), super::DEFAULT_EDITOR);
+  }
+
+  #[test]
+  fn test_editor_invocation() {
+    let args = |editor: &str, line: Option<usize>| {
+      let command = editor_invocation(editor, Path::new("f"), line).unwrap();
+      let mut args = vec![command.get_program().to_string_lossy().to_string()];
+      args.extend(command.get_args().map(|arg| arg.to_string_lossy().to_string()));
+      args
+    };
+    assert_eq!(args("vim", Some(3)), ["vim", "+3", "f"]);
+    assert_eq!(args("/usr/bin/nvim -u NONE", Some(3)), ["/usr/bin/nvim", "-u", "NONE", "+3", "f"]);
+    assert_eq!(args("code --wait", Some(3)), ["code", "--wait", "f"]);
+    assert_eq!(args("vim", None), ["vim", "f"]);
+    assert!(editor_invocation("", Path::new("f"), None).is_err());
+  }
+
+  #[test]
+  fn test_edited_command() {
+    assert_eq!(edited_command(&format!("{}echo one\necho two\n\n", alias_template("x")), "x"), "echo one\necho two");
+    assert_eq!(edited_command("# a comment of my own\nls\r\n", "x"), "# a comment of my own\nls");
+    assert_eq!(edited_command(&alias_template("x"), "x"), "");
+  }
+
+  #[test]
+  fn test_create_temp_file() {
+    let path = create_temp_file("a/b c.sh", "echo").unwrap();
+    assert!(path.file_name().unwrap().to_str().unwrap().ends_with("-a_b_c.sh"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "echo");
+    #[cfg(unix)]
+    assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+    fs::remove_file(&path).unwrap();
+  }
+}
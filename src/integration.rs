@@ -0,0 +1,110 @@
+use std::fs;
+use std::time::UNIX_EPOCH;
+use crate::shells::{script_from_lines, Shell};
+use crate::store::AliasStore;
+
+/// Describes the state of every alias file in effect, so the shell integration can tell when it has to reload.
+/// Changing an alias file, or moving to a directory with different `.alia` files, changes the fingerprint.
+pub fn fingerprint(cfg: &AliasStore) -> String {
+  let layers: Vec<String> = cfg.layers.iter().map(|layer| {
+    let modified = fs::metadata(&layer.path).and_then(|metadata| metadata.modified()).ok()
+      .and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|duration| duration.as_nanos()).unwrap_or(0);
+    format!("{}={modified}", layer.path.display())
+  }).collect();
+  layers.join(";")
+}
+
+/// Builds the script `alia init <shell>` prints. It defines a function for every alias that calls back into the
+/// Alia binary at exe, and hooks into the prompt to redefine them whenever the fingerprint changes. Functions from
+/// an earlier run of the script are removed first, so aliases that no longer exist go away as well. Returns None for
+/// shells that are not supported.
+pub fn init_script(shell: Shell, exe: &str, cfg: &AliasStore) -> Option<String> {
+  let mut names: Vec<&str> = cfg.merged().into_keys().filter(|name| shell.is_valid_function_name(name)).collect();
+  names.sort_unstable();
+  let exe = shell.quote(exe);
+  let fingerprint = shell.quote(&fingerprint(cfg));
+  let shell_name = shell.name();
+  let mut lines = vec![format!("# Alia integration for {shell_name}. Generated by `alia init {shell_name}`.")];
+  match shell {
+    Shell::Bash | Shell::Zsh => {
+      lines.push("for __alia_name in \"${__alia_functions[@]}\"; do unset -f \"$__alia_name\"; done".to_string());
+      lines.push("unset __alia_name".to_string());
+      for name in &names {
+        lines.push(format!("function {name} {{ {exe} e {} -- \"$@\"; }}", shell.quote(name)));
+      }
+      lines.push(format!("__alia_functions=({})", names.join(" ")));
+      lines.push(format!("__alia_fingerprint={fingerprint}"));
+      lines.push("function __alia_refresh {".to_string());
+      lines.push(format!("  if [ \"$({exe} __fingerprint)\" != \"$__alia_fingerprint\" ]; then eval \"$({exe} init {shell_name})\"; fi"));
+      lines.push("}".to_string());
+      if shell == Shell::Bash {
+        lines.push("if [[ \";${PROMPT_COMMAND:-};\" != *\";__alia_refresh;\"* ]]; then PROMPT_COMMAND=\"__alia_refresh;${PROMPT_COMMAND:-}\"; fi".to_string());
+      } else {
+        // add-zsh-hook does nothing if the hook is already registered.
+        lines.push("autoload -Uz add-zsh-hook".to_string());
+        lines.push("add-zsh-hook precmd __alia_refresh".to_string());
+      }
+    }
+    Shell::Fish => {
+      lines.push("for __alia_name in $__alia_functions; functions -e $__alia_name; end".to_string());
+      for name in &names {
+        lines.push(format!("function {name}; {exe} e {} -- $argv; end", shell.quote(name)));
+      }
+      lines.push(format!("set -g __alia_functions {}", names.join(" ")));
+      lines.push(format!("set -g __alia_fingerprint {fingerprint}"));
+      lines.push("function __alia_refresh --on-event fish_prompt".to_string());
+      lines.push(format!("  set -l fingerprint ({exe} __fingerprint)"));
+      lines.push(format!("  if test \"$fingerprint\" != \"$__alia_fingerprint\"; {exe} init fish | source; end"));
+      lines.push("end".to_string());
+    }
+    Shell::Powershell => {
+      lines.push("foreach ($__alia_name in $global:__alia_functions) { Remove-Item -Path \"Function:\\$__alia_name\" -ErrorAction SilentlyContinue }".to_string());
+      for name in &names {
+        // A bare -- would be swallowed by PowerShell, so it is quoted to reach Alia.
+        lines.push(format!("function global:{name} {{ & {exe} e {} '--' @args }}", shell.quote(name)));
+      }
+      let quoted_names: Vec<String> = names.iter().map(|name| shell.quote(name)).collect();
+      lines.push(format!("$global:__alia_functions = @({})", quoted_names.join(", ")));
+      lines.push(format!("$global:__alia_fingerprint = {fingerprint}"));
+      lines.push("if (-not $global:__alia_prompt) {".to_string());
+      lines.push("  $global:__alia_prompt = $function:prompt".to_string());
+      lines.push("  function global:prompt {".to_string());
+      lines.push(format!("    if ((& {exe} __fingerprint) -ne $global:__alia_fingerprint) {{ & {exe} init powershell | Out-String | Invoke-Expression }}"));
+      lines.push("    & $global:__alia_prompt".to_string());
+      lines.push("  }".to_string());
+      lines.push("}".to_string());
+    }
+    Shell::Elvish => return None,
+  }
+  Some(script_from_lines(&lines))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+  use crate::shells::Shell;
+  use crate::store::{Alias, AliasStore, Layer, LayerKind};
+  use super::init_script;
+
+  fn store() -> AliasStore {
+    let aliases = [("build", "cargo build"), ("db.up", "docker compose up"), ("has space", "ls")].map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).into();
+    AliasStore::new(Layer::new(PathBuf::from("/nonexistent/cfg.alia"), LayerKind::Global, aliases))
+  }
+
+  #[test]
+  fn test_init_script() {
+    let cfg = store();
+    let bash = init_script(Shell::Bash, "/usr/bin/alia", &cfg).unwrap();
+    assert!(bash.contains("\nfunction build { /usr/bin/alia e build -- \"$@\"; }\n"));
+    assert!(bash.contains("\nfunction db.up { /usr/bin/alia e db.up -- \"$@\"; }\n"));
+    assert!(bash.contains("\n__alia_functions=(build db.up)\n"));
+    assert!(bash.contains("\n__alia_fingerprint=/nonexistent/cfg.alia=0\n"));
+    assert!(!bash.contains("has space"));
+    let fish = init_script(Shell::Fish, "/opt/my alia", &cfg).unwrap();
+    assert!(fish.contains("\nfunction build; '/opt/my alia' e 'build' -- $argv; end\n"));
+    let powershell = init_script(Shell::Powershell, "C:\\alia.exe", &cfg).unwrap();
+    assert!(powershell.contains("\nfunction global:build { & 'C:\\alia.exe' e 'build' '--' @args }\n"));
+    assert!(powershell.contains("\n$global:__alia_functions = @('build')\n"));
+    assert_eq!(init_script(Shell::Elvish, "alia", &cfg), None);
+  }
+}
@@ -0,0 +1,312 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, InlineTable, Value};
+use crate::schema::{alias_to_inline_table, parse_inline_alias};
+use crate::store::Alias;
+use crate::time::format_timestamp;
+
+/// A change to a single alias. before is None for an alias that was added and after is None for one that was removed.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Change {
+  /// The alias file the alias is in.
+  pub path: PathBuf,
+  pub name: String,
+  pub before: Option<Alias>,
+  pub after: Option<Alias>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum EntryKind {
+  /// The changes one invocation of Alia made, along with its command line.
+  Changes(String, Vec<Change>),
+  /// The changes of the entry with the given id were undone.
+  Undo(u64),
+  /// The changes of the entry with the given id were made again after being undone.
+  Redo(u64),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Entry {
+  pub id: u64,
+  /// When the entry was recorded, in seconds since the Unix epoch.
+  pub time: u64,
+  pub kind: EntryKind,
+}
+
+/// Every change that was made to the alias files, kept in a file next to the global store. Entries are only ever
+/// appended to it, one per line, and undoing is recorded as an entry of its own, so nothing in it is ever lost.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Journal {
+  pub path: Option<PathBuf>,
+  pub entries: Vec<Entry>,
+  /// How many of the entries are in the file already. The rest are appended by save.
+  saved: usize,
+  /// The changes made so far by this invocation that are not part of an entry yet.
+  pub pending: Vec<Change>,
+  /// The command line of this invocation, which is recorded along with its changes.
+  pub command: String,
+}
+
+impl Journal {
+  /// The file the journal for the global store at config_path is kept in.
+  pub fn path_for(config_path: &Path) -> PathBuf {
+    let mut path = config_path.as_os_str().to_owned();
+    path.push(".journal");
+    path.into()
+  }
+
+  /// Loads the journal at path. A missing file means nothing was changed yet, and lines that cannot be read, such as
+  /// one cut short by a crash, are skipped.
+  pub fn load(path: PathBuf) -> Self {
+    let entries: Vec<Entry> = fs::read_to_string(&path).map(|contents| contents.lines().filter_map(parse_entry).collect()).unwrap_or_default();
+    Self { path: Some(path), saved: entries.len(), entries, ..Default::default() }
+  }
+
+  /// Notes that the alias name in the alias file at path went from before to after. Several changes to the same alias
+  /// are merged into one.
+  pub fn record(&mut self, path: &Path, name: &str, before: Option<Alias>, after: Option<Alias>) {
+    match self.pending.iter_mut().find(|change| change.path == path && change.name == name) {
+      Some(change) => change.after = after,
+      None => self.pending.push(Change { path: path.to_path_buf(), name: name.to_string(), before, after }),
+    }
+  }
+
+  /// Turns the pending changes into an entry, leaving out the ones that ended up where they started.
+  pub fn commit(&mut self, time: u64) {
+    let changes: Vec<Change> = self.pending.drain(..).filter(|change| change.before != change.after).collect();
+    if !changes.is_empty() {
+      self.push(EntryKind::Changes(self.command.clone(), changes), time);
+    }
+  }
+
+  pub fn push(&mut self, kind: EntryKind, time: u64) {
+    let id = self.entries.iter().map(|entry| entry.id).max().unwrap_or(0) + 1;
+    self.entries.push(Entry { id, time, kind });
+  }
+
+  pub fn find(&self, id: u64) -> Option<&Entry> {
+    self.entries.iter().find(|entry| entry.id == id)
+  }
+
+  /// The ids of the entries whose changes are in effect, oldest first, and of the entries that were undone and can be
+  /// redone, with the next one to redo last. Making new changes after undoing means the undone ones cannot be redone.
+  pub fn stacks(&self) -> (Vec<u64>, Vec<u64>) {
+    let mut done = Vec::new();
+    let mut undone = Vec::new();
+    for entry in &self.entries {
+      match entry.kind {
+        EntryKind::Changes(..) => {
+          done.push(entry.id);
+          undone.clear();
+        }
+        EntryKind::Undo(id) if done.last() == Some(&id) => undone.extend(done.pop()),
+        EntryKind::Redo(id) if undone.last() == Some(&id) => done.extend(undone.pop()),
+        EntryKind::Undo(_) | EntryKind::Redo(_) => {}
+      }
+    }
+    (done, undone)
+  }
+
+  /// Appends the entries that are not in the file yet to it.
+  pub fn save(&self) -> io::Result<()> {
+    let Some(path) = &self.path else {
+      return Ok(());
+    };
+    if self.entries.len() == self.saved {
+      return Ok(());
+    }
+    let lines: String = self.entries[self.saved..].iter().map(|entry| format!("{}\n", entry_to_string(entry))).collect();
+    // A single write, so a crash leaves at most the last line incomplete.
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()
+  }
+}
+
+/// Lists the last count entries of journal, newest first, along with what each change did. Changes that are undone
+/// at the moment are marked as such.
+pub fn format_history(journal: &Journal, count: usize) -> String {
+  let (done, _) = journal.stacks();
+  let mut result = String::new();
+  for entry in journal.entries.iter().rev().take(count) {
+    let time = format_timestamp(entry.time);
+    match &entry.kind {
+      EntryKind::Changes(command, changes) => {
+        let undone = if done.contains(&entry.id) { "" } else { " (undone)" };
+        result.push_str(&format!("#{} {time} {command}{undone}\n", entry.id));
+        for change in changes {
+          let action = match (&change.before, &change.after) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "changed",
+          };
+          result.push_str(&format!("    {action} {} in {}\n", change.name, change.path.display()));
+        }
+      }
+      EntryKind::Undo(id) => result.push_str(&format!("#{} {time} undo #{id}\n", entry.id)),
+      EntryKind::Redo(id) => result.push_str(&format!("#{} {time} redo #{id}\n", entry.id)),
+    }
+  }
+  if result.is_empty() {
+    result.push_str("No changes have been recorded yet.\n");
+  }
+  result
+}
+
+/// Renders entry as a TOML inline table on a single line, such as
+/// `{ id = 3, time = 1646141820, command = "alia r build", changes = [{ file = "/home/me/.config/alia/cfg.alia", name = "build", before = { command = "make" } }] }`.
+fn entry_to_string(entry: &Entry) -> String {
+  let mut table = InlineTable::new();
+  table.insert("id", (entry.id as i64).into());
+  table.insert("time", (entry.time as i64).into());
+  match &entry.kind {
+    EntryKind::Changes(command, changes) => {
+      table.insert("command", command.as_str().into());
+      table.insert("changes", Value::Array(changes.iter().map(change_to_table).collect::<Array>()));
+    }
+    EntryKind::Undo(id) => { table.insert("undo", (*id as i64).into()); }
+    EntryKind::Redo(id) => { table.insert("redo", (*id as i64).into()); }
+  }
+  let mut value = Value::InlineTable(table);
+  escape_strings(&mut value);
+  value.to_string().trim().to_string()
+}
+
+/// Turns every string in value into a basic string, which toml_edit would otherwise render as a multi-line string if
+/// it holds a line break, so value renders on a single line.
+fn escape_strings(value: &mut Value) {
+  match value {
+    Value::String(string) => {
+      let mut escaped = String::from("\"");
+      for c in string.value().chars() {
+        match c {
+          '"' => escaped.push_str("\\\""),
+          '\\' => escaped.push_str("\\\\"),
+          '\n' => escaped.push_str("\\n"),
+          '\r' => escaped.push_str("\\r"),
+          '\t' => escaped.push_str("\\t"),
+          c if c.is_control() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+          c => escaped.push(c),
+        }
+      }
+      escaped.push('"');
+      if let Ok(parsed) = escaped.parse::<Value>() {
+        *value = parsed;
+        value.decor_mut().clear();
+      }
+    }
+    Value::Array(array) => array.iter_mut().for_each(escape_strings),
+    Value::InlineTable(table) => table.iter_mut().for_each(|(_, value)| escape_strings(value)),
+    _ => {}
+  }
+}
+
+fn change_to_table(change: &Change) -> InlineTable {
+  let mut table = InlineTable::new();
+  table.insert("file", change.path.to_string_lossy().as_ref().into());
+  table.insert("name", change.name.as_str().into());
+  if let Some(before) = &change.before {
+    table.insert("before", Value::InlineTable(alias_to_inline_table(before)));
+  }
+  if let Some(after) = &change.after {
+    table.insert("after", Value::InlineTable(alias_to_inline_table(after)));
+  }
+  table
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+  let value = line.parse::<Value>().ok()?;
+  let table = value.as_inline_table()?;
+  let number = |key: &str| table.get(key).and_then(Value::as_integer).and_then(|number| u64::try_from(number).ok());
+  let kind = if let Some(id) = number("undo") {
+    EntryKind::Undo(id)
+  } else if let Some(id) = number("redo") {
+    EntryKind::Redo(id)
+  } else {
+    let changes = table.get("changes")?.as_array()?.iter().map(parse_change).collect::<Option<Vec<Change>>>()?;
+    EntryKind::Changes(table.get("command")?.as_str()?.to_string(), changes)
+  };
+  Some(Entry { id: number("id")?, time: number("time")?, kind })
+}
+
+fn parse_change(value: &Value) -> Option<Change> {
+  let table = value.as_inline_table()?;
+  let name = table.get("name")?.as_str()?;
+  let alias = |key: &str| match table.get(key) {
+    None => Some(None),
+    Some(value) => parse_inline_alias(name, value.as_inline_table()?).ok().map(Some),
+  };
+  Some(Change { path: table.get("file")?.as_str()?.into(), name: name.to_string(), before: alias("before")?, after: alias("after")? })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+  use std::path::PathBuf;
+  use crate::store::Alias;
+  use super::{Change, Entry, entry_to_string, EntryKind, format_history, Journal, parse_entry};
+
+  fn change(name: &str, before: Option<&str>, after: Option<&str>) -> Change {
+    let alias = |command: &str| Alias::new(command.to_string());
+    Change { path: PathBuf::from("/home/me/cfg.alia"), name: name.to_string(), before: before.map(alias), after: after.map(alias) }
+  }
+
+  #[test]
+  fn test_entry_round_trip() {
+    let mut alias = Alias::new("echo \"one\"\necho two".to_string());
+    alias.tags = vec!["x".to_string()];
+    alias.env = BTreeMap::from([("A".to_string(), "1".to_string())]);
+    let changes = vec![Change { after: Some(alias), ..change("a b", None, None) }, change("c", Some("ls"), None)];
+    for kind in [EntryKind::Changes("alia a 'a b' ...".to_string(), changes), EntryKind::Undo(3), EntryKind::Redo(3)] {
+      let entry = Entry { id: 4, time: 1646141820, kind };
+      let line = entry_to_string(&entry);
+      assert!(!line.contains('\n'), "{line}");
+      assert_eq!(parse_entry(&line), Some(entry));
+    }
+    assert_eq!(entry_to_string(&Entry { id: 1, time: 2, kind: EntryKind::Undo(1) }), "{ id = 1, time = 2, undo = 1 }");
+    assert_eq!(parse_entry("{ id = 1, time = 2, command = \"alia r x\", changes = [{ file = "), None);
+  }
+
+  #[test]
+  fn test_record_and_commit() {
+    let mut journal = Journal { command: "alia ...".to_string(), ..Default::default() };
+    journal.record(&PathBuf::from("/home/me/cfg.alia"), "x", None, Some(Alias::new("1".to_string())));
+    journal.record(&PathBuf::from("/home/me/cfg.alia"), "x", Some(Alias::new("1".to_string())), Some(Alias::new("2".to_string())));
+    journal.record(&PathBuf::from("/home/me/cfg.alia"), "y", Some(Alias::new("ls".to_string())), None);
+    journal.record(&PathBuf::from("/home/me/cfg.alia"), "y", None, Some(Alias::new("ls".to_string())));
+    journal.commit(5);
+    assert_eq!(journal.entries, [Entry { id: 1, time: 5, kind: EntryKind::Changes("alia ...".to_string(), vec![change("x", None, Some("2"))]) }]);
+    journal.commit(6);
+    assert_eq!(journal.entries.len(), 1);
+  }
+
+  #[test]
+  fn test_stacks() {
+    let mut journal = Journal::default();
+    let changes = || EntryKind::Changes(String::new(), vec![change("x", None, Some("1"))]);
+    journal.push(changes(), 0);
+    journal.push(changes(), 0);
+    journal.push(changes(), 0);
+    assert_eq!(journal.stacks(), (vec![1, 2, 3], vec![]));
+    journal.push(EntryKind::Undo(3), 0);
+    journal.push(EntryKind::Undo(2), 0);
+    assert_eq!(journal.stacks(), (vec![1], vec![3, 2]));
+    journal.push(EntryKind::Redo(2), 0);
+    assert_eq!(journal.stacks(), (vec![1, 2], vec![3]));
+    journal.push(changes(), 0);
+    assert_eq!(journal.stacks(), (vec![1, 2, 7], vec![]));
+  }
+
+  #[test]
+  fn test_format_history() {
+    let mut journal = Journal::default();
+    assert_eq!(format_history(&journal, 20), "No changes have been recorded yet.\n");
+    journal.push(EntryKind::Changes("alia a x 1 r y".to_string(), vec![change("x", None, Some("1")), change("y", Some("2"), None)]), 0);
+    journal.push(EntryKind::Changes("alia c x 3".to_string(), vec![change("x", Some("1"), Some("3"))]), 60);
+    journal.push(EntryKind::Undo(2), 120);
+    let expected = "#3 1970-01-01 00:02:00 UTC undo #2\n#2 1970-01-01 00:01:00 UTC alia c x 3 (undone)\n    changed x in /home/me/cfg.alia\n#1 1970-01-01 00:00:00 UTC alia a x 1 r y\n    added x in /home/me/cfg.alia\n    removed y in /home/me/cfg.alia\n";
+    assert_eq!(format_history(&journal, 20), expected);
+    assert_eq!(format_history(&journal, 1), "#3 1970-01-01 00:02:00 UTC undo #2\n");
+  }
+}
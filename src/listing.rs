@@ -0,0 +1,432 @@
+use std::collections::BTreeMap;
+use regex::Regex;
+use crate::store::{Alias, AliasStore, in_namespace, Layer, NAMESPACE_SEPARATOR};
+use crate::time::format_timestamp;
+use crate::usage::Usage;
+use crate::END_OF_LINE_SEQUENCE;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum SortOrder {
+  #[default]
+  Name,
+  /// Most recently executed first.
+  Recent,
+  /// Most often executed first.
+  Usage,
+}
+
+impl SortOrder {
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "name" => Some(SortOrder::Name),
+      "recent" => Some(SortOrder::Recent),
+      "usage" => Some(SortOrder::Usage),
+      _ => None,
+    }
+  }
+}
+
+/// Which aliases to list, matched against their names. Aliases can be narrowed down by their tags as well, with
+/// collect_entries.
+#[derive(Debug, Clone, Default)]
+pub enum Filter {
+  #[default]
+  All,
+  Glob(String),
+  Regex(Regex),
+  /// The aliases in a namespace, along with the alias with the name of the namespace itself if there is one.
+  Namespace(String),
+}
+
+impl Filter {
+  pub fn matches(&self, name: &str) -> bool {
+    match self {
+      Filter::All => true,
+      Filter::Glob(pattern) => glob_match(pattern, name),
+      Filter::Regex(regex) => regex.is_match(name),
+      Filter::Namespace(namespace) => name == namespace || in_namespace(name, namespace),
+    }
+  }
+}
+
+/// An alias that is in effect, with everything `list` and `show` know about it.
+#[derive(Debug, Clone)]
+pub struct ListEntry<'a> {
+  pub name: &'a str,
+  pub alias: &'a Alias,
+  pub layer: &'a Layer,
+  pub usage: Usage,
+}
+
+pub fn get_entry<'a>(cfg: &'a AliasStore, name: &str) -> Option<ListEntry<'a>> {
+  let layer = &cfg.layers[cfg.origin(name)?];
+  let (name, alias) = layer.aliases.get_key_value(name)?;
+  Some(ListEntry { name, alias, layer, usage: cfg.usage.get(name).copied().unwrap_or_default() })
+}
+
+/// The aliases in effect that match filter and have every one of tags, in the given order.
+pub fn collect_entries<'a>(cfg: &'a AliasStore, filter: &Filter, tags: &[String], sort: SortOrder) -> Vec<ListEntry<'a>> {
+  let mut entries: Vec<ListEntry> = cfg.merged().into_iter()
+    .filter(|(name, (alias, _))| filter.matches(name) && tags.iter().all(|tag| alias.tags.contains(tag)))
+    .map(|(name, (alias, layer))| ListEntry { name, alias, layer: &cfg.layers[layer], usage: cfg.usage.get(name).copied().unwrap_or_default() })
+    .collect();
+  entries.sort_unstable_by(|a, b| {
+    let by_name = a.name.cmp(b.name);
+    match sort {
+      SortOrder::Name => by_name,
+      SortOrder::Recent => b.usage.last_used.cmp(&a.usage.last_used).then(by_name),
+      SortOrder::Usage => b.usage.count.cmp(&a.usage.count).then(by_name),
+    }
+  });
+  entries
+}
+
+/// Formats entries as a table with a column for the name, the value and, if show_origin is set, the file the alias
+/// came from. Columns for the tags and the description are added if any of the aliases have them.
+pub fn format_table(entries: &[ListEntry], show_origin: bool) -> String {
+  if entries.is_empty() {
+    return String::new();
+  }
+  let show_tags = entries.iter().any(|entry| !entry.alias.tags.is_empty());
+  let show_description = entries.iter().any(|entry| entry.alias.description.is_some());
+  let mut rows = vec![vec!["NAME".to_string(), "VALUE".to_string()]];
+  if show_tags {
+    rows[0].push("TAGS".to_string());
+  }
+  if show_origin {
+    rows[0].push("ORIGIN".to_string());
+  }
+  if show_description {
+    rows[0].push("DESCRIPTION".to_string());
+  }
+  for entry in entries {
+    let mut row = vec![entry.name.to_string(), entry.alias.command.to_string()];
+    if show_tags {
+      row.push(entry.alias.tags.join(","));
+    }
+    if show_origin {
+      row.push(format!("{} ({})", entry.layer.path.display(), entry.layer.kind));
+    }
+    if show_description {
+      row.push(entry.alias.description.clone().unwrap_or_default());
+    }
+    rows.push(row);
+  }
+  let widths: Vec<usize> = (0..rows[0].len()).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+  let mut result_string = String::new();
+  for row in rows {
+    let mut line = String::new();
+    for (column, cell) in row.iter().enumerate() {
+      line.push_str(cell);
+      line.push_str(&" ".repeat(widths[column] - cell.chars().count() + 2));
+    }
+    // The last column, or the ones before it if it is empty, would only add trailing spaces.
+    result_string.push_str(line.trim_end());
+    result_string.push_str(END_OF_LINE_SEQUENCE);
+  }
+  result_string
+}
+
+/// A namespace in the tree drawn by format_tree, along with the alias that has its name, if there is one.
+#[derive(Default)]
+struct TreeNode<'a> {
+  alias: Option<&'a Alias>,
+  children: BTreeMap<&'a str, TreeNode<'a>>,
+}
+
+/// Formats the entries in namespace as a tree, with a branch for every namespace nested in it and the value of every
+/// alias next to the last part of its name. Entries that are not in namespace are left out.
+pub fn format_tree(namespace: &str, entries: &[ListEntry]) -> String {
+  let mut root = TreeNode::default();
+  for entry in entries {
+    let mut node = &mut root;
+    if let Some(rest) = entry.name.strip_prefix(namespace).and_then(|rest| rest.strip_prefix(NAMESPACE_SEPARATOR)) {
+      for segment in rest.split(NAMESPACE_SEPARATOR) {
+        node = node.children.entry(segment).or_default();
+      }
+    } else if entry.name != namespace {
+      continue;
+    }
+    node.alias = Some(entry.alias);
+  }
+  let mut lines = vec![tree_label(namespace, root.alias, 0)];
+  draw_branches(&root, "", &mut lines);
+  lines.into_iter().map(|line| line + END_OF_LINE_SEQUENCE).collect()
+}
+
+/// Draws a line for every child of node, each followed by its own children, with prefix in front of every line.
+fn draw_branches(node: &TreeNode, prefix: &str, lines: &mut Vec<String>) {
+  // The values of siblings line up with each other.
+  let width = node.children.keys().map(|name| name.chars().count()).max().unwrap_or(0);
+  for (index, (name, child)) in node.children.iter().enumerate() {
+    let last = index + 1 == node.children.len();
+    lines.push(format!("{prefix}{}{}", if last { "└── " } else { "├── " }, tree_label(name, child.alias, width)));
+    draw_branches(child, &format!("{prefix}{}", if last { "    " } else { "│   " }), lines);
+  }
+}
+
+fn tree_label(name: &str, alias: Option<&Alias>, width: usize) -> String {
+  match alias {
+    Some(alias) => format!("{name}{}  {}", " ".repeat(width.saturating_sub(name.chars().count())), alias.command),
+    None => name.to_string(),
+  }
+}
+
+/// Formats entries as a JSON array with one object per alias.
+pub fn format_json(entries: &[ListEntry]) -> String {
+  let objects: Vec<String> = entries.iter().map(|entry| format!("  {}", entry_to_json(entry))).collect();
+  if objects.is_empty() {
+    return format!("[]{END_OF_LINE_SEQUENCE}");
+  }
+  format!("[{END_OF_LINE_SEQUENCE}{}{END_OF_LINE_SEQUENCE}]{END_OF_LINE_SEQUENCE}", objects.join(&format!(",{END_OF_LINE_SEQUENCE}")))
+}
+
+pub fn entry_to_json(entry: &ListEntry) -> String {
+  let last_used = match entry.usage.count {
+    0 => "null".to_string(),
+    _ => entry.usage.last_used.to_string(),
+  };
+  let description = entry.alias.description.as_deref().map_or_else(|| "null".to_string(), json_string);
+  let tags: Vec<String> = entry.alias.tags.iter().map(|tag| json_string(tag)).collect();
+  format!(
+    "{{\"name\": {}, \"value\": {}, \"description\": {description}, \"tags\": [{}], \"origin\": {}, \"layer\": {}, \"uses\": {}, \"last_used\": {last_used}}}",
+    json_string(entry.name), json_string(&entry.alias.command), tags.join(", "), json_string(&entry.layer.path.display().to_string()), json_string(&entry.layer.kind.to_string()), entry.usage.count,
+  )
+}
+
+/// Formats everything that is known about a single alias, one field per line.
+pub fn format_details(entry: &ListEntry) -> String {
+  let last_used = match entry.usage.count {
+    0 => "never".to_string(),
+    _ => format_timestamp(entry.usage.last_used),
+  };
+  let mut lines = vec![format!("name:        {}", entry.name), format!("value:       {}", entry.alias.command)];
+  if let Some(description) = &entry.alias.description {
+    lines.push(format!("description: {description}"));
+  }
+  if !entry.alias.tags.is_empty() {
+    lines.push(format!("tags:        {}", entry.alias.tags.join(", ")));
+  }
+  if let Some(cwd) = &entry.alias.cwd {
+    lines.push(format!("cwd:         {cwd}"));
+  }
+  if let Some(shell) = &entry.alias.shell {
+    lines.push(format!("shell:       {shell}"));
+  }
+  if !entry.alias.env.is_empty() {
+    lines.push(format!("env:         {}", entry.alias.env.iter().map(|(variable, value)| format!("{variable}={value}")).collect::<Vec<_>>().join(", ")));
+  }
+  if !entry.alias.unset_env.is_empty() {
+    lines.push(format!("unset env:   {}", entry.alias.unset_env.join(", ")));
+  }
+  lines.push(format!("origin:      {} ({})", entry.layer.path.display(), entry.layer.kind));
+  lines.push(format!("uses:        {}", entry.usage.count));
+  lines.push(format!("last used:   {last_used}"));
+  lines.into_iter().map(|line| line + END_OF_LINE_SEQUENCE).collect()
+}
+
+/// Explains what the alias of entry is for, as `alia help <alias>` does: its description, its command and its tags.
+pub fn format_alias_help(entry: &ListEntry) -> String {
+  let mut lines = vec![match &entry.alias.description {
+    Some(description) => format!("{}: {description}", entry.name),
+    None => format!("{} has no description.", entry.name),
+  }];
+  lines.push(String::new());
+  lines.extend(entry.alias.command.lines().map(|line| format!("    {line}")));
+  if !entry.alias.tags.is_empty() {
+    lines.push(String::new());
+    lines.push(format!("Tags: {}", entry.alias.tags.join(", ")));
+  }
+  lines.into_iter().map(|line| line + END_OF_LINE_SEQUENCE).collect()
+}
+
+fn json_string(s: &str) -> String {
+  let mut result = String::with_capacity(s.len() + 2);
+  result.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+      c => result.push(c),
+    }
+  }
+  result.push('"');
+  result
+}
+
+/// Matches name against a glob pattern supporting `*`, `?` and character classes such as `[abc]`, `[a-z]` and `[!x]`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let name: Vec<char> = name.chars().collect();
+  glob_match_chars(&pattern, &name)
+}
+
+fn glob_match_chars(pattern: &[char], name: &[char]) -> bool {
+  match pattern.first() {
+    None => name.is_empty(),
+    Some('*') => (0..=name.len()).any(|skip| glob_match_chars(&pattern[1..], &name[skip..])),
+    Some('?') => !name.is_empty() && glob_match_chars(&pattern[1..], &name[1..]),
+    Some('[') => match parse_class(pattern) {
+      Some((len, matches)) => !name.is_empty() && matches(name[0]) && glob_match_chars(&pattern[len..], &name[1..]),
+      None => name.first() == Some(&'[') && glob_match_chars(&pattern[1..], &name[1..]),
+    },
+    Some(c) => name.first() == Some(c) && glob_match_chars(&pattern[1..], &name[1..]),
+  }
+}
+
+/// Parses the character class at the start of pattern. Returns its length and a function that checks whether a char
+/// is in it, or None if the class is never closed, in which case the `[` is matched literally.
+fn parse_class(pattern: &[char]) -> Option<(usize, impl Fn(char) -> bool + '_)> {
+  let mut index = 1;
+  let negated = matches!(pattern.get(index), Some('!' | '^'));
+  if negated {
+    index += 1;
+  }
+  let start = index;
+  // A `]` right at the start of a class is part of it rather than closing it.
+  if pattern.get(index) == Some(&']') {
+    index += 1;
+  }
+  while pattern.get(index)? != &']' {
+    index += 1;
+  }
+  let members = &pattern[start..index];
+  let matches = move |c: char| {
+    let mut i = 0;
+    let mut found = false;
+    while i < members.len() {
+      if i + 2 < members.len() && members[i + 1] == '-' {
+        found |= members[i] <= c && c <= members[i + 2];
+        i += 3;
+      } else {
+        found |= members[i] == c;
+        i += 1;
+      }
+    }
+    found != negated
+  };
+  Some((index + 1, matches))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use std::path::PathBuf;
+  use regex::Regex;
+  use crate::store::{Alias, AliasStore, Layer, LayerKind};
+  use crate::usage::Usage;
+  use crate::END_OF_LINE_SEQUENCE;
+  use super::{collect_entries, Filter, format_alias_help, format_details, format_json, format_table, format_tree, get_entry, glob_match, SortOrder};
+
+  #[test]
+  fn test_glob_match() {
+    assert!(glob_match("*", "anything"));
+    assert!(glob_match("b*d", "build"));
+    assert!(!glob_match("b*d", "builds"));
+    assert!(glob_match("?uild", "build"));
+    assert!(glob_match("db.*", "db.migrate"));
+    assert!(glob_match("[bd]*", "deploy"));
+    assert!(!glob_match("[!bd]*", "deploy"));
+    assert!(glob_match("test[0-9]", "test7"));
+    assert!(!glob_match("test[0-9]", "testx"));
+    assert!(glob_match("[]x]", "]"));
+    assert!(glob_match("a[b", "a[b"));
+  }
+
+  fn store() -> AliasStore {
+    let global = HashMap::from([("b".to_string(), Alias::new("global b".to_string())), ("a".to_string(), Alias::new("global a".to_string())), ("c".to_string(), Alias::new("c".to_string()))]);
+    let mut cfg = AliasStore::new(Layer::new(PathBuf::from("global.alia"), LayerKind::Global, global));
+    cfg.push_layer(Layer::new(PathBuf::from(".alia"), LayerKind::Local, HashMap::from([("b".to_string(), Alias::new("local \"b\"".to_string()))])));
+    cfg.usage.entries.insert("c".to_string(), Usage { count: 5, last_used: 10 });
+    cfg.usage.entries.insert("b".to_string(), Usage { count: 1, last_used: 20 });
+    cfg
+  }
+
+  fn names(cfg: &AliasStore, filter: &Filter, sort: SortOrder) -> Vec<String> {
+    collect_entries(cfg, filter, &[], sort).iter().map(|entry| entry.name.to_string()).collect()
+  }
+
+  #[test]
+  fn test_collect_entries() {
+    let cfg = store();
+    assert_eq!(names(&cfg, &Filter::All, SortOrder::Name), ["a", "b", "c"]);
+    assert_eq!(names(&cfg, &Filter::All, SortOrder::Recent), ["b", "c", "a"]);
+    assert_eq!(names(&cfg, &Filter::All, SortOrder::Usage), ["c", "b", "a"]);
+    assert_eq!(names(&cfg, &Filter::Glob("[ab]".to_string()), SortOrder::Name), ["a", "b"]);
+    assert_eq!(names(&cfg, &Filter::Regex(Regex::new("^c$").unwrap()), SortOrder::Name), ["c"]);
+  }
+
+  #[test]
+  fn test_format_table() {
+    let cfg = store();
+    let entries = collect_entries(&cfg, &Filter::All, &[], SortOrder::Name);
+    let expected = ["NAME  VALUE", "a     global a", "b     local \"b\"", "c     c"].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat();
+    assert_eq!(format_table(&entries, false), expected);
+    let expected = ["NAME  VALUE      ORIGIN", "a     global a   global.alia (global)", "b     local \"b\"  .alia (local)", "c     c          global.alia (global)"].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat();
+    assert_eq!(format_table(&entries, true), expected);
+    assert_eq!(format_table(&[], true), "");
+  }
+
+  #[test]
+  fn test_format_json() {
+    let cfg = store();
+    let entries = collect_entries(&cfg, &Filter::Glob("[ab]".to_string()), &[], SortOrder::Name);
+    let expected = [
+      "[",
+      "  {\"name\": \"a\", \"value\": \"global a\", \"description\": null, \"tags\": [], \"origin\": \"global.alia\", \"layer\": \"global\", \"uses\": 0, \"last_used\": null},",
+      "  {\"name\": \"b\", \"value\": \"local \\\"b\\\"\", \"description\": null, \"tags\": [], \"origin\": \".alia\", \"layer\": \"local\", \"uses\": 1, \"last_used\": 20}",
+      "]",
+    ].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat();
+    assert_eq!(format_json(&entries), expected);
+    assert_eq!(format_json(&[]), format!("[]{END_OF_LINE_SEQUENCE}"));
+  }
+
+  #[test]
+  fn test_descriptions_and_tags() {
+    let mut cfg = store();
+    let a = cfg.layers[0].aliases.get_mut("a").unwrap();
+    a.tags = vec!["deploy".to_string(), "k8s".to_string()];
+    let c = cfg.layers[0].aliases.get_mut("c").unwrap();
+    c.tags = vec!["deploy".to_string()];
+    c.description = Some("Deploys c".to_string());
+    c.cwd = Some("deploy".to_string());
+    c.unset_env = vec!["KUBECONFIG".to_string()];
+    let tagged = |tags: &[&str]| collect_entries(&cfg, &Filter::All, &tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>(), SortOrder::Name).iter().map(|entry| entry.name.to_string()).collect::<Vec<_>>();
+    assert_eq!(tagged(&["deploy"]), ["a", "c"]);
+    assert_eq!(tagged(&["deploy", "k8s"]), ["a"]);
+    assert_eq!(tagged(&["nope"]), Vec::<String>::new());
+    let entries = collect_entries(&cfg, &Filter::All, &[], SortOrder::Name);
+    let expected = ["NAME  VALUE      TAGS        DESCRIPTION", "a     global a   deploy,k8s", "b     local \"b\"", "c     c          deploy      Deploys c"].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat();
+    assert_eq!(format_table(&entries, false), expected);
+    assert!(format_json(&entries[2..]).contains("\"description\": \"Deploys c\", \"tags\": [\"deploy\"],"));
+    let c = get_entry(&cfg, "c").unwrap();
+    assert!(format_details(&c).contains(&format!("description: Deploys c{END_OF_LINE_SEQUENCE}tags:        deploy{END_OF_LINE_SEQUENCE}cwd:         deploy{END_OF_LINE_SEQUENCE}unset env:   KUBECONFIG{END_OF_LINE_SEQUENCE}")));
+    assert_eq!(format_alias_help(&c), ["c: Deploys c", "", "    c", "", "Tags: deploy"].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat());
+    assert_eq!(format_alias_help(&get_entry(&cfg, "b").unwrap()), ["b has no description.", "", "    local \"b\""].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat());
+  }
+
+  #[test]
+  fn test_format_tree() {
+    let aliases = [("db", "psql"), ("db.migrate", "./migrate.sh"), ("db.reset", "./reset.sh"), ("db.seed.dev", "./seed.sh dev"), ("db.seed.production", "./seed.sh prod"), ("dbx", "x"), ("k8s.logs", "kubectl logs")];
+    let aliases = aliases.into_iter().map(|(name, command)| (name.to_string(), Alias::new(command.to_string()))).collect();
+    let cfg = AliasStore::new(Layer::new(PathBuf::from("global.alia"), LayerKind::Global, aliases));
+    let entries = collect_entries(&cfg, &Filter::Namespace("db".to_string()), &[], SortOrder::Name);
+    assert_eq!(entries.iter().map(|entry| entry.name).collect::<Vec<_>>(), ["db", "db.migrate", "db.reset", "db.seed.dev", "db.seed.production"]);
+    let expected = [
+      "db  psql",
+      "├── migrate  ./migrate.sh",
+      "├── reset    ./reset.sh",
+      "└── seed",
+      "    ├── dev         ./seed.sh dev",
+      "    └── production  ./seed.sh prod",
+    ].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat();
+    assert_eq!(format_tree("db", &entries), expected);
+    let entries = collect_entries(&cfg, &Filter::Namespace("db.seed".to_string()), &[], SortOrder::Name);
+    let expected = ["db.seed", "├── dev         ./seed.sh dev", "└── production  ./seed.sh prod"].map(|line| line.to_string() + END_OF_LINE_SEQUENCE).concat();
+    assert_eq!(format_tree("db.seed", &entries), expected);
+  }
+}
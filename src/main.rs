@@ -1,11 +1,56 @@
-use std::collections::HashMap;
+mod backup;
+mod completions;
+mod diff;
+mod diagnostics;
+mod editor;
+mod integration;
+mod journal;
+mod listing;
+mod paths;
+mod placeholders;
+mod process;
+mod references;
+mod schema;
+mod shell_words;
+mod shells;
+mod storage;
+mod store;
+mod suggest;
+mod time;
+mod usage;
+
+use std::collections::{BTreeMap, HashMap};
 use std::{fs};
 use std::fmt::{Display, Formatter};
-use std::fs::{File};
-use std::process::{Command, exit};
+use std::io::ErrorKind;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
 use simple_logger::SimpleLogger;
+use std::env;
 use std::env::{args};
 use std::hash::Hash;
+use paths::{CONFIG_ENV_VAR, resolve_config_path, resolve_working_directory};
+use placeholders::{expand_placeholders, PlaceholderErrorCode};
+use references::{expand_references, ReferenceErrorCode};
+use shell_words::ShellWordsErrorCode;
+use shells::Shell;
+use integration::{fingerprint, init_script};
+use backup::{create_backup, DEFAULT_BACKUP_COUNT, find_backup, list_backups};
+use diff::unified_diff;
+use journal::{Change, EntryKind, format_history, Journal};
+use completions::{command_names, complete, completion_script, ends_forwarded_args, find_command};
+use listing::{collect_entries, entry_to_json, Filter, format_alias_help, format_details, format_json, format_table, format_tree, get_entry, SortOrder};
+use regex::Regex;
+use suggest::{closest_matches, did_you_mean, Suggestion, unambiguous};
+use usage::UsageStats;
+use process::{ChildFailure, Execution, Interpreter, Program, wait_for_child};
+use diagnostics::{Diagnostic, Span};
+use editor::{alias_template, confirm, create_temp_file, edit_file, edited_command, editor_command};
+use schema::{is_legacy_format, legacy_to_document, LegacyEntry, parse_document, SchemaError, update_document};
+use storage::{DEFAULT_LOCK_TIMEOUT, enable_locking, lock_store_file, LOCK_TIMEOUT_ENV_VAR, LockErrorCode, parse_lock_timeout, release_locks, set_lock_timeout, with_lock, write_atomically};
+use store::{Alias, AliasStore, discover_local_files, in_namespace, is_valid_alias_name, Layer, LayerKind, LayerSelector, LOCAL_CONFIG_FILE_NAME, new_local_file_path, PendingEdit, PendingRemoval};
 
 trait PopChar {
   fn pop_char(&mut self) -> Option<char>;
@@ -14,8 +59,6 @@ trait PopChar {
 
 impl PopChar for &str {
   /// Pops the first char from the string and returns it. returns None if the string is empty.
-
-
   fn pop_char(&mut self) -> Option<char> {
     let top = self.chars().next();
     match top {
@@ -28,25 +71,7 @@ impl PopChar for &str {
   }
 }
 
-trait OkOrDefault {
-  type T;
-  fn ok_or_default<E: Default>(self) -> Result<Self::T, E>;
-}
-
-impl<X> OkOrDefault for Option<X> {
-  type T = X;
-  fn ok_or_default<E: Default>(self) -> Result<Self::T, E> {
-    self.ok_or(E::default())
-  }
-}
-
-
-const PATH_TO_CONFIG: &'static str = "./cfg.alia";
-
-const END_OF_LINE_SEQUENCE: &'static str = if cfg!(windows) { "\r\n" } else { "\n" };
-
-const NAME_OF_TERMINAL_PROGRAM: &'static str = if cfg!(windows) { "cmd" } else { "sh" };
-const RUN_AS_COMMAND_IN_OS: &'static str = if cfg!(windows) { "/C" } else { "-c" };
+const END_OF_LINE_SEQUENCE: &str = if cfg!(windows) { "\r\n" } else { "\n" };
 
 
 use log::{info, log, Level};
@@ -57,6 +82,11 @@ enum StringParseErrorCode {
   InvalidString,
   EmptyString,
   StringWithoutClosingQuote,
+  /// A `\u` escape that is not followed by `{`, one to six hex digits and `}`, or whose digits are not a Unicode
+  /// scalar value. Holds the text after the `\u`.
+  InvalidUnicodeEscape(String),
+  /// An alias name with a namespace segment that is empty, as in `db..migrate`. Holds the name.
+  InvalidNameSegment(String),
 }
 
 impl Display for StringParseErrorCode {
@@ -67,21 +97,28 @@ impl Display for StringParseErrorCode {
 
 fn string_parse_error_to_string(err: &StringParseErrorCode) -> String {
   match err {
-    StringWithoutOpeningQuote => "Your string did not contain an opening quote.",
-    InvalidString => "Your string consisted of only an opening parenthesis and nothing else.",
-    EmptyString => "Your string was empty. This is not allowed as all aliases must have names and values that are not empty.",
-    StringWithoutClosingQuote => "Your string did not contain a closing quote.",
-  }.to_string()
+    StringWithoutOpeningQuote => "Your string did not contain an opening quote.".to_string(),
+    InvalidString => "Your string consisted of only an opening parenthesis and nothing else.".to_string(),
+    EmptyString => "Your string was empty. This is not allowed as all aliases must have names and values that are not empty.".to_string(),
+    StringWithoutClosingQuote => "Your string did not contain a closing quote.".to_string(),
+    InvalidUnicodeEscape(s) => format!("Your string contained \\u{s}, which is not a valid Unicode escape. It should look like \\u{{1F600}}."),
+    InvalidNameSegment(s) => format!("Your string {s} cannot be an alias name, as the parts it is split into by dots must not be empty and the last one cannot be *."),
+  }
 }
 
 #[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
 enum ConfigParseErrorCode {
-  ConfigNotFound,
-  ConfigCouldNotBeCreated(String),
-  MissingEqualSign(usize),
-  MissingAliasValue(usize),
-  InvalidAlias(StringParseErrorCode, usize),
-  InvalidValue(StringParseErrorCode, usize),
+  ConfigLocationUnknown,
+  ConfigCouldNotBeRead(String),
+  MissingEqualSign(Span),
+  MissingAliasValue(Span),
+  InvalidAlias(StringParseErrorCode, Span),
+  InvalidValue(StringParseErrorCode, Span),
+  InvalidDocument(SchemaError),
+  /// One of the errors above, pointing at where it is in the alias file it was found in.
+  InvalidConfigFile(Box<Diagnostic>),
+  ConfigCouldNotBeMigrated(String),
+  ConfigCouldNotBeLocked(LockErrorCode),
 }
 
 impl Display for ConfigParseErrorCode {
@@ -92,25 +129,117 @@ impl Display for ConfigParseErrorCode {
 
 fn config_parse_error_to_string(err: &ConfigParseErrorCode) -> String {
   match err {
-    ConfigNotFound => format!("A config file could not be found. A new one has been created."),
-    ConfigCouldNotBeCreated(s) => format!("A config file could not be found. Creating a new one also failed. Here is the error the OS reported when creating the file:{END_OF_LINE_SEQUENCE}{s}"),
-    MissingEqualSign(v) => format!("Expected an equal sign after alias name. Line number: {v}"),
-    MissingAliasValue(v) => format!("Missing alias value. Line number: {v}"),
-    InvalidAlias(e, v) => format!("The alias name could not be parsed into a string. Line number: {v}. Here is the string parse error:{END_OF_LINE_SEQUENCE}{e}."),
-    InvalidValue(e, v) => format!("The alias value could not be parsed into a string. Line number: {v}. Here is the string parse error:{END_OF_LINE_SEQUENCE}{e}."),
+    ConfigLocationUnknown => format!("Could not work out where to store your aliases. Set the {CONFIG_ENV_VAR} environment variable, pass --config <path> or set HOME."),
+    ConfigCouldNotBeRead(s) => format!("The config file could not be read. Here is the error the OS reported:{END_OF_LINE_SEQUENCE}{s}"),
+    MissingEqualSign(_) => "Expected an equal sign after the alias name.".to_string(),
+    MissingAliasValue(_) => "Expected an alias value after the equal sign.".to_string(),
+    InvalidAlias(e, _) => format!("The alias name could not be parsed into a string. {e}"),
+    InvalidValue(e, _) => format!("The alias value could not be parsed into a string. {e}"),
+    InvalidDocument(e) => e.to_string(),
+    InvalidConfigFile(diagnostic) => diagnostic.to_string(),
+    ConfigCouldNotBeMigrated(s) => format!("The alias file is in the old format and could not be upgraded to the new one. Here is the error the OS reported:{END_OF_LINE_SEQUENCE}{s}"),
+    ConfigCouldNotBeLocked(e) => e.to_string(),
+  }
+}
+
+/// The part of the alias file err is about, if it is about a particular part.
+fn config_parse_error_span(err: &ConfigParseErrorCode) -> Option<Span> {
+  match err {
+    MissingEqualSign(span) | MissingAliasValue(span) | InvalidAlias(_, span) | InvalidValue(_, span) => Some(*span),
+    InvalidDocument(e) => e.span,
+    _ => None,
+  }
+}
+
+/// A suggestion for fixing err, if there is anything more to say than its message does.
+fn config_parse_error_help(err: &ConfigParseErrorCode) -> Option<String> {
+  match err {
+    MissingEqualSign(_) => Some("names and values are separated by an equal sign, as in \"name\" = \"value\"".to_string()),
+    MissingAliasValue(_) => Some("add the value of the alias after the equal sign, in quotes".to_string()),
+    InvalidAlias(StringWithoutOpeningQuote, _) => Some("did you forget to quote the alias name? Names and values are written as \"name\" = \"value\"".to_string()),
+    InvalidValue(StringWithoutOpeningQuote, _) => Some("did you forget to quote the alias value? Names and values are written as \"name\" = \"value\"".to_string()),
+    InvalidAlias(e, _) | InvalidValue(e, _) => string_parse_error_help(e),
+    InvalidDocument(e) => e.code.help(),
+    _ => None,
+  }
+}
+
+fn string_parse_error_help(err: &StringParseErrorCode) -> Option<String> {
+  match err {
+    StringWithoutOpeningQuote => None,
+    InvalidString | StringWithoutClosingQuote => Some("add a closing quote at the end of the string. A quote inside the string has to be written as \\\"".to_string()),
+    EmptyString => Some("put something between the quotes".to_string()),
+    InvalidUnicodeEscape(_) => Some("write Unicode escapes as \\u{1F600}, or write \\\\ for a backslash".to_string()),
+    InvalidNameSegment(_) => Some("dots separate namespaces, as in db.migrate, so a name cannot start or end with one or have two in a row".to_string()),
   }
 }
 
+/// Turns err, which was found in contents, the text of the alias file at path, into a diagnostic that points at where
+/// in the file it is.
+fn config_parse_error_diagnostic(err: &ConfigParseErrorCode, path: &Path, contents: &str) -> Diagnostic {
+  Diagnostic::new(config_parse_error_to_string(err), path, contents, config_parse_error_span(err), config_parse_error_help(err))
+}
+
 #[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
 enum CommandLineArgumentErrorCode {
   MissingNameArgument(usize),
   MissingContentArgument(usize),
   FailedExecute(String, usize),
   CannotRemoveNonExistentValue(String, usize),
-  InvalidAliasName(String, usize),
+  /// The alias does not exist. Holds its name, the closest matches and the number of the arg.
+  InvalidAliasName(String, Vec<String>, usize),
   AliasDoesNotExist(String, usize),
   AliasAlreadyExists(String, usize),
-  InvalidCommand(String, usize),
+  /// There is no command with the name. Holds the name, the closest matches and the number of the arg.
+  InvalidCommand(String, Vec<String>, usize),
+  MissingFlagValue(String, usize),
+  UnknownFlag(String, usize),
+  InvalidFlagValue(String, String, usize),
+  InvalidRegex(String, String, usize),
+  CannotShowNonExistentAlias(String, usize),
+  MissingShellArgument(usize),
+  UnsupportedShell(String, usize),
+  InvalidStoreFile(String, ConfigParseErrorCode, usize),
+  CannotCreateLocalStore(usize),
+  MissingPlaceholderArgument(String, String, usize),
+  AliasFailed(String, ChildFailure, usize),
+  InvalidShellWords(String, ShellWordsErrorCode, usize),
+  /// The `@name` references in the alias with the given name could not be expanded.
+  InvalidReferences(String, ReferenceErrorCode, usize),
+  /// The directory the alias with the given name is set to run in, which is the given path once resolved, does not
+  /// exist.
+  MissingWorkingDirectory(String, String, usize),
+  InvalidEnvironmentVariable(String, String),
+  /// Holds the report of the problems and how many there are.
+  ProblemsFound(String, usize, usize),
+  CannotCopyNonExistentAlias(String, usize),
+  CannotOverwriteAlias(String, usize),
+  EditorFailed(String, String, usize),
+  TemporaryFileFailed(String, usize),
+  EditNotSaved(String, usize),
+  /// edit was given along with other commands.
+  EditNotAlone(usize),
+  /// The alias file at the first path was changed while it was being edited. Holds the path of the file the edit was
+  /// kept in as well.
+  EditConflict(String, String, usize),
+  NothingToUndo(usize),
+  NothingToRedo(usize),
+  /// The entry of the journal with the given id cannot be undone or redone, as the alias in the file has been changed
+  /// since. Holds the id, the name of the alias and the path of the file.
+  HistoryConflict(u64, String, String, usize),
+  MissingBackupCommand(usize),
+  InvalidBackupCommand(String, usize),
+  MissingBackupId(usize),
+  BackupDoesNotExist(String, usize),
+  /// The backup with the given id has the given number of problems, so it cannot be restored.
+  InvalidBackup(String, usize, usize),
+  BackupFailed(String, usize),
+  /// The name cannot be given to an alias, as one of its namespace segments is empty.
+  MalformedAliasName(String, usize),
+  /// Removing the aliases in the namespace was not confirmed.
+  RemovalNotConfirmed(String, usize),
+  /// The name given to run is a namespace rather than an alias. Holds the name and the tree of the aliases in it.
+  AliasIsNamespace(String, String, usize),
   NoValidArgs,
   NoArgs,
 }
@@ -129,12 +258,55 @@ fn command_line_error_to_string(err: &CommandLineArgumentErrorCode) -> String {
     MissingContentArgument(v) => format!("You did not supply a content argument for command. Error occurred at argument number {v}"),
     FailedExecute(error, v) => format!("Execution of execute command failed. Error occurred at argument number {v}. Here is the error the OS returned.{END_OF_LINE_SEQUENCE}{END_OF_LINE_SEQUENCE}{error}"),
     CannotRemoveNonExistentValue(name, v) => format!("Error removing alias of name {name}. This alias had no value associated with it and thus could not be removed. Error occurred at argument number {v}"),
-    InvalidAliasName(name, v) => format!("Alias with name {name} has no content associated with it and thus cannot be executed. Error occurred at argument number {v}"),
+    InvalidAliasName(name, suggestions, v) => format!("Alias with name {name} has no content associated with it and thus cannot be executed.{} Error occurred at argument number {v}", did_you_mean(suggestions)),
     AliasDoesNotExist(name, v) => format!("Cannot change alias with name {name} because it does not exist. Error occurred at argument number {v}"),
     AliasAlreadyExists(name, v) => format!("Cannot create alias with name {name} that already exists. Error occurred at argument number {v}"),
-    InvalidCommand(name, v) => format!("Command with name {name} does not exist. Error occurred at argument number {v}"),
-    NoValidArgs => format!("You passed no valid arguments to Alia."),
-    NoArgs => format!("You did not pass any args to Alia."),
+    InvalidCommand(name, suggestions, v) => format!("Command with name {name} does not exist.{} Error occurred at argument number {v}", did_you_mean(suggestions)),
+    MissingFlagValue(flag, v) => format!("The flag {flag} expects a value but none was supplied. Error occurred at argument number {v}"),
+    UnknownFlag(flag, v) => format!("The flag {flag} is not supported by this command. Error occurred at argument number {v}"),
+    InvalidFlagValue(flag, value, v) => format!("{value} is not a valid value for the flag {flag}. Error occurred at argument number {v}"),
+    InvalidRegex(regex, error, v) => format!("{regex} is not a valid regular expression. Error occurred at argument number {v}. Here is the error:{END_OF_LINE_SEQUENCE}{error}"),
+    CannotShowNonExistentAlias(name, v) => format!("Cannot show alias with name {name} because it does not exist. Error occurred at argument number {v}"),
+    MissingShellArgument(v) => format!("You did not supply the name of a shell. Error occurred at argument number {v}"),
+    UnsupportedShell(shell, v) => format!("The shell {shell} is not supported by this command. Error occurred at argument number {v}"),
+    InvalidStoreFile(path, error, v) => format!("The alias file {path} could not be read. Error occurred at argument number {v}. Here is the config parse error:{END_OF_LINE_SEQUENCE}{error}"),
+    MissingPlaceholderArgument(name, placeholder, v) => format!("Alias with name {name} uses the placeholder {placeholder}, but no argument was passed for it. Error occurred at argument number {v}"),
+    AliasFailed(name, failure, v) => format!("Alias with name {name} {failure}. Error occurred at argument number {v}"),
+    InvalidShellWords(name, error, v) => format!("Alias with name {name} could not be split into words to run it without a shell. Error occurred at argument number {v}. Here is the error:{END_OF_LINE_SEQUENCE}{error}"),
+    InvalidReferences(name, error, v) => format!("The aliases that alias with name {name} references could not be expanded. Error occurred at argument number {v}. Here is the error:{END_OF_LINE_SEQUENCE}{error}"),
+    MissingWorkingDirectory(name, path, v) => format!("Alias with name {name} is set to run in the directory {path}, which does not exist. Error occurred at argument number {v}"),
+    CannotCreateLocalStore(v) => format!("The current directory could not be determined, so no {LOCAL_CONFIG_FILE_NAME} file could be created in it. Error occurred at argument number {v}"),
+    InvalidEnvironmentVariable(name, value) => format!("The environment variable {name} is set to {value}, which is not a valid value for it."),
+    ProblemsFound(report, count, v) => format!("{report}Found {count} problem{} in your alias files. Error occurred at argument number {v}", if *count == 1 { "" } else { "s" }),
+    CannotCopyNonExistentAlias(name, v) => format!("Cannot rename or copy alias with name {name} because it does not exist. Error occurred at argument number {v}"),
+    CannotOverwriteAlias(name, v) => format!("Cannot overwrite alias with name {name} that already exists. Pass --force before the names to replace it. Error occurred at argument number {v}"),
+    EditorFailed(editor, error, v) => format!("Editing with {editor} failed, so nothing was saved. Set $VISUAL or $EDITOR to choose another editor. Error occurred at argument number {v}. Here is the error:{END_OF_LINE_SEQUENCE}{error}"),
+    TemporaryFileFailed(error, v) => format!("The temporary file to edit in could not be written or read. Error occurred at argument number {v}. Here is the error the OS returned.{END_OF_LINE_SEQUENCE}{END_OF_LINE_SEQUENCE}{error}"),
+    EditNotSaved(path, v) => format!("Your changes were not saved because they have problems. They are kept in {path} so nothing is lost. Error occurred at argument number {v}"),
+    EditNotAlone(v) => format!("edit opens an editor, so it has to be the only command. Run the other commands separately. Error occurred at argument number {v}"),
+    EditConflict(path, edit_path, v) => format!("{path} was changed by another process while you were editing it, so your changes were not saved over it. They are kept in {edit_path} so nothing is lost. Error occurred at argument number {v}"),
+    NothingToUndo(v) => format!("There are not that many changes to undo. Run alia history to see them. Error occurred at argument number {v}"),
+    NothingToRedo(v) => format!("There are not that many undone changes to redo. Once other changes are made after undoing, what was undone cannot be redone. Error occurred at argument number {v}"),
+    HistoryConflict(id, name, path, v) => format!("Change #{id} cannot be undone or redone because the alias {name} in {path} has been changed since. Error occurred at argument number {v}"),
+    MissingBackupCommand(v) => format!("You did not say what to do with the backups. Use backup list, backup restore <id> or backup diff <id>. Error occurred at argument number {v}"),
+    InvalidBackupCommand(command, v) => format!("{command} is not something that can be done with backups. Use backup list, backup restore <id> or backup diff <id>. Error occurred at argument number {v}"),
+    MissingBackupId(v) => format!("You did not supply the id of a backup. Run alia backup list to see them. Error occurred at argument number {v}"),
+    BackupDoesNotExist(id, v) => format!("There is no backup with the id {id}. Run alia backup list to see them. Error occurred at argument number {v}"),
+    InvalidBackup(id, count, v) => format!("The backup {id} was not restored because it has {count} problem{}. Error occurred at argument number {v}", if *count == 1 { "" } else { "s" }),
+    BackupFailed(error, v) => format!("The backups could not be read. Error occurred at argument number {v}. Here is the error the OS returned.{END_OF_LINE_SEQUENCE}{END_OF_LINE_SEQUENCE}{error}"),
+    MalformedAliasName(name, v) => format!("{name} cannot be the name of an alias. Dots separate namespaces, as in db.migrate, so a name cannot start or end with one, have two in a row or end in .*. Error occurred at argument number {v}"),
+    RemovalNotConfirmed(namespace, v) => format!("The aliases in {namespace} were not removed, as removing them was not confirmed. Pass --yes before the name to remove them without asking. Error occurred at argument number {v}"),
+    AliasIsNamespace(name, tree, v) => format!("{name} is a group of aliases, so it cannot be run. Run one of them with alia e {name}.<name>. Error occurred at argument number {v}. Here are the aliases in it:{END_OF_LINE_SEQUENCE}{}", tree.trim_end()),
+    NoValidArgs => "You passed no valid arguments to Alia.".to_string(),
+    NoArgs => "You did not pass any args to Alia.".to_string(),
+  }
+}
+
+/// The status Alia exits with when err stops it. A failed alias passes its own status on, so scripts can check it.
+fn command_line_error_to_exit_code(err: &CommandLineArgumentErrorCode) -> i32 {
+  match err {
+    AliasFailed(_, failure, _) => failure.exit_code(),
+    _ => 1,
   }
 }
 
@@ -163,14 +335,18 @@ macro_rules! debug_info {
     }
   );
 }
-macro_rules! printlnln {
-  ($($arg:tt)+) => {
-    println!($($arg)+);
-    println!();
+/// Appends a line to the String out, like println would print it.
+macro_rules! pushln {
+  ($out:expr, $($arg:tt)+) => {
+    $out.push_str(&format!($($arg)+));
+    $out.push('\n');
   };
-  () => {
-    println!();
-    println!();
+}
+/// Appends a line and then an empty one to the String out.
+macro_rules! pushlnln {
+  ($out:expr, $($arg:tt)+) => {
+    pushln!($out, $($arg)+);
+    $out.push('\n');
   };
 }
 
@@ -193,49 +369,252 @@ fn main() {
   }
   debug_log!(Level::Info, "Successfully initialized logger");
 
-  let cfg = read_from_config_file();
+  let mut args: Vec<String> = args().collect();
+  let options = parse_global_options(&mut args);
+  if let Err(e) = &options {
+    println!("Error parsing your arguments.");
+    println!("{}", e);
+    exit_failure!();
+  }
+  let options = unsafe { options.unwrap_unchecked() };
+
+  let lock_timeout = match options.lock_timeout {
+    Some(timeout) => Ok(timeout),
+    None => lock_timeout_from_env(),
+  };
+  if let Err(e) = &lock_timeout {
+    println!("Error parsing your arguments.");
+    println!("{}", e);
+    exit_failure!();
+  }
+  set_lock_timeout(unsafe { lock_timeout.unwrap_unchecked() });
+
+  let path = resolve_config_path(options.config_path.as_deref());
+  let first_run = path.as_deref().is_some_and(|path| !path.exists());
+  let mut store = load_store(path.as_deref(), &args, &options);
+
+  // doctor lists the problems itself, and the commands the shell integration runs on every prompt stay quiet.
+  if !args.get(1).is_some_and(|arg| arg == "doctor" || arg.starts_with("__")) {
+    store.layers.iter().for_each(print_problems);
+  }
+  // init and completions are run by the shell on startup, so they should not greet on every new shell, and there is
+  // no need to explain how to add an alias to someone who is adding one.
+  if first_run && !args.get(1).is_some_and(|arg| arg == "init" || arg == "completions" || arg.starts_with("__") || is_mutating_command(arg)) {
+    print_welcome(&store.layers[0].path);
+  }
+
+
+  // The commands only check their arguments and change the aliases in memory. Nothing is written and no alias is run
+  // unless every one of them succeeded, so a batch of commands is applied either as a whole or not at all.
+  let mut res = parse_command_line_args(args.clone().into_iter(), &mut store);
+
+  // The alias files were read without locks. If the commands are going to change them, the files are locked and read
+  // again, and the commands are checked once more against what they hold now, so no change another Alia process made
+  // in the meantime is lost. The editor is opened before that, so other Alia processes are not kept waiting for it.
+  let writes = store.layers.iter().any(|layer| layer.modified) || store.pending_edit.is_some();
+  if res.is_ok() && writes && !options.dry_run {
+    let edit = match store.pending_edit.clone() {
+      Some(pending) => open_editor(&store, &pending, &editor_command()).map(|edit| edit.map(|edit| (edit, store.layers[pending.layer].source.clone()))),
+      None => Ok(None),
+    };
+    if let Err(e) = &edit {
+      print_command_line_error(e);
+      exit(command_line_error_to_exit_code(e));
+    }
+    enable_locking();
+    store = load_store(path.as_deref(), &args, &options);
+    res = parse_command_line_args(args.into_iter(), &mut store);
+    if let (Ok(()), Ok(Some((edit, original)))) = (&res, edit) {
+      res = apply_edit(&mut store, edit, &original);
+    }
+  }
+  eprint!("{}", store.notices);
+  let res = res.and_then(|()| confirm_removals(&store));
+
+  if let Err(e) = res {
+    print_command_line_error(&e);
+    if store.layers.iter().any(|layer| layer.modified) || !store.executions.is_empty() {
+      eprintln!("None of the other commands took effect.");
+    }
+    exit(command_line_error_to_exit_code(&e));
+  }
+
+  if let Some(layer) = store.layers.iter().find(|layer| layer.modified && layer.broken) {
+    println!("Your changes have not been saved, because parts of {} could not be read and would have been lost.", layer.path.display());
+    println!("Run `alia doctor` to see the problems and fix them by hand, then try again.");
+    exit_failure!();
+  }
+
+  if options.dry_run {
+    print!("{}", format_plan(&store));
+    return;
+  }
+  print!("{}", store.output);
+
+  for layer in store.layers.iter().filter(|layer| layer.modified) {
+    if layer.kind == LayerKind::Global {
+      let keep = layer.settings.backups.unwrap_or(DEFAULT_BACKUP_COUNT);
+      if let Err(e) = create_backup(&layer.path, keep, time::now()) {
+        eprintln!("{} could not be backed up before changing it. Here is the error the OS reported:", layer.path.display());
+        eprintln!("{}", e);
+      }
+    }
+    let res = write_to_config_file(layer);
+
+    if let Err(e) = res {
+      println!("Error writing to cfg file {}. Your changes have not been saved.", layer.path.display());
+      println!("{}", e);
+      exit_failure!();
+    }
+  }
+
+  store.journal.commit(time::now());
+  if let Err(e) = store.journal.save() {
+    eprintln!("Your changes were saved, but could not be recorded in the journal, so they cannot be undone. Here is the error the OS reported:");
+    eprintln!("{}", e);
+  }
+
+  if let Err(e) = store.usage.save() {
+    debug_info!("Failed to save usage statistics: {}", e);
+  }
+
+  release_locks();
+  let res = run_executions(&mut store);
+  if let Err(e) = res {
+    print_command_line_error(&e);
+    exit(command_line_error_to_exit_code(&e));
+  }
+}
+
+/// Reads the global alias file at path and the local ones that are in effect in the current directory, along with the
+/// usage statistics and the journal, and exits if one of the alias files cannot be read.
+fn load_store(path: Option<&Path>, args: &[String], options: &GlobalOptions) -> AliasStore {
+  let cfg = path.ok_or(ConfigLocationUnknown).and_then(|path| read_from_config_file(path, LayerKind::Global, options.strict));
   if let Err(error) = &cfg {
     println!("Error parsing config!");
-    println!("Error:");
     println!("{}", error);
     exit_failure!();
   }
 
-  let mut cfg = unsafe { cfg.unwrap_unchecked() };
+  let mut store = AliasStore::new(unsafe { cfg.unwrap_unchecked() });
+  store.usage = UsageStats::load(UsageStats::path_for(&store.layers[0].path));
+  store.journal = Journal::load(Journal::path_for(&store.layers[0].path));
+  store.journal.command = std::iter::once("alia").chain(args.iter().skip(1).map(String::as_str)).map(shell_words::quote).collect::<Vec<_>>().join(" ");
+  store.strict = options.strict;
+  store.dry_run = options.dry_run;
+  if let Ok(current_dir) = env::current_dir() {
+    for path in discover_local_files(&current_dir) {
+      if store.find_layer(&path).is_some() {
+        continue;
+      }
+      match read_from_config_file(&path, LayerKind::Local, options.strict) {
+        Ok(layer) => { store.push_layer(layer); }
+        Err(error) => {
+          println!("Error parsing config at {}!", path.display());
+          println!("{}", error);
+          exit_failure!();
+        }
+      }
+    }
+  }
   debug_info!("Successfully parsed config!");
   debug_info!("Config:");
-  debug_info!("{:?}", cfg);
-
-
-  let res = parse_command_line_args(args(), &mut cfg);
+  debug_info!("{:?}", store);
+  store
+}
 
-  if let Err(e) = res {
+fn print_command_line_error(e: &CommandLineArgumentErrorCode) {
+  if let AliasFailed(..) | ProblemsFound(..) | EditorFailed(..) | EditNotSaved(..) | EditConflict(..) = e {
+    println!("{}", e);
+  } else {
     println!("Error parsing your arguments.");
     println!("{}", e);
-    exit_failure!();
   }
+}
+
+/// The lock timeout set by the `ALIA_LOCK_TIMEOUT` environment variable, or the default if it is not set.
+fn lock_timeout_from_env() -> Result<Duration, CommandLineArgumentErrorCode> {
+  match env::var(LOCK_TIMEOUT_ENV_VAR) {
+    Ok(value) if !value.is_empty() => parse_lock_timeout(&value).ok_or(InvalidEnvironmentVariable(LOCK_TIMEOUT_ENV_VAR.to_string(), value)),
+    _ => Ok(DEFAULT_LOCK_TIMEOUT),
+  }
+}
 
-  let res = write_to_config_file(&cfg);
+/// Options that apply to the whole invocation rather than to a single command. These have to come before the
+/// first command, e.g. `alia --config ./my.alia add name value`.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+struct GlobalOptions {
+  config_path: Option<String>,
+  /// Whether an alias file with a problem stops Alia, instead of the entry with the problem being skipped.
+  strict: bool,
+  /// How long to wait for another Alia process to finish with an alias file, as set by `--lock-timeout <seconds>`.
+  lock_timeout: Option<Duration>,
+  /// Whether to only print what the commands would change and run, as set by `--dry-run`.
+  dry_run: bool,
+}
 
-  if let Err(_) = res {
-    println!("Error writing to cfg file. Your changes may not have been saved.");
-    exit_failure!();
+/// Removes the global options from the front of args and returns them. The first arg is the path to the program
+/// and is left where it is.
+fn parse_global_options(args: &mut Vec<String>) -> Result<GlobalOptions, CommandLineArgumentErrorCode> {
+  let mut options = GlobalOptions::default();
+  let mut current_arg: usize = 1;
+  while let Some(arg) = args.get(1) {
+    match arg.as_str() {
+      "--config" => {
+        args.remove(1);
+        if args.len() < 2 {
+          return Err(MissingFlagValue("--config".to_string(), current_arg));
+        }
+        options.config_path = Some(args.remove(1));
+        current_arg += 2;
+      }
+      "--strict" => {
+        args.remove(1);
+        options.strict = true;
+        current_arg += 1;
+      }
+      "--dry-run" => {
+        args.remove(1);
+        options.dry_run = true;
+        current_arg += 1;
+      }
+      "--lock-timeout" => {
+        args.remove(1);
+        if args.len() < 2 {
+          return Err(MissingFlagValue("--lock-timeout".to_string(), current_arg));
+        }
+        let value = args.remove(1);
+        options.lock_timeout = Some(parse_lock_timeout(&value).ok_or(InvalidFlagValue("--lock-timeout".to_string(), value, current_arg + 1))?);
+        current_arg += 2;
+      }
+      _ => break,
+    }
   }
+  Ok(options)
 }
 
-type CommandLineArgParser<T> = &'static dyn Fn(&mut T, &mut usize, &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode>;
+type CommandLineArgParser<T> = &'static dyn Fn(&mut Peekable<T>, &mut usize, &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode>;
 
-fn get_next_arg<T: ExactSizeIterator<Item = String>>(args: &mut T, current_arg: &mut usize) -> Option<String> {
+fn get_next_arg<T: Iterator<Item = String>>(args: &mut T, current_arg: &mut usize) -> Option<String> {
   let res = args.next()?;
   *current_arg += 1;
   Some(res)
 }
 
-fn parse_command_line_args<T: 'static + ExactSizeIterator<Item = String>>(mut args: T, cfg: &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode> {
+/// Consumes the next arg if it is a flag, i.e. if it starts with `--`. A `--` on its own ends the flags, so it is
+/// consumed without being returned and the args after it are taken as they are, as in `alia r -- --name`.
+fn get_next_flag<T: Iterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize) -> Option<String> {
+  let res = args.next_if(|arg| arg.starts_with("--"))?;
+  *current_arg += 1;
+  Some(res).filter(|flag| flag != "--")
+}
+
+fn parse_command_line_args<T: 'static + ExactSizeIterator<Item = String>>(args: T, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
   let mut current_arg: usize = 0;
   if args.len() == 0 {
     return Err(NoArgs);
   }
+  let mut args = args.peekable();
   let maybe_path = args.next();
   let mut first_arg_invalid = false;
   if let Some(arg) = maybe_path {
@@ -253,8 +632,21 @@ fn parse_command_line_args<T: 'static + ExactSizeIterator<Item = String>>(mut ar
     current_arg += 1;
     let arg_str = arg.as_str();
     let parser = parse_arg::<T>(arg_str);
-    if let None = parser {
-      return Err(InvalidCommand(arg, current_arg));
+    if parser.is_none() {
+      let suggestions = command_suggestions(cfg, &arg);
+      match unambiguous(&suggestions).filter(|_| cfg.settings().autocorrect).map(|suggestion| suggestion.value.clone()) {
+        Some(Correction::Command(name)) => {
+          pushln!(cfg.notices, "There is no command {arg}, so {name} is run instead.");
+          let parser = unsafe { parse_arg::<T>(name).unwrap_unchecked() };
+          parser(&mut args, &mut current_arg, cfg)?;
+        }
+        Some(Correction::Alias(name)) => {
+          pushln!(cfg.notices, "There is no command {arg}, so the alias {name} is executed instead.");
+          queue_execution(&mut args, &mut current_arg, cfg, name, false, false)?;
+        }
+        None => return Err(InvalidCommand(arg, suggestions.into_iter().map(|suggestion| suggestion.value.to_string()).collect(), current_arg)),
+      }
+      continue;
     }
     let parser = unsafe { parser.unwrap_unchecked() };
     parser(&mut args, &mut current_arg, cfg)?;
@@ -262,199 +654,1335 @@ fn parse_command_line_args<T: 'static + ExactSizeIterator<Item = String>>(mut ar
   Ok(())
 }
 
+/// What a mistyped command may have been meant as.
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum Correction {
+  Command(&'static str),
+  /// An alias, which is executed.
+  Alias(String),
+}
+
+impl Display for Correction {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Correction::Command(name) => write!(f, "{name}"),
+      Correction::Alias(name) => write!(f, "e {name}"),
+    }
+  }
+}
+
+/// The commands, and the aliases to execute, that arg is closest to.
+fn command_suggestions(cfg: &AliasStore, arg: &str) -> Vec<Suggestion<Correction>> {
+  let commands = command_names().map(|name| (name.to_string(), Correction::Command(name)));
+  let aliases = cfg.merged().into_keys().map(|name| (name.to_string(), Correction::Alias(name.to_string())));
+  closest_matches(arg, commands.chain(aliases))
+}
+
 fn parse_arg<T: ExactSizeIterator<Item = String>>(arg: &str) -> Option<CommandLineArgParser<T>> {
   let res: CommandLineArgParser<T> = match arg {
     "a" | "add" => &add_alias,
     "r" | "remove" => &remove_alias,
     "e" | "execute" => &execute_alias,
     "c" | "change" => &change_alias,
-    "h" | "help" => {
-      display_help_message();
-      &do_nothing
-    }
+    "rename" => &rename_alias,
+    "copy" => &copy_alias,
+    "edit" => &edit_alias,
+    "undo" => &undo_changes,
+    "redo" => &redo_changes,
+    "history" => &show_history,
+    "backup" => &manage_backups,
+    "l" | "list" => &list_aliases,
+    "show" => &show_alias,
+    "expand" => &expand_alias,
+    "where" => &print_config_path,
+    "doctor" => &diagnose_config,
+    "init" => &print_init_script,
+    "completions" => &print_completion_script,
+    // Used by the scripts init and completions print rather than by people, so these are left out of the help message.
+    "__fingerprint" => &print_fingerprint,
+    "__complete" => &print_completions,
+    "h" | "help" => &show_help,
     _ => { return None; }
   };
   Some(res)
 }
 
-fn do_nothing<T: ExactSizeIterator<Item = String>>(_args: &mut T, _current_arg: &mut usize, _cfg: &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode> { Ok(()) }
+fn is_command(arg: &str) -> bool {
+  parse_arg::<std::iter::Empty<String>>(arg).is_some()
+}
+
+/// Whether arg is a command that may change an alias file.
+fn is_mutating_command(arg: &str) -> bool {
+  find_command(arg).is_some_and(|info| info.changes_aliases)
+}
+
+/// Collects the args that come after the name of an alias that is being executed, so they can be passed on to it.
+/// These run up to the next Alia command given by its full name, or to the very end if they start with a `--` separator.
+fn get_forwarded_args<T: Iterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize) -> Vec<String> {
+  let mut result = Vec::new();
+  if args.next_if(|arg| arg == "--").is_some() {
+    *current_arg += 1;
+    result.extend(args.by_ref());
+    *current_arg += result.len();
+    return result;
+  }
+  while let Some(arg) = args.next_if(|arg| !ends_forwarded_args(arg)) {
+    *current_arg += 1;
+    result.push(arg);
+  }
+  result
+}
+
+fn do_nothing<T: ExactSizeIterator<Item = String>>(_args: &mut Peekable<T>, _current_arg: &mut usize, _cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> { Ok(()) }
+
+/// Prints the help message, or explains the alias named by the next arg if there is one.
+fn show_help<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let Some(name_of_alias) = args.next_if(|arg| !is_command(arg)) else {
+    cfg.output.push_str(&help_message());
+    return Ok(());
+  };
+  *current_arg += 1;
+  let entry = get_entry(cfg, &name_of_alias).ok_or(CannotShowNonExistentAlias(name_of_alias.clone(), *current_arg))?;
+  cfg.output.push_str(&format_alias_help(&entry));
+  Ok(())
+}
+
+/// Reads the `--global`, `--local` and `--file <path>` flags that may come right after a mutating command.
+fn parse_layer_flags<T: Iterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize) -> Result<LayerSelector, CommandLineArgumentErrorCode> {
+  let mut selector = LayerSelector::Default;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    selector = parse_layer_flag(&flag, args, current_arg)?.ok_or(UnknownFlag(flag, *current_arg))?;
+  }
+  Ok(selector)
+}
+
+/// Reads the value of flag if it is one of the layer flags, and returns None if it is some other flag.
+fn parse_layer_flag<T: Iterator<Item = String>>(flag: &str, args: &mut Peekable<T>, current_arg: &mut usize) -> Result<Option<LayerSelector>, CommandLineArgumentErrorCode> {
+  Ok(Some(match flag {
+    "--global" => LayerSelector::Global,
+    "--local" => LayerSelector::Local,
+    "--file" => LayerSelector::File(get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.to_string(), *current_arg))?.into()),
+    _ => return Ok(None),
+  }))
+}
+
+/// Returns the index of the layer chosen by selector, loading or creating it if needed. Returns None for
+/// LayerSelector::Default, since what that means depends on the command.
+fn select_layer(cfg: &mut AliasStore, selector: &LayerSelector, current_arg: usize) -> Result<Option<usize>, CommandLineArgumentErrorCode> {
+  let (path, kind) = match selector {
+    LayerSelector::Default => return Ok(None),
+    LayerSelector::Global => return Ok(cfg.layers.iter().position(|layer| layer.kind == LayerKind::Global)),
+    LayerSelector::Local => {
+      if let Some(index) = cfg.layers.iter().rposition(|layer| layer.kind == LayerKind::Local) {
+        return Ok(Some(index));
+      }
+      (new_local_file_path().ok_or(CannotCreateLocalStore(current_arg))?, LayerKind::Local)
+    }
+    LayerSelector::File(path) => (path.clone(), LayerKind::File),
+  };
+  load_layer(cfg, path, kind, current_arg).map(Some)
+}
+
+/// Returns the index of the layer for the alias file at path, loading it first if it is not loaded yet.
+fn load_layer(cfg: &mut AliasStore, path: PathBuf, kind: LayerKind, current_arg: usize) -> Result<usize, CommandLineArgumentErrorCode> {
+  if let Some(index) = cfg.find_layer(&path) {
+    return Ok(index);
+  }
+  let layer = read_from_config_file(&path, kind, cfg.strict).map_err(|e| InvalidStoreFile(path.display().to_string(), e, current_arg))?;
+  print_problems(&layer);
+  Ok(cfg.push_layer(layer))
+}
 
-fn add_alias<T: ExactSizeIterator<Item = String>>(args: &mut T, current_arg: &mut usize, cfg: &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode> {
+fn add_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut selector = LayerSelector::Default;
+  let mut description = None;
+  let mut tags: Vec<String> = Vec::new();
+  let mut cwd = None;
+  let mut env = BTreeMap::new();
+  let mut unset_env: Vec<String> = Vec::new();
+  let mut shell = None;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    match flag.as_str() {
+      "--desc" => description = Some(get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag, *current_arg))?),
+      "--tag" => {
+        let tag = get_tag(&flag, args, current_arg)?;
+        if !tags.contains(&tag) {
+          tags.push(tag);
+        }
+      }
+      "--cwd" => {
+        let value = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.clone(), *current_arg))?;
+        if value.is_empty() {
+          return Err(InvalidFlagValue(flag, value, *current_arg));
+        }
+        cwd = Some(value);
+      }
+      "--env" => {
+        let value = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.clone(), *current_arg))?;
+        match value.split_once('=') {
+          Some((variable, variable_value)) if is_valid_variable_name(variable) => env.insert(variable.to_string(), variable_value.to_string()),
+          _ => return Err(InvalidFlagValue(flag, value, *current_arg)),
+        };
+      }
+      "--unset-env" => {
+        let variable = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.clone(), *current_arg))?;
+        if !is_valid_variable_name(&variable) {
+          return Err(InvalidFlagValue(flag, variable, *current_arg));
+        }
+        if !unset_env.contains(&variable) {
+          unset_env.push(variable);
+        }
+      }
+      "--shell" => {
+        let value = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.clone(), *current_arg))?;
+        shell = Some(Interpreter::parse(&value).ok_or(InvalidFlagValue(flag, value, *current_arg))?.name().to_string());
+      }
+      _ => selector = parse_layer_flag(&flag, args, current_arg)?.ok_or(UnknownFlag(flag, *current_arg))?,
+    }
+  }
   let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
-  let res = cfg.get(name_of_alias.as_str());
-  if let Some(_) = res {
+  if !is_valid_alias_name(&name_of_alias) {
+    return Err(MalformedAliasName(name_of_alias, *current_arg));
+  }
+  let layer = select_layer(cfg, &selector, *current_arg)?.unwrap_or(0);
+  if cfg.layers[layer].aliases.contains_key(name_of_alias.as_str()) {
     return Err(AliasAlreadyExists(name_of_alias, *current_arg));
   }
   let content_of_alias = get_next_arg(args, current_arg).ok_or(MissingContentArgument(*current_arg))?;
-  cfg.insert(name_of_alias, content_of_alias);
+  // An empty description is the same as none, so that it does not end up as an empty line in the alias file.
+  let description = description.filter(|description| !description.trim().is_empty());
+  cfg.insert(layer, name_of_alias, Alias { description, tags, cwd, env, unset_env, shell, ..Alias::new(content_of_alias) });
   Ok(())
 }
 
-fn remove_alias<T: ExactSizeIterator<Item = String>>(args: &mut T, current_arg: &mut usize, cfg: &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode> {
+/// Whether name can be given to an environment variable by `--env` or `--unset-env`.
+fn is_valid_variable_name(name: &str) -> bool {
+  !name.is_empty() && !name.contains(['=', '\0'])
+}
+
+/// Reads the value of a `--tag` flag. Tags cannot be empty or contain whitespace or commas, which is what they are
+/// separated by when listed.
+fn get_tag<T: Iterator<Item = String>>(flag: &str, args: &mut Peekable<T>, current_arg: &mut usize) -> Result<String, CommandLineArgumentErrorCode> {
+  let tag = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.to_string(), *current_arg))?;
+  if tag.is_empty() || tag.chars().any(|c| c.is_whitespace() || c == ',') {
+    return Err(InvalidFlagValue(flag.to_string(), tag, *current_arg));
+  }
+  Ok(tag)
+}
+
+/// Removes an alias, or every alias in a namespace when the name is followed by `.*`, as in `db.*`. Removing a
+/// namespace has to be confirmed unless `--yes` is given.
+fn remove_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut selector = LayerSelector::Default;
+  let mut yes = false;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    if flag == "--yes" {
+      yes = true;
+      continue;
+    }
+    selector = parse_layer_flag(&flag, args, current_arg)?.ok_or(UnknownFlag(flag, *current_arg))?;
+  }
   let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
-  match cfg.remove(&name_of_alias) {
+  if let Some(namespace) = name_of_alias.strip_suffix(".*") {
+    return remove_namespace(cfg, &selector, namespace, yes, *current_arg);
+  }
+  let layer = select_layer(cfg, &selector, *current_arg)?.or_else(|| cfg.origin(&name_of_alias));
+  match layer.and_then(|layer| cfg.remove(layer, &name_of_alias)) {
     Some(_) => Ok(()),
     None => Err(CannotRemoveNonExistentValue(name_of_alias, *current_arg))
   }
 }
 
-fn execute_alias<T: ExactSizeIterator<Item = String>>(args: &mut T, current_arg: &mut usize, cfg: &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode> {
-  let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
-  let content_of_alias = cfg.get(name_of_alias.as_str()).ok_or(InvalidAliasName(name_of_alias, *current_arg))?;
-  let split = content_of_alias.split_whitespace();
-  let iter = [RUN_AS_COMMAND_IN_OS].into_iter();
-  let iter = iter.chain(split);
-  let output = Command::new(NAME_OF_TERMINAL_PROGRAM).args(iter).spawn();
-  match output {
-    Ok(_) => Ok(()),
-    Err(e) => Err(FailedExecute(e.to_string(), *current_arg))
+/// Removes every alias in namespace from the layer chosen by selector, or else from the layers they are in effect from,
+/// once that has been confirmed.
+fn remove_namespace(cfg: &mut AliasStore, selector: &LayerSelector, namespace: &str, yes: bool, current_arg: usize) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut members: Vec<(String, usize)> = match select_layer(cfg, selector, current_arg)? {
+    Some(layer) => cfg.layers[layer].aliases.keys().filter(|name| in_namespace(name, namespace)).map(|name| (name.clone(), layer)).collect(),
+    None => cfg.merged().into_iter().filter(|(name, _)| in_namespace(name, namespace)).map(|(name, (_, layer))| (name.to_string(), layer)).collect(),
+  };
+  if members.is_empty() {
+    return Err(CannotRemoveNonExistentValue(format!("{namespace}.*"), current_arg));
+  }
+  members.sort();
+  // Asking is left until every command has been checked, as the batch may fail anyway.
+  if !yes && !cfg.dry_run {
+    let names = members.iter().map(|(name, _)| name.clone()).collect();
+    cfg.pending_removals.push(PendingRemoval { namespace: namespace.to_string(), names, arg: current_arg });
+  }
+  for (name, layer) in members {
+    cfg.remove(layer, &name);
+  }
+  Ok(())
+}
+
+/// Asks whether the namespaces that were removed without `--yes` should really be removed. Returns an error for the
+/// first one that is not confirmed.
+fn confirm_removals(cfg: &AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  for removal in &cfg.pending_removals {
+    let count = removal.names.len();
+    let question = format!("Remove {count} alias{} in {} ({})?", if count == 1 { "" } else { "es" }, removal.namespace, removal.names.join(", "));
+    if !confirm(&question, false) {
+      return Err(RemovalNotConfirmed(removal.namespace.clone(), removal.arg));
+    }
+  }
+  Ok(())
+}
+
+fn rename_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  copy_or_rename_alias(args, current_arg, cfg, true)
+}
+
+fn copy_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  copy_or_rename_alias(args, current_arg, cfg, false)
+}
+
+/// Copies an alias to a new name in the same alias file, along with its description, tags and the rest of its
+/// settings. Renaming removes the original and carries its usage statistics over. Unless `--force` is given, an alias
+/// that already has the new name is left alone and an error is returned.
+fn copy_or_rename_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore, rename: bool) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut selector = LayerSelector::Default;
+  let mut force = false;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    if flag == "--force" {
+      force = true;
+      continue;
+    }
+    selector = parse_layer_flag(&flag, args, current_arg)?.ok_or(UnknownFlag(flag, *current_arg))?;
+  }
+  let source = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
+  let source_arg = *current_arg;
+  let target = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
+  if !is_valid_alias_name(&target) {
+    return Err(MalformedAliasName(target, *current_arg));
+  }
+  let layer = select_layer(cfg, &selector, *current_arg)?.or_else(|| cfg.origin(&source));
+  let Some((layer, alias)) = layer.and_then(|layer| Some((layer, cfg.layers[layer].aliases.get(&source)?.clone()))) else {
+    return Err(CannotCopyNonExistentAlias(source, source_arg));
+  };
+  if source == target {
+    return Ok(());
+  }
+  if !force && cfg.layers[layer].aliases.contains_key(&target) {
+    return Err(CannotOverwriteAlias(target, *current_arg));
   }
+  if rename {
+    cfg.remove(layer, &source);
+    cfg.usage.rename(&source, &target);
+  }
+  cfg.insert(layer, target, alias);
+  Ok(())
 }
 
-fn change_alias<T: ExactSizeIterator<Item = String>>(args: &mut T, current_arg: &mut usize, cfg: &mut HashMap<String, String>) -> Result<(), CommandLineArgumentErrorCode> {
+fn execute_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut detach = false;
+  let mut exec = false;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    match flag.as_str() {
+      "--detach" => detach = true,
+      "--exec" => exec = true,
+      _ => return Err(UnknownFlag(flag, *current_arg)),
+    }
+  }
   let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
-  let res = cfg.get(name_of_alias.as_str());
-  if let None = res {
-    return Err(AliasDoesNotExist(name_of_alias, *current_arg));
+  queue_execution(args, current_arg, cfg, name_of_alias, detach, exec)
+}
+
+/// Takes the args that are passed on to the alias name, which was the last arg, and queues it to be executed. A name
+/// that is not an alias is replaced by the closest one if autocorrect is turned on and there is no doubt about it.
+fn queue_execution<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore, name_of_alias: String, detach: bool, exec: bool) -> Result<(), CommandLineArgumentErrorCode> {
+  let name_arg = *current_arg;
+  let forwarded_args = get_forwarded_args(args, current_arg);
+  let name_of_alias = if cfg.get(&name_of_alias).is_some() {
+    name_of_alias
+  } else if cfg.is_namespace(&name_of_alias) {
+    let tree = format_tree(&name_of_alias, &collect_entries(cfg, &Filter::Namespace(name_of_alias.clone()), &[], SortOrder::Name));
+    return Err(AliasIsNamespace(name_of_alias, tree, name_arg));
+  } else {
+    let suggestions = closest_matches(&name_of_alias, cfg.merged().into_keys().map(|name| (name.to_string(), ())));
+    match unambiguous(&suggestions).filter(|_| cfg.settings().autocorrect) {
+      Some(suggestion) => {
+        pushln!(cfg.notices, "There is no alias {name_of_alias}, so {} is executed instead.", suggestion.name);
+        suggestion.name.clone()
+      }
+      None => return Err(InvalidAliasName(name_of_alias, suggestions.into_iter().map(|suggestion| suggestion.name).collect(), name_arg)),
+    }
+  };
+  let content_of_alias = resolve_references(cfg, &name_of_alias, name_arg)?;
+  let interpreter = if exec { Interpreter::Exec } else { alias_interpreter(cfg, &name_of_alias) };
+  let quote = interpreter.quote();
+  let content_of_alias = expand_placeholders(&content_of_alias, &forwarded_args, quote).map_err(|e| match e {
+    PlaceholderErrorCode::MissingPositionalArgument(placeholder) => MissingPlaceholderArgument(name_of_alias.clone(), placeholder, name_arg),
+  })?;
+  let program = if interpreter == Interpreter::Exec {
+    let words = shell_words::split(&content_of_alias).map_err(|e| InvalidShellWords(name_of_alias.clone(), e, name_arg))?;
+    Program::Words(words)
+  } else {
+    Program::Shell(interpreter, content_of_alias)
+  };
+  let alias = cfg.get(&name_of_alias).ok_or(InvalidAliasName(name_of_alias.clone(), Vec::new(), name_arg))?;
+  let cwd = alias_working_directory(cfg, &name_of_alias);
+  if let Some(cwd) = cwd.as_ref().filter(|cwd| !cwd.is_dir()) {
+    return Err(MissingWorkingDirectory(name_of_alias, cwd.display().to_string(), name_arg));
   }
+  let (env, unset_env) = (alias.env.clone(), alias.unset_env.clone());
+  // The alias is run by run_executions once the rest of the commands turned out to be valid.
+  cfg.executions.push(Execution { name: name_of_alias, program, cwd, env, unset_env, detach, arg: name_arg });
+  Ok(())
+}
+
+/// The interpreter the alias name is set to run with, or the system shell if it sets none.
+fn alias_interpreter(cfg: &AliasStore, name: &str) -> Interpreter {
+  cfg.get(name).and_then(|alias| alias.shell.as_deref()).and_then(Interpreter::parse).unwrap_or_else(Interpreter::system)
+}
+
+/// The directory the alias name is set to run in, resolved against the alias file it came from, or None if it runs in
+/// the current one.
+fn alias_working_directory(cfg: &AliasStore, name: &str) -> Option<PathBuf> {
+  let layer = &cfg.layers[cfg.origin(name)?];
+  layer.aliases[name].cwd.as_deref().map(|cwd| resolve_working_directory(cwd, &layer.path))
+}
+
+/// Whether the aliases a and b run the same way apart from their commands: with the same interpreter, in the same
+/// directory and with the same environment.
+fn same_execution_settings(cfg: &AliasStore, a: &str, b: &str) -> bool {
+  let environment = |name: &str| cfg.get(name).map(|alias| (&alias.env, &alias.unset_env));
+  alias_interpreter(cfg, a) == alias_interpreter(cfg, b) && alias_working_directory(cfg, a) == alias_working_directory(cfg, b) && environment(a) == environment(b)
+}
+
+/// The command of the alias name with the `@name` references in it expanded, which is what gets executed once its
+/// placeholders are filled in. The commands of the referenced aliases are run by the interpreter of name, so they have
+/// to be set to run the same way it is.
+fn resolve_references(cfg: &AliasStore, name: &str, name_arg: usize) -> Result<String, CommandLineArgumentErrorCode> {
+  let alias = cfg.get(name).ok_or(InvalidAliasName(name.to_string(), Vec::new(), name_arg))?;
+  let lookup = |referenced: &str| cfg.get(referenced).map(|alias| alias.command.as_str());
+  expand_references(name, &alias.command, lookup, |referenced| same_execution_settings(cfg, name, referenced)).map_err(|e| InvalidReferences(name.to_string(), e, name_arg))
+}
+
+/// Runs the aliases that were asked to be executed, in order, stopping at the first one that fails. Their usage is
+/// recorded as they are started.
+fn run_executions(cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  for execution in std::mem::take(&mut cfg.executions) {
+    // The use is saved before the alias starts, so it counts even if the alias keeps running or Alia is killed.
+    cfg.usage.record(&execution.name, time::now());
+    if let Err(e) = cfg.usage.save() {
+      debug_info!("Failed to save usage statistics: {}", e);
+    }
+    let child = execution.command().spawn().map_err(|e| FailedExecute(e.to_string(), execution.arg))?;
+    if execution.detach {
+      continue;
+    }
+    match wait_for_child(child) {
+      Ok(Ok(())) => {}
+      Ok(Err(failure)) => return Err(AliasFailed(execution.name, failure, execution.arg)),
+      Err(e) => return Err(FailedExecute(e.to_string(), execution.arg)),
+    }
+  }
+  Ok(())
+}
+
+/// What Alia would do if it was not passed `--dry-run`: what the commands print, how every alias file would change, as
+/// a unified diff, what would be opened in the editor and which aliases would be run.
+fn format_plan(cfg: &AliasStore) -> String {
+  let mut result = String::new();
+  for layer in cfg.layers.iter().filter(|layer| layer.modified) {
+    let label = layer.path.display().to_string();
+    result.push_str(&unified_diff(&layer.source, &config_to_string(&layer.source, &layer.aliases), &label, &label));
+  }
+  if let Some(edit) = &cfg.pending_edit {
+    let path = cfg.layers[edit.layer].path.display();
+    match &edit.name {
+      Some(name) => result.push_str(&format!("Would open {name} from {path} in the editor\n")),
+      None => result.push_str(&format!("Would open {path} in the editor\n")),
+    }
+  }
+  for execution in &cfg.executions {
+    let (command, interpreter) = match &execution.program {
+      Program::Shell(interpreter, script) => (script.clone(), Some(*interpreter)),
+      Program::Words(words) => (words.iter().map(String::as_str).map(shell_words::quote).collect::<Vec<_>>().join(" "), None),
+    };
+    let mut settings = String::new();
+    if let Some(interpreter) = interpreter.filter(|interpreter| *interpreter != Interpreter::system()) {
+      settings.push_str(&format!(" with {}", interpreter.name()));
+    }
+    if let Some(cwd) = &execution.cwd {
+      settings.push_str(&format!(" in {}", cwd.display()));
+    }
+    for variable in &execution.unset_env {
+      settings.push_str(&format!(" without {variable}"));
+    }
+    for (variable, value) in &execution.env {
+      settings.push_str(&format!(" {variable}={}", shell_words::quote(value)));
+    }
+    result.push_str(&format!("Would {} {}{settings}: {command}\n", if execution.detach { "start" } else { "run" }, execution.name));
+  }
+  if result.is_empty() {
+    result.push_str("Nothing would be changed or run.\n");
+  }
+  format!("{}{result}", cfg.output)
+}
+
+fn change_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let selector = parse_layer_flags(args, current_arg)?;
+  let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
+  let layer = select_layer(cfg, &selector, *current_arg)?.or_else(|| cfg.origin(&name_of_alias));
+  let layer = match layer {
+    Some(layer) if cfg.layers[layer].aliases.contains_key(name_of_alias.as_str()) => layer,
+    _ => return Err(AliasDoesNotExist(name_of_alias, *current_arg)),
+  };
   let content_of_alias = get_next_arg(args, current_arg).ok_or(MissingContentArgument(*current_arg))?;
-  cfg.insert(name_of_alias, content_of_alias);
+  // Only the command changes, everything else that is stored about the alias is kept.
+  let mut alias = cfg.layers[layer].aliases[&name_of_alias].clone();
+  alias.command = content_of_alias;
+  cfg.insert(layer, name_of_alias, alias);
   Ok(())
 }
 
-fn parse_config(config_as_string: String) -> Result<HashMap<String, String>, ConfigParseErrorCode> {
-  let slice = &mut config_as_string.trim();
-  let mut result = HashMap::with_capacity(config_as_string.lines().count());
-  let mut current_alias: usize = 1;
-  while slice.len() > 0 {
-    let alias = parse_string(slice);
-    if let Err(e) = alias {
-      return Err(InvalidAlias(e, current_alias));
+/// Opens the alias named by the next arg in the editor, or a template if there is no such alias yet. Without a name
+/// the whole alias file is opened instead.
+/// Checks what edit asks to open in the editor. The editor is only opened once the invocation has been checked, and a
+/// batch cannot take back what was done in it, so edit has to be the only command.
+fn edit_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let command_arg = *current_arg;
+  let anything_before = cfg.layers.iter().any(|layer| layer.modified) || !cfg.executions.is_empty() || !cfg.output.is_empty() || !cfg.pending_removals.is_empty() || cfg.pending_edit.is_some();
+  if anything_before {
+    return Err(EditNotAlone(command_arg));
+  }
+  let selector = parse_layer_flags(args, current_arg)?;
+  let name_of_alias = args.next_if(|arg| !is_command(arg));
+  if name_of_alias.is_some() {
+    *current_arg += 1;
+  }
+  let layer = select_layer(cfg, &selector, *current_arg)?.or_else(|| name_of_alias.as_ref().and_then(|name| cfg.origin(name))).unwrap_or(0);
+  if let Some(name) = name_of_alias.as_ref().filter(|name| !cfg.layers[layer].aliases.contains_key(*name) && !is_valid_alias_name(name)) {
+    return Err(MalformedAliasName(name.clone(), *current_arg));
+  }
+  if args.peek().is_some() {
+    return Err(EditNotAlone(command_arg));
+  }
+  cfg.pending_edit = Some(PendingEdit { layer, name: name_of_alias, arg: *current_arg });
+  Ok(())
+}
+
+/// An edit that was made in the editor, to be saved once it is known that the alias file was not changed in the
+/// meantime. Until then it is kept in the temporary file at path.
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Edit {
+  pending: PendingEdit,
+  /// The new command of the alias, or the new contents of the whole file.
+  text: String,
+  path: PathBuf,
+}
+
+/// Opens the editor for the pending edit of cfg. Returns None if nothing was changed.
+fn open_editor(cfg: &AliasStore, pending: &PendingEdit, editor: &str) -> Result<Option<Edit>, CommandLineArgumentErrorCode> {
+  match &pending.name {
+    Some(name) => edit_single_alias(cfg, pending, name, editor),
+    None => edit_config_file(cfg, pending, editor),
+  }
+}
+
+/// Lets the command of the alias name be edited in editor.
+fn edit_single_alias(cfg: &AliasStore, pending: &PendingEdit, name: &str, editor: &str) -> Result<Option<Edit>, CommandLineArgumentErrorCode> {
+  let existing = cfg.layers[pending.layer].aliases.get(name);
+  let text = match existing {
+    Some(alias) => format!("{}\n", alias.command),
+    None => alias_template(name),
+  };
+  let path = create_temp_file(&format!("{name}.sh"), &text).map_err(|e| TemporaryFileFailed(e.to_string(), pending.arg))?;
+  let command = edit_until_valid(editor, &path, pending.arg, |text| {
+    let command = edited_command(text, name);
+    // Leaving a new alias empty cancels, but an alias that exists has to keep a command.
+    if command.is_empty() && existing.is_some() {
+      return Err((string_parse_error_to_string(&EmptyString), None));
     }
-    let alias = unsafe { alias.unwrap_unchecked() };
-    *slice = slice.trim_start();
-    let next = slice.pop_char();
-    if let None = next {
-      return Err(MissingAliasValue(current_alias));
+    Ok(command)
+  })?;
+  if command.is_empty() || existing.is_some_and(|alias| alias.command == command) {
+    if command.is_empty() {
+      eprintln!("Nothing was saved, as the alias {name} was left empty.");
+    }
+    let _ = fs::remove_file(&path);
+    return Ok(None);
+  }
+  Ok(Some(Edit { pending: pending.clone(), text: command, path }))
+}
+
+/// Lets a whole alias file be edited in editor. The result is only accepted once it parses without problems.
+fn edit_config_file(cfg: &AliasStore, pending: &PendingEdit, editor: &str) -> Result<Option<Edit>, CommandLineArgumentErrorCode> {
+  let layer = &cfg.layers[pending.layer];
+  let original = match layer.source.as_str() {
+    "" => config_to_string("", &layer.aliases),
+    source => source.to_string(),
+  };
+  let file_name = layer.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+  let path = create_temp_file(&file_name, &original).map_err(|e| TemporaryFileFailed(e.to_string(), pending.arg))?;
+  let source = edit_until_valid(editor, &path, pending.arg, |text| {
+    let parsed = parse_document(text);
+    let problems: Vec<Diagnostic> = parsed.errors.into_iter().map(|e| config_parse_error_diagnostic(&InvalidDocument(e), &path, text)).collect();
+    match problems.first() {
+      Some(problem) => Err((problems.iter().map(|problem| problem.to_string()).collect::<Vec<_>>().join(END_OF_LINE_SEQUENCE), problem.snippet.as_ref().map(|snippet| snippet.line))),
+      None => Ok(text.to_string()),
+    }
+  })?;
+  if source == original {
+    let _ = fs::remove_file(&path);
+    return Ok(None);
+  }
+  Ok(Some(Edit { pending: pending.clone(), text: source, path }))
+}
+
+/// Saves edit to the alias file it was made in, which held original when the editor was opened. The file is locked
+/// and read again first, and if another process changed it in the meantime, the edit is kept in its temporary file
+/// instead of overwriting those changes.
+fn apply_edit(cfg: &mut AliasStore, edit: Edit, original: &str) -> Result<(), CommandLineArgumentErrorCode> {
+  let Edit { pending, text, path } = edit;
+  let layer_path = cfg.layers[pending.layer].path.clone();
+  lock_store_file(&layer_path).map_err(|e| InvalidStoreFile(layer_path.display().to_string(), ConfigCouldNotBeLocked(e), pending.arg))?;
+  let current = match fs::read_to_string(&layer_path) {
+    Ok(contents) => contents,
+    Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+    Err(e) => return Err(InvalidStoreFile(layer_path.display().to_string(), ConfigCouldNotBeRead(e.to_string()), pending.arg)),
+  };
+  if current != original {
+    return Err(EditConflict(layer_path.display().to_string(), path.display().to_string(), pending.arg));
+  }
+  match pending.name {
+    Some(name) => {
+      let alias = match cfg.layers[pending.layer].aliases.get(&name) {
+        Some(alias) => Alias { command: text, ..alias.clone() },
+        None => Alias::new(text),
+      };
+      cfg.insert(pending.layer, name, alias);
+    }
+    None => {
+      let parsed = parse_document(&text);
+      cfg.replace_aliases(pending.layer, parsed.aliases);
+      let layer = &mut cfg.layers[pending.layer];
+      layer.source = text;
+      layer.settings = parsed.settings;
+      layer.problems.clear();
+      layer.broken = false;
+    }
+  }
+  let _ = fs::remove_file(&path);
+  Ok(())
+}
+
+/// Opens the file at path in editor until validate accepts what it holds, and returns what validate made of it. When
+/// validate rejects it, the problems it returns are shown and the editor is offered again at the line it points to.
+/// The file is kept if that is declined, and also once it is accepted, so the edit is not lost before it is saved.
+fn edit_until_valid<R>(editor: &str, path: &Path, current_arg: usize, validate: impl Fn(&str) -> Result<R, (String, Option<usize>)>) -> Result<R, CommandLineArgumentErrorCode> {
+  let mut line = None;
+  loop {
+    if let Err(e) = edit_file(editor, path, line) {
+      let _ = fs::remove_file(path);
+      return Err(EditorFailed(editor.to_string(), e, current_arg));
+    }
+    let text = fs::read_to_string(path).map_err(|e| TemporaryFileFailed(e.to_string(), current_arg))?;
+    match validate(&text) {
+      Ok(result) => return Ok(result),
+      Err((problems, problem_line)) => {
+        eprintln!("{problems}");
+        let question = match problem_line {
+          Some(problem_line) => format!("Open the editor again at line {problem_line}?"),
+          None => "Open the editor again?".to_string(),
+        };
+        if !confirm(&question, true) {
+          return Err(EditNotSaved(path.display().to_string(), current_arg));
+        }
+        line = problem_line;
+      }
+    }
+  }
+}
+
+/// Reads the optional count that may follow undo, redo and history.
+fn get_count<T: Iterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize) -> Option<usize> {
+  let count = args.next_if(|arg| arg.parse::<usize>().is_ok())?;
+  *current_arg += 1;
+  count.parse().ok()
+}
+
+/// Undoes the last change recorded in the journal, or the last n if a count is given.
+fn undo_changes<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let count = get_count(args, current_arg).unwrap_or(1);
+  // Changes made earlier in this invocation can be undone as well.
+  cfg.journal.commit(time::now());
+  if cfg.journal.stacks().0.len() < count {
+    return Err(NothingToUndo(*current_arg));
+  }
+  for _ in 0..count {
+    let id = *cfg.journal.stacks().0.last().ok_or(NothingToUndo(*current_arg))?;
+    replay_entry(cfg, id, true, *current_arg)?;
+    cfg.journal.push(EntryKind::Undo(id), time::now());
+  }
+  Ok(())
+}
+
+/// Makes the last undone change again, or the last n if a count is given.
+fn redo_changes<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let count = get_count(args, current_arg).unwrap_or(1);
+  cfg.journal.commit(time::now());
+  if cfg.journal.stacks().1.len() < count {
+    return Err(NothingToRedo(*current_arg));
+  }
+  for _ in 0..count {
+    let id = *cfg.journal.stacks().1.last().ok_or(NothingToRedo(*current_arg))?;
+    replay_entry(cfg, id, false, *current_arg)?;
+    cfg.journal.push(EntryKind::Redo(id), time::now());
+  }
+  Ok(())
+}
+
+/// Reverts the changes of the journal entry with the given id if undo is set, and makes them again otherwise. Every
+/// alias has to be as the entry left it, or as it found it when redoing, so changes made since are never overwritten.
+fn replay_entry(cfg: &mut AliasStore, id: u64, undo: bool, current_arg: usize) -> Result<(), CommandLineArgumentErrorCode> {
+  let Some(EntryKind::Changes(command, changes)) = cfg.journal.find(id).map(|entry| entry.kind.clone()) else {
+    return Ok(());
+  };
+  let ordered: Box<dyn Iterator<Item = &Change>> = if undo { Box::new(changes.iter().rev()) } else { Box::new(changes.iter()) };
+  for change in ordered {
+    let (expected, new) = if undo { (&change.after, &change.before) } else { (&change.before, &change.after) };
+    let layer = load_layer(cfg, change.path.clone(), LayerKind::File, current_arg)?;
+    if cfg.layers[layer].aliases.get(&change.name) != expected.as_ref() {
+      return Err(HistoryConflict(id, change.name.clone(), change.path.display().to_string(), current_arg));
+    }
+    match new {
+      Some(alias) => cfg.insert(layer, change.name.clone(), alias.clone()),
+      None => { cfg.remove(layer, &change.name); }
+    }
+  }
+  // The journal records this as an undo or redo of the entry rather than as changes of their own.
+  cfg.journal.pending.clear();
+  pushln!(cfg.notices, "{} #{id}: {command}", if undo { "Undid" } else { "Redid" });
+  Ok(())
+}
+
+/// Lists the backups of the global store, restores one of them, or shows how one differs from the store.
+fn manage_backups<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let subcommand = get_next_arg(args, current_arg).ok_or(MissingBackupCommand(*current_arg))?;
+  if !matches!(subcommand.as_str(), "list" | "restore" | "diff") {
+    return Err(InvalidBackupCommand(subcommand, *current_arg));
+  }
+  let global = cfg.layers.iter().position(|layer| layer.kind == LayerKind::Global).unwrap_or(0);
+  let config_path = cfg.layers[global].path.clone();
+  if subcommand == "list" {
+    let backups = list_backups(&config_path).map_err(|e| BackupFailed(e.to_string(), *current_arg))?;
+    if backups.is_empty() {
+      pushln!(cfg.output, "No backups of {} have been made yet. One is made every time it is changed.", config_path.display());
+    }
+    for backup in backups {
+      match fs::read_to_string(&backup.path) {
+        Ok(contents) => {
+          let count = parse_config(&backup.path, LayerKind::File, &contents).aliases.len();
+          pushln!(cfg.output, "{}  {count} alias{}", backup.id, if count == 1 { "" } else { "es" });
+        }
+        Err(e) => { pushln!(cfg.output, "{}  could not be read: {e}", backup.id); }
+      }
+    }
+    return Ok(());
+  }
+  let id = get_next_arg(args, current_arg).ok_or(MissingBackupId(*current_arg))?;
+  let backup = find_backup(&config_path, &id).map_err(|e| BackupFailed(e.to_string(), *current_arg))?.ok_or(BackupDoesNotExist(id.clone(), *current_arg))?;
+  let contents = fs::read_to_string(&backup.path).map_err(|e| BackupFailed(e.to_string(), *current_arg))?;
+  let current = config_to_string(&cfg.layers[global].source, &cfg.layers[global].aliases);
+  if subcommand == "diff" {
+    let diff = unified_diff(&current, &contents, &config_path.display().to_string(), &backup.path.display().to_string());
+    if diff.is_empty() {
+      pushln!(cfg.output, "The backup {id} is the same as {}.", config_path.display());
+    }
+    cfg.output.push_str(&diff);
+    return Ok(());
+  }
+  // The backup is parsed as the store would be, so one that was edited by hand or cut short cannot replace it.
+  let restored = parse_config(&backup.path, LayerKind::Global, &contents);
+  if !restored.problems.is_empty() {
+    for problem in &restored.problems {
+      pushln!(cfg.notices, "{}", problem.render("error"));
+    }
+    return Err(InvalidBackup(id, restored.problems.len(), *current_arg));
+  }
+  if restored.source == current {
+    pushln!(cfg.notices, "{} is the same as the backup {id} already.", config_path.display());
+    return Ok(());
+  }
+  cfg.replace_aliases(global, restored.aliases);
+  let layer = &mut cfg.layers[global];
+  layer.source = restored.source;
+  layer.settings = restored.settings;
+  layer.problems.clear();
+  layer.broken = false;
+  pushln!(cfg.notices, "Restored {} from the backup {id}.", config_path.display());
+  Ok(())
+}
+
+/// Prints the entries of the journal, newest first. Only the last 20 are shown unless a count is given.
+fn show_history<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let count = get_count(args, current_arg).unwrap_or(20);
+  cfg.journal.commit(time::now());
+  let history = format_history(&cfg.journal, count);
+  cfg.output.push_str(&history);
+  Ok(())
+}
+
+fn list_aliases<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let listing = format_listing(args, current_arg, cfg)?;
+  cfg.output.push_str(&listing);
+  Ok(())
+}
+
+/// Formats the aliases the args of list ask for, in the way they ask for.
+fn format_listing<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &AliasStore) -> Result<String, CommandLineArgumentErrorCode> {
+  let mut show_origin = false;
+  let mut json = false;
+  let mut sort = SortOrder::default();
+  let mut filter = Filter::default();
+  let mut tags = Vec::new();
+  loop {
+    if let Some(flag) = get_next_flag(args, current_arg) {
+      match flag.as_str() {
+        "--origin" => show_origin = true,
+        "--tag" => tags.push(get_tag(&flag, args, current_arg)?),
+        "--json" => json = true,
+        "--sort" => {
+          let value = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.clone(), *current_arg))?;
+          sort = SortOrder::parse(&value).ok_or(InvalidFlagValue(flag, value, *current_arg))?;
+        }
+        "--regex" => {
+          let value = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag, *current_arg))?;
+          filter = Filter::Regex(Regex::new(&value).map_err(|e| InvalidRegex(value.clone(), e.to_string(), *current_arg))?);
+        }
+        _ => return Err(UnknownFlag(flag, *current_arg)),
+      }
+      continue;
+    }
+    // The pattern is optional, so an arg that names a command is taken to be the next command instead.
+    if let Filter::All = filter {
+      if let Some(pattern) = args.next_if(|arg| !is_command(arg)) {
+        *current_arg += 1;
+        filter = Filter::Glob(pattern);
+        continue;
+      }
     }
-    if next != Some('=') {
-      return Err(MissingEqualSign(current_alias));
+    break;
+  }
+  // A pattern without wildcards that names a namespace lists the aliases in it.
+  if let Filter::Glob(pattern) = &filter {
+    if !pattern.contains(['*', '?', '[']) && cfg.is_namespace(pattern) {
+      filter = Filter::Namespace(pattern.clone());
     }
-    let value = parse_string(slice);
-    if let Err(e) = value {
-      return Err(InvalidValue(e, current_alias));
+  }
+  let entries = collect_entries(cfg, &filter, &tags, sort);
+  Ok(match &filter {
+    _ if json => format_json(&entries),
+    Filter::Namespace(namespace) if !show_origin && !entries.is_empty() => format_tree(namespace, &entries),
+    _ => format_table(&entries, show_origin),
+  })
+}
+
+fn show_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut json = false;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    match flag.as_str() {
+      "--json" => json = true,
+      _ => return Err(UnknownFlag(flag, *current_arg)),
     }
-    let value = unsafe { value.unwrap_unchecked() };
-    result.insert(alias, value);
-    *slice = &slice.trim_start();
-    current_alias += 1;
   }
-  Ok(result)
+  let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
+  let entry = get_entry(cfg, &name_of_alias).ok_or(CannotShowNonExistentAlias(name_of_alias.clone(), *current_arg))?;
+  if json {
+    pushln!(cfg.output, "{}", entry_to_json(&entry));
+  } else {
+    cfg.output.push_str(&format_details(&entry));
+  }
+  Ok(())
+}
+
+/// Prints the command the alias named by the next arg would execute, with the aliases it references expanded, without
+/// running it. Args after the name fill in its placeholders like they do for execute. Without any they are left as
+/// they are.
+fn expand_alias<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let name_of_alias = get_next_arg(args, current_arg).ok_or(MissingNameArgument(*current_arg))?;
+  let name_arg = *current_arg;
+  let forwarded_args = get_forwarded_args(args, current_arg);
+  if cfg.get(&name_of_alias).is_none() {
+    return Err(CannotShowNonExistentAlias(name_of_alias, name_arg));
+  }
+  let mut command = resolve_references(cfg, &name_of_alias, name_arg)?;
+  if !forwarded_args.is_empty() {
+    command = expand_placeholders(&command, &forwarded_args, alias_interpreter(cfg, &name_of_alias).quote()).map_err(|e| match e {
+      PlaceholderErrorCode::MissingPositionalArgument(placeholder) => MissingPlaceholderArgument(name_of_alias.clone(), placeholder, name_arg),
+    })?;
+  }
+  pushln!(cfg.output, "{command}");
+  Ok(())
 }
 
-fn read_from_config_file() -> Result<HashMap<String, String>, ConfigParseErrorCode> {
-  let contents = fs::read_to_string(PATH_TO_CONFIG);
-  if let Err(_) = contents {
-    if let Err(e) = File::create(PATH_TO_CONFIG) {
-      return Err(ConfigCouldNotBeCreated(e.to_string()));
+fn print_config_path<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut all = false;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    match flag.as_str() {
+      "--all" => all = true,
+      _ => return Err(UnknownFlag(flag, *current_arg)),
+    }
+  }
+  for layer in &cfg.layers {
+    if all {
+      pushln!(cfg.output, "{}: {}", layer.kind, layer.path.display());
+    } else if layer.kind == LayerKind::Global {
+      pushln!(cfg.output, "{}", layer.path.display());
     }
-    return Err(ConfigNotFound);
   }
-  parse_config(unsafe { contents.unwrap_unchecked() })
+  Ok(())
+}
+
+/// Lists every problem in the alias files that are in effect, including the entries that were skipped because of them.
+fn diagnose_config<T: ExactSizeIterator<Item = String>>(_args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut count = 0;
+  let mut report = String::new();
+  for layer in &cfg.layers {
+    match layer.problems.len() {
+      0 => { pushln!(report, "{}: no problems found", layer.path.display()); }
+      1 => { pushln!(report, "{}: 1 problem", layer.path.display()); }
+      n => { pushln!(report, "{}: {n} problems", layer.path.display()); }
+    }
+    for problem in &layer.problems {
+      pushln!(report, "{problem}");
+    }
+    if layer.broken {
+      pushln!(report, "Parts of this file could not be read at all, so Alia will not save changes to it until they are fixed.");
+    }
+    count += layer.problems.len();
+  }
+  if count > 0 {
+    return Err(ProblemsFound(report, count, *current_arg));
+  }
+  cfg.output.push_str(&report);
+  Ok(())
+}
+
+fn print_init_script<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let name_of_shell = get_next_arg(args, current_arg).ok_or(MissingShellArgument(*current_arg))?;
+  let shell = Shell::parse(&name_of_shell).ok_or(UnsupportedShell(name_of_shell.clone(), *current_arg))?;
+  // The functions call back into this very binary, so they keep working even if Alia is not on the PATH.
+  let exe = env::current_exe().map(|path| path.display().to_string()).unwrap_or_else(|_| "alia".to_string());
+  let script = init_script(shell, &exe, cfg).ok_or(UnsupportedShell(name_of_shell, *current_arg))?;
+  cfg.output.push_str(&script);
+  Ok(())
+}
+
+fn print_completion_script<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let name_of_shell = get_next_arg(args, current_arg).ok_or(MissingShellArgument(*current_arg))?;
+  let shell = Shell::parse(&name_of_shell).ok_or(UnsupportedShell(name_of_shell, *current_arg))?;
+  cfg.output.push_str(&completion_script(shell));
+  Ok(())
+}
+
+/// Prints what the word being completed can be completed with, one candidate per line. The words typed after `alia`
+/// come after the `--` that ends the flags. Unless `--position <n>` says otherwise, the last of them is the one being completed.
+fn print_completions<T: ExactSizeIterator<Item = String>>(args: &mut Peekable<T>, current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let mut position = None;
+  while let Some(flag) = get_next_flag(args, current_arg) {
+    match flag.as_str() {
+      "--position" => {
+        let value = get_next_arg(args, current_arg).ok_or(MissingFlagValue(flag.clone(), *current_arg))?;
+        position = Some(value.parse::<usize>().map_err(|_| InvalidFlagValue(flag, value, *current_arg))?);
+      }
+      _ => return Err(UnknownFlag(flag, *current_arg)),
+    }
+  }
+  let mut words: Vec<String> = args.by_ref().collect();
+  *current_arg += words.len();
+  if let Some(position) = position {
+    words.resize(position + 1, String::new());
+  }
+  for candidate in complete(cfg, &words) {
+    pushln!(cfg.output, "{}", candidate);
+  }
+  Ok(())
+}
+
+fn print_fingerprint<T: ExactSizeIterator<Item = String>>(_args: &mut Peekable<T>, _current_arg: &mut usize, cfg: &mut AliasStore) -> Result<(), CommandLineArgumentErrorCode> {
+  let fingerprint = fingerprint(cfg);
+  pushln!(cfg.output, "{}", fingerprint);
+  Ok(())
+}
+
+/// What parse_legacy_config could make of an alias file in the legacy format.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+struct LegacyConfig {
+  entries: Vec<LegacyEntry>,
+  /// The comments at the end of the file.
+  trailing_comments: Vec<String>,
+  /// Every problem that was found, in the order they appear in. The entries they were found in are skipped.
+  errors: Vec<ConfigParseErrorCode>,
+}
+
+/// Parses an alias file in the legacy format into its entries, in the order they appear in, and the comments at the
+/// end of the file. A `#` starts a comment that runs to the end of the line, wherever an alias name could start. An
+/// entry that cannot be parsed is skipped, and parsing carries on at the line after the one it started on.
+fn parse_legacy_config(config_as_string: &str) -> LegacyConfig {
+  let slice = &mut config_as_string.trim_end();
+  // slice always ends where the trimmed config does, so its length tells how far into the config it starts.
+  let end_of_config = slice.len();
+  let offset = |slice: &str| end_of_config - slice.len();
+  *slice = slice.trim_start();
+  let mut result = LegacyConfig { entries: Vec::with_capacity(config_as_string.lines().count()), ..Default::default() };
+  let mut comments = Vec::new();
+  loop {
+    while slice.starts_with('#') {
+      comments.push(pop_line(slice).trim_end().to_string());
+      *slice = slice.trim_start();
+    }
+    if slice.is_empty() {
+      break;
+    }
+    let start_of_entry = *slice;
+    let (alias, value) = match parse_legacy_entry(slice, offset) {
+      Ok(entry) => entry,
+      Err(e) => {
+        result.errors.push(e);
+        *slice = start_of_entry;
+        pop_line(slice);
+        *slice = slice.trim_start();
+        comments.clear();
+        continue;
+      }
+    };
+    let rest_of_line = slice.split_once('\n').map_or(*slice, |(line, _)| line);
+    let trailing_comment = if rest_of_line.trim_start().starts_with('#') { Some(pop_line(slice).trim().to_string()) } else { None };
+    result.entries.push(LegacyEntry { comments: std::mem::take(&mut comments), name: alias, value, trailing_comment });
+    *slice = slice.trim_start();
+  }
+  result.trailing_comments = comments;
+  result
+}
+
+/// Parses the name and value of a single entry of an alias file in the legacy format from the start of slice.
+fn parse_legacy_entry(slice: &mut &str, offset: impl Fn(&str) -> usize + Copy) -> Result<(String, String), ConfigParseErrorCode> {
+  let alias = parse_located_string(slice, offset, parse_alias_name).map_err(|(e, span)| InvalidAlias(e, span))?;
+  *slice = slice.trim_start();
+  let equal_sign = offset(slice);
+  let next = slice.pop_char();
+  if next.is_none() {
+    return Err(MissingAliasValue(Span::new(equal_sign, equal_sign)));
+  }
+  if next != Some('=') {
+    return Err(MissingEqualSign(Span::new(equal_sign, equal_sign + next.map_or(0, char::len_utf8))));
+  }
+  if slice.trim_start().is_empty() {
+    return Err(MissingAliasValue(Span::new(equal_sign, equal_sign + 1)));
+  }
+  let value = parse_located_string(slice, offset, parse_string).map_err(|(e, span)| InvalidValue(e, span))?;
+  Ok((alias, value))
+}
+
+/// Parses a quoted string from the start of slice with parse, which is parse_string or parse_alias_name. If that fails,
+/// returns the span of the part of the string that is wrong as well, using offset to work out where slice is in the
+/// whole config.
+fn parse_located_string(slice: &mut &str, offset: impl Fn(&str) -> usize, parse: fn(&mut &str) -> Result<String, StringParseErrorCode>) -> Result<String, (StringParseErrorCode, Span)> {
+  *slice = slice.trim_start();
+  let start = offset(slice);
+  let rest = *slice;
+  parse(slice).map_err(|e| {
+    let span = match &e {
+      // Point at the whole word that should have been quoted.
+      StringWithoutOpeningQuote => Span::new(start, start + rest.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(rest.len()).max(1)),
+      EmptyString => Span::new(start, start + 2),
+      // The escape is right before where parsing stopped, and `\u` is two bytes long.
+      InvalidUnicodeEscape(text) => Span::new(offset(slice) - 2, offset(slice) + text.len()),
+      InvalidString | StringWithoutClosingQuote => Span::new(start, start + 1),
+      // The whole name has been parsed by now.
+      InvalidNameSegment(_) => Span::new(start, offset(slice)),
+    };
+    (e, span)
+  })
+}
+
+/// Pops everything up to the end of the line from the string and returns it, without the line break.
+fn pop_line<'a>(slice: &mut &'a str) -> &'a str {
+  let whole: &'a str = slice;
+  let (line, rest) = whole.split_once('\n').unwrap_or((whole, ""));
+  *slice = rest;
+  line
+}
+
+/// Reads the alias file at path, after locking it if this invocation may change it. Entries with problems are skipped
+/// and the problems are kept in the layer, unless strict is set, in which case the first problem is returned instead.
+/// A file that does not exist yet is read as one without any aliases, and is only created once an alias is added.
+fn read_from_config_file(path: &Path, kind: LayerKind, strict: bool) -> Result<Layer, ConfigParseErrorCode> {
+  lock_store_file(path).map_err(ConfigCouldNotBeLocked)?;
+  let contents = match fs::read_to_string(path) {
+    Ok(contents) => contents,
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Layer::new(path.to_path_buf(), kind, HashMap::new())),
+    Err(e) => return Err(ConfigCouldNotBeRead(e.to_string())),
+  };
+  let layer = parse_config(path, kind, &contents);
+  if strict {
+    if let Some(problem) = layer.problems.first() {
+      return Err(InvalidConfigFile(Box::new(problem.clone())));
+    }
+  }
+  // A file with problems is left as it is, as migrating it would lose the entries that were skipped. The file may have
+  // been read without a lock, so it is only migrated if it still holds what was read once it is locked.
+  if is_legacy_format(&contents) && layer.problems.is_empty() {
+    with_lock(path, || match fs::read_to_string(path) {
+      Ok(current) if current == contents => migrate_config_file(path, &layer.source),
+      _ => Ok(()),
+    }).map_err(ConfigCouldNotBeLocked)??;
+  }
+  Ok(layer)
+}
+
+/// Parses contents, read from the alias file at path, into a layer. Entries with problems are skipped and the problems
+/// are kept in the layer. The source of a file in the legacy format without problems is what it is migrated to.
+fn parse_config(path: &Path, kind: LayerKind, contents: &str) -> Layer {
+  let (mut layer, errors, broken) = if is_legacy_format(contents) {
+    let legacy = parse_legacy_config(contents);
+    let aliases = legacy.entries.iter().map(|entry| (entry.name.clone(), Alias::new(entry.value.clone()))).collect();
+    let mut layer = Layer::new(path.to_path_buf(), kind, aliases);
+    if legacy.errors.is_empty() {
+      layer.source = legacy_to_document(&legacy.entries, &legacy.trailing_comments).to_string();
+      return layer;
+    }
+    (layer, legacy.errors, true)
+  } else {
+    let parsed = parse_document(contents);
+    let mut layer = Layer::new(path.to_path_buf(), kind, parsed.aliases);
+    layer.settings = parsed.settings;
+    (layer, parsed.errors.into_iter().map(InvalidDocument).collect(), parsed.broken)
+  };
+  layer.problems = errors.iter().map(|e| config_parse_error_diagnostic(e, path, contents)).collect();
+  layer.broken = broken;
+  layer.source = contents.to_string();
+  layer
+}
+
+/// Greets someone running Alia before any alias was added, and points them at the commands to get started with.
+fn print_welcome(path: &Path) {
+  eprintln!("Welcome to Alia! You have no aliases yet. They will be stored in {} once you add one.", path.display());
+  eprintln!("Add one with `alia add <name> <command>`, set up your shell with `alia init <shell>`, or see `alia help` for everything else.");
+}
+
+/// Warns about every problem in the alias file of layer. The entries the problems are in were skipped.
+fn print_problems(layer: &Layer) {
+  for problem in &layer.problems {
+    eprintln!("{}", problem.render("warning"));
+  }
+  if !layer.problems.is_empty() {
+    eprintln!("The entries of {} with problems were skipped. Run `alia doctor` to list the problems, or pass --strict to stop at the first one.", layer.path.display());
+  }
+}
+
+/// Replaces the legacy alias file at path with migrated, the same aliases in the versioned format, after copying the
+/// original to a backup file next to it.
+fn migrate_config_file(path: &Path, migrated: &str) -> Result<(), ConfigParseErrorCode> {
+  let backup_path = legacy_backup_path(path);
+  fs::copy(path, &backup_path).map_err(|e| ConfigCouldNotBeMigrated(e.to_string()))?;
+  write_atomically(path, migrated.as_bytes()).map_err(|e| ConfigCouldNotBeMigrated(e.to_string()))?;
+  // This goes to stderr so it does not end up in the output of the scripts generated by init and completions.
+  eprintln!("Upgraded {} to the new alias file format. The original was saved as {}.", path.display(), backup_path.display());
+  Ok(())
 }
 
-fn config_to_string(cfg: &HashMap<String, String>) -> String {
-  let mut result_string = String::with_capacity(cfg.len() * 50);
-  for thing in cfg {
-    result_string.push('"');
-    result_string.push_str(thing.0);
-    result_string.push_str("\" = \"");
-    result_string.push_str(thing.1);
-    result_string.push('"');
-    result_string.push_str(END_OF_LINE_SEQUENCE);
+/// The first of `<path>.v0.bak`, `<path>.v0.bak.1`, `<path>.v0.bak.2` and so on that does not exist yet, so an older
+/// backup is never overwritten.
+fn legacy_backup_path(path: &Path) -> PathBuf {
+  let mut backup_path = path.as_os_str().to_owned();
+  backup_path.push(".v0.bak");
+  let backup_path = PathBuf::from(backup_path);
+  let mut candidate = backup_path.clone();
+  let mut number = 0;
+  while candidate.exists() {
+    number += 1;
+    let mut numbered = backup_path.clone().into_os_string();
+    numbered.push(format!(".{number}"));
+    candidate = numbered.into();
   }
-  result_string
+  candidate
+}
+
+/// Renders cfg as the new contents of the alias file whose current contents are source. Only the entries that changed
+/// are touched, so comments, ordering and spacing are kept.
+fn config_to_string(source: &str, cfg: &HashMap<String, Alias>) -> String {
+  update_document(source, cfg)
 }
 
-fn write_to_config_file(cfg: &HashMap<String, String>) -> Result<(), ()> {
-  let result_string = config_to_string(cfg);
-  match fs::write("./cfg.alia", result_string) {
-    Ok(()) => Ok(()),
-    Err(_) => Err(()),
+/// Writes the aliases of layer to its file, creating the directories it is in if they do not exist yet.
+fn write_to_config_file(layer: &Layer) -> std::io::Result<()> {
+  if let Some(directory) = layer.path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+    fs::create_dir_all(directory)?;
   }
+  let result_string = config_to_string(&layer.source, &layer.aliases);
+  write_atomically(&layer.path, result_string.as_bytes())
 }
 
 fn parse_string(slice: &mut &str) -> Result<String, StringParseErrorCode> {
   parse_string_track_lines(slice, &mut 0)
 }
 
+/// Parses a quoted alias name like parse_string does, rejecting names with an empty namespace segment.
+fn parse_alias_name(slice: &mut &str) -> Result<String, StringParseErrorCode> {
+  let name = parse_string(slice)?;
+  if !is_valid_alias_name(&name) {
+    return Err(InvalidNameSegment(name));
+  }
+  Ok(name)
+}
+
+/// Parses a quoted string from the start of slice and pops it. Inside the quotes `\"`, `\\`, `\n`, `\t`, `\r`, `\0`
+/// and `\u{...}` with one to six hex digits are understood. A backslash before anything else is kept as it is, since
+/// files written by older versions of Alia did not escape backslashes.
 fn parse_string_track_lines(slice: &mut &str, current_line: &mut u32) -> Result<String, StringParseErrorCode> {
   *slice = slice.trim_start();
   if slice.pop_char() != Some('"') {
     return Err(StringWithoutOpeningQuote);
   }
-  let ret_slice: &str = *slice;
-  let current_char = slice.chars().next();
-  if let None = current_char {
+  if slice.is_empty() {
     return Err(InvalidString);
   }
-  let mut current_char = unsafe { current_char.unwrap_unchecked() };
-  let mut index = 1;
-  let mut backslashes_in_a_row: usize = 0;
+  let mut ret = String::new();
   loop {
+    let current_char = slice.pop_char().ok_or(StringWithoutClosingQuote)?;
     if current_char == '\n' {
       *current_line += 1;
     }
-    if current_char == '"' && backslashes_in_a_row % 2 == 0 {
-      slice.pop_char();
-
-      break;
-    }
-    if current_char == '\\' {
-      backslashes_in_a_row += 1;
-    } else {
-      backslashes_in_a_row = 0;
-    }
-    slice.pop_char();
-    let current_char_option = slice.chars().next();
-    if let None = current_char_option {
-      return Err(StringWithoutClosingQuote);
-    }
-    current_char = unsafe { current_char_option.unwrap_unchecked() };
-    index += 1;
-  }
-  let mut ret = String::with_capacity(index - 1);
-  let mut iter = ret_slice.chars();
-  let mut last_char = '\0';
-
-  for _ in 0..index - 1 {
-    let char = unsafe { iter.next().unwrap_unchecked() };
-    if last_char == '\\' && char == '"' {
-      ret.pop();
+    match current_char {
+      '"' => break,
+      '\\' => parse_escape_sequence(slice, &mut ret, current_line)?,
+      _ => ret.push(current_char),
     }
-    ret.push(char);
-    last_char = char;
   }
-
-  if ret.len() == 0 {
+  if ret.is_empty() {
     return Err(EmptyString);
   }
-  ret.shrink_to_fit();
   Ok(ret)
 }
 
-fn display_help_message() {
-  printlnln!("Available commands are:");
-  println!("a add ---- Add an alias to Alia ---- Takes name of the alias and the content of the alias as arguments");
-  printlnln!("Example usage: alia --add run_release \"cargo run --release\"");
-  println!("r remove ---- Remove an alias from Alia ---- Takes the name of the alias to remove as an argument");
-  printlnln!("Example usage: alia --remove run_release");
-  println!("c change ---- changes an alias in Alia ---- Takes the name of the alias and the new content as arguments");
-  printlnln!("Example usage: alia --change my_alias \"echo test\"");
-  println!("e execute ---- Executes the given alias ---- Takes the name of the alias to execute as an argument");
-  printlnln!("Example usage: alia --execute my_alias");
-  println!("h help ---- Displays this message");
-  printlnln!("Example usage: alia --help");
+/// Parses what follows a backslash in a quoted string and pushes the char it stands for onto ret.
+fn parse_escape_sequence(slice: &mut &str, ret: &mut String, current_line: &mut u32) -> Result<(), StringParseErrorCode> {
+  let escaped = slice.pop_char().ok_or(StringWithoutClosingQuote)?;
+  match escaped {
+    '"' => ret.push('"'),
+    '\\' => ret.push('\\'),
+    'n' => ret.push('\n'),
+    't' => ret.push('\t'),
+    'r' => ret.push('\r'),
+    '0' => ret.push('\0'),
+    'u' => {
+      let digits = slice.strip_prefix('{').and_then(|rest| rest.split_once('}')).map(|(digits, _)| digits);
+      let char = digits.filter(|digits| (1..=6).contains(&digits.len())).and_then(|digits| u32::from_str_radix(digits, 16).ok()).and_then(char::from_u32);
+      match (digits, char) {
+        (Some(digits), Some(char)) => {
+          *slice = &slice[digits.len() + 2..];
+          ret.push(char);
+        }
+        _ => return Err(InvalidUnicodeEscape(slice.chars().take_while(|c| *c != '"').take(10).collect())),
+      }
+    }
+    _ => {
+      if escaped == '\n' {
+        *current_line += 1;
+      }
+      ret.push('\\');
+      ret.push(escaped);
+    }
+  }
+  Ok(())
+}
+
+fn help_message() -> String {
+  let mut help = String::new();
+  pushln!(help, "Available commands are listed below. Several of them can be given at once, as in alia a x \"echo x\" r y e x");
+  pushln!(help, "They are applied all together or not at all: if one of them fails, no alias file is changed and no alias is run");
+  pushlnln!(help, "Aliases are only run once every command has been checked and the changes of the others have been saved");
+  pushln!(help, "a add ---- Add an alias to Alia ---- Takes name of the alias and the content of the alias as arguments");
+  pushln!(help, "Example usage: alia --add run_release \"cargo run --release\"");
+  pushln!(help, "Pass --desc <text> before the name to describe what the alias is for, and --tag <tag> to tag it. --tag can be given several times");
+  pushln!(help, "Example usage: alia add --desc \"Deploys to the second cluster\" --tag deploy dpl2 \"./deploy.sh 2\"");
+  pushln!(help, "Pass --cwd <dir> to run the alias in that directory, which is relative to the alias file unless it is absolute or starts with ~");
+  pushln!(help, "--env NAME=VALUE sets an environment variable for it and --unset-env NAME removes one. Both can be given several times");
+  pushln!(help, "--shell <shell> runs it with {} instead of the system shell, or without any with exec", Interpreter::NAMES.iter().filter(|name| **name != "exec").copied().collect::<Vec<_>>().join(", "));
+  pushlnln!(help, "Example usage: alia add --cwd web --env NODE_ENV=production --shell bash serve \"npm run serve\"");
+  pushln!(help, "r remove ---- Remove an alias from Alia ---- Takes the name of the alias to remove as an argument");
+  pushln!(help, "Example usage: alia --remove run_release");
+  pushln!(help, "A name ending in .* removes every alias in that namespace, after asking. Pass --yes before the name to skip the question");
+  pushlnln!(help, "Example usage: alia r db.*");
+  pushln!(help, "c change ---- changes an alias in Alia ---- Takes the name of the alias and the new content as arguments");
+  pushlnln!(help, "Example usage: alia --change my_alias \"echo test\"");
+  pushln!(help, "e execute ---- Executes the given alias ---- Takes the name of the alias to execute as an argument");
+  pushln!(help, "Example usage: alia --execute my_alias");
+  pushln!(help, "Any args after the name are passed on to the alias, up to the next Alia command given by its full name, such as remove rather than r");
+  pushln!(help, "Put them after -- to pass everything that follows");
+  pushln!(help, "The alias can place them with $1 to $9, $@ for all of them and ${{1:-default}} for one with a default. Otherwise they are added to the end");
+  pushln!(help, "Example usage: alia e build --release");
+  pushln!(help, "${{name}} and ${{name:-default}} are filled in by passing name=value. Without that they are left to the shell, which uses the default");
+  pushln!(help, "Example usage: alia a push \"git push ${{remote:-origin}} $@\" e push remote=upstream main");
+  pushln!(help, "Alia waits for the alias to finish and exits with its status code. Pass --detach before the name to return straight away instead");
+  pushln!(help, "Example usage: alia e --detach my_alias");
+  pushln!(help, "The alias is run by {}, or by the shell it sets with --shell. Pass --exec before the name to split it into words like a POSIX shell would and run it directly instead", Interpreter::system().name());
+  pushln!(help, "Example usage: alia e --exec my_alias");
+  pushln!(help, "An alias can run other aliases by naming them with an @, as in release = \"@build && @test\". A reference that is not the name of an alias is left alone");
+  pushln!(help, "The aliases an alias references are run by its shell, so they have to use the same --shell, --cwd, --env and --unset-env as it does");
+  pushlnln!(help, "Example usage: alia a release \"@build && @test && @publish\"");
+  pushln!(help, "rename ---- Renames an alias, keeping its description, tags and settings ---- Takes the current name and the new name as arguments");
+  pushlnln!(help, "Example usage: alia rename run_release rr");
+  pushln!(help, "copy ---- Copies an alias to a new name, keeping its description, tags and settings ---- Takes the name to copy and the new name as arguments");
+  pushln!(help, "Example usage: alia copy run_release run_debug");
+  pushlnln!(help, "rename and copy refuse to replace an alias that already has the new name. Pass --force before the names to replace it anyway");
+  pushln!(help, "edit ---- Opens an alias in your editor, which is a good way to write long commands over several lines ---- Optionally takes the name of the alias as an argument");
+  pushln!(help, "A name that does not exist yet starts a new alias. Without a name the whole alias file is opened, and checked before it is saved");
+  pushln!(help, "The editor is taken from $VISUAL, then $EDITOR, and is {} if neither is set. edit cannot be given along with other commands", editor::DEFAULT_EDITOR);
+  pushlnln!(help, "Example usage: alia edit deploy");
+  pushln!(help, "a, r, c, rename, copy and edit take --global, --local or --file <path> before the name to choose which alias file they change");
+  pushlnln!(help, "A -- after the flags of a command ends them, so a name that starts with -- can follow it, as in alia r -- --old");
+  pushln!(help, "l list ---- Lists every alias that is in effect ---- Optionally takes a glob pattern the names have to match as an argument");
+  pushln!(help, "Pass --regex <pattern> to filter with a regular expression instead, --sort name|recent|usage to change the order,");
+  pushln!(help, "--tag <tag> to only list aliases with that tag, --origin to show which file each alias came from and --json to print JSON for scripts");
+  pushln!(help, "Example usage: alia list 'db*' --sort usage");
+  pushln!(help, "Dots in names group aliases into namespaces, as in db.migrate and db.reset. Listing a namespace, or executing it, shows its aliases as a tree");
+  pushlnln!(help, "Example usage: alia list db");
+  pushln!(help, "show ---- Shows the value of an alias along with where it came from and how often it was used ---- Takes the name of the alias as an argument");
+  pushlnln!(help, "Example usage: alia show my_alias");
+  pushln!(help, "expand ---- Prints the command an alias runs, with the aliases it references filled in, without running it ---- Takes the name of the alias as an argument");
+  pushln!(help, "Any args after the name fill in its placeholders, as they would for execute");
+  pushlnln!(help, "Example usage: alia expand release");
+  pushln!(help, "init ---- Prints a script that defines every alias as a function in your shell ---- Takes bash, zsh, fish or powershell as an argument");
+  pushln!(help, "The functions are kept up to date as your alias files change. Add the line for your shell to its startup file:");
+  pushln!(help, "bash: eval \"$(alia init bash)\"    zsh: eval \"$(alia init zsh)\"    fish: alia init fish | source");
+  pushlnln!(help, "powershell: alia init powershell | Out-String | Invoke-Expression");
+  pushln!(help, "completions ---- Prints a script that lets your shell complete commands and alias names ---- Takes bash, zsh, fish, elvish or powershell as an argument");
+  pushln!(help, "bash: source <(alia completions bash)    zsh: source <(alia completions zsh)    fish: alia completions fish | source");
+  pushlnln!(help, "elvish: eval (alia completions elvish | slurp)    powershell: alia completions powershell | Out-String | Invoke-Expression");
+  pushln!(help, "doctor ---- Lists every problem in the alias files that are in effect, such as entries that could not be parsed");
+  pushlnln!(help, "Entries with problems are skipped with a warning, so the rest of your aliases keep working until you fix them");
+  pushln!(help, "where ---- Prints the path of your global alias file. Pass --all to print every alias file in effect");
+  pushlnln!(help, "Example usage: alia where");
+  pushln!(help, "undo ---- Undoes the last change to your aliases, including ones made by rename, copy and edit ---- Optionally takes how many changes to undo as an argument");
+  pushln!(help, "Every invocation of Alia that changes aliases counts as one change, no matter how many commands it ran");
+  pushlnln!(help, "Example usage: alia undo 2");
+  pushlnln!(help, "redo ---- Makes the last undone change again ---- Optionally takes how many changes to redo as an argument");
+  pushln!(help, "history ---- Lists the last changes to your aliases, newest first ---- Optionally takes how many to list as an argument");
+  pushlnln!(help, "The changes are kept in a .journal file next to your global alias file");
+  pushln!(help, "backup list ---- Lists the backups of your global alias file, newest first. One is made every time Alia changes it");
+  pushln!(help, "The last 10 are kept in a backups directory next to the file. Set backups = <count> in its [settings] table to keep more or fewer, or 0 for none");
+  pushln!(help, "backup diff <id> ---- Shows what restoring the backup with the given id would change");
+  pushln!(help, "backup restore <id> ---- Replaces your global alias file with the backup, after checking that it can be read");
+  pushlnln!(help, "Example usage: alia backup restore 20220301-133700");
+  pushln!(help, "h help ---- Displays this message ---- Optionally takes the name of an alias to show its description, command and tags instead");
+  pushlnln!(help, "Example usage: alia help dpl2");
+  pushln!(help, "Global options, which go before any command:");
+  pushln!(help, "--config <path> ---- Use the given file as the alias store");
+  pushln!(help, "--strict ---- Stop at the first problem in an alias file instead of skipping the entry it is in");
+  pushln!(help, "--dry-run ---- Print how the commands would change your alias files, as a diff, and which aliases they would run, without doing either");
+  pushln!(help, "--lock-timeout <seconds> ---- How long to wait for another Alia process that is changing the same alias file. Defaults to 10 seconds");
+  pushln!(help, "This can also be set with ${LOCK_TIMEOUT_ENV_VAR}. Commands that change aliases lock each file they touch with a <file>.lock file next to it");
+  pushln!(help, "Alia looks for its store in ${CONFIG_ENV_VAR}, then --config, then $XDG_CONFIG_HOME/alia/cfg.alia and finally ~/.config/alia/cfg.alia");
+  pushln!(help, "On top of that every {LOCAL_CONFIG_FILE_NAME} file in the current directory and its parents is loaded, with closer files winning");
+  pushlnln!(help, "Alias files can be edited by hand. Alia keeps your comments, ordering and spacing and only rewrites the entries it changes");
+  pushln!(help, "A mistyped command or alias name is answered with the closest matches. Set autocorrect = true in the [settings] table of your global");
+  pushln!(help, "alias file to go with the closest match instead, as long as it is the only one that is a single typo away");
+  help
 }
 
 
@@ -462,8 +1990,18 @@ fn display_help_message() {
 mod tests {
   #![allow(warnings)]
 
-  use std::collections::HashMap;
-  use crate::{CommandLineArgumentErrorCode, config_to_string, ConfigParseErrorCode, END_OF_LINE_SEQUENCE, parse_command_line_args, parse_config, parse_string, StringParseErrorCode};
+  use std::collections::{BTreeMap, HashMap};
+  use std::path::PathBuf;
+  use crate::process::{ChildFailure, Interpreter};
+  use crate::shell_words::ShellWordsErrorCode;
+  use crate::completions::{Arg, COMMANDS, COMPLETION_SHELLS, INIT_SHELLS};
+  use crate::shells::Shell;
+  use std::time::Duration;
+  use quickcheck::quickcheck;
+  use crate::schema::{legacy_to_document, LegacyEntry, parse_document, ParsedDocument, SchemaErrorCode};
+  use std::path::Path;
+  use crate::diagnostics::Span;
+  use crate::{create_backup, format_listing, is_valid_alias_name, PendingRemoval, ReferenceErrorCode, format_plan, is_mutating_command, run_executions, Execution, Program, config_parse_error_diagnostic, apply_edit, open_editor, is_command, LayerSelector, LegacyConfig, read_from_config_file, write_to_config_file, Alias, AliasStore, CommandLineArgumentErrorCode, config_to_string, ConfigParseErrorCode, END_OF_LINE_SEQUENCE, GlobalOptions, Layer, LayerKind, parse_command_line_args, parse_global_options, parse_legacy_config, parse_string, StringParseErrorCode};
   use CommandLineArgumentErrorCode::*;
   use StringParseErrorCode::*;
   use ConfigParseErrorCode::*;
@@ -484,6 +2022,58 @@ mod tests {
     assert_eq!(slice, &"");
     util("\"Snail\"", "", Ok("Snail".into()));
     util("\"HAAAAAA\"some more stuff", "some more stuff", Ok("HAAAAAA".into()));
+    util("\"a\\\\\" b", " b", Ok("a\\".into()));
+    util("\"\\n\\t\\r\\0\\\"\\\\\"", "", Ok("\n\t\r\0\"\\".into()));
+    util("\"\\u{1F600}\\u{e9}\\u{0}\"", "", Ok("\u{1F600}\u{e9}\u{0}".into()));
+    util("\"C:\\dir\\x\"", "", Ok("C:\\dir\\x".into()));
+    util("\"\\u{D800}\"", "{D800}\"",Err(InvalidUnicodeEscape("{D800}".into())));
+    util("\"\\u{1234567}\"", "{1234567}\"", Err(InvalidUnicodeEscape("{1234567}".into())));
+    util("\"\\u41\"", "41\"", Err(InvalidUnicodeEscape("41".into())));
+    util("\"abc\\", "", Err(StringWithoutClosingQuote));
+  }
+
+  /// Quotes s the way parse_string expects, escaping everything it understands an escape for.
+  fn escape_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+      match c {
+        '"' => result.push_str("\\\""),
+        '\\' => result.push_str("\\\\"),
+        '\n' => result.push_str("\\n"),
+        '\t' => result.push_str("\\t"),
+        '\r' => result.push_str("\\r"),
+        '\0' => result.push_str("\\0"),
+        c if c.is_control() => result.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        c => result.push(c),
+      }
+    }
+    result.push('"');
+    result
+  }
+
+  fn parse_string_round_trips(s: String) -> bool {
+    let escaped = escape_string(&s);
+    let parsed = parse_string(&mut escaped.as_str());
+    if s.is_empty() { parsed == Err(EmptyString) } else { parsed == Ok(s) }
+  }
+
+  /// Whether a legacy file holding name and value still holds them after it has been migrated to the new format.
+  fn legacy_file_round_trips(name: String, value: String) -> bool {
+    if !is_valid_alias_name(&name) || value.is_empty() {
+      return true;
+    }
+    let legacy = format!("# A comment{END_OF_LINE_SEQUENCE}{} = {}{END_OF_LINE_SEQUENCE}", escape_string(&name), escape_string(&value));
+    let legacy = parse_legacy_config_strict(&legacy).unwrap();
+    let migrated = legacy_to_document(&legacy.entries, &legacy.trailing_comments).to_string();
+    parse_document(&migrated) == ParsedDocument { aliases: HashMap::from([(name, Alias::new(value))]), ..Default::default() }
+  }
+
+  #[test]
+  fn test_string_round_trip() {
+    quickcheck(parse_string_round_trips as fn(String) -> bool);
+    quickcheck(legacy_file_round_trips as fn(String, String) -> bool);
+    assert!(parse_string_round_trips("\\\"\\n\n\u{1F600}\u{7}\u{85}".to_string()));
   }
 
   fn util(input: &'static str, expected_slice_at_end: &'static str, expected_result: Result<String, StringParseErrorCode>) {
@@ -503,32 +2093,537 @@ mod tests {
     test_cmd_args_template(["a", "my_alias", "echo test"], [], Ok(()), [("my_alias", "echo test")]);
     test_cmd_args_template(["a", "my_alias", "echo test", "r", "my_alias"], [], Ok(()), []);
     test_cmd_args_template(["c", "my_alias", "echo test"], [], Err(AliasDoesNotExist("my_alias".to_string(), 2)), []);
-    test_cmd_args_template(["e", "my_alias"], [], Err(InvalidAliasName("my_alias".to_string(), 2)), []);
+    test_cmd_args_template(["e", "my_alias"], [], Err(InvalidAliasName("my_alias".to_string(), vec![], 2)), []);
     test_cmd_args_template(["a", "my_alias", "echo test", "e", "my_alias"], [], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["where"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["list", "--origin", "r", "my_alias"], [("my_alias", "echo test")], Ok(()), []);
+    test_cmd_args_template(["e", "my_alias", "hello", "remove", "my_alias"], [("my_alias", "echo")], Ok(()), []);
+    // Short forms of commands are passed on, as in `alia e grep -r a .`.
+    test_cmd_args_template(["e", "my_alias", "hello", "r", "my_alias", "c", "-r", "a", "."], [("my_alias", "echo")], Ok(()), [("my_alias", "echo")]);
+    test_cmd_args_template(["e", "my_alias", "--", "r", "my_alias"], [("my_alias", "echo")], Ok(()), [("my_alias", "echo")]);
+    test_cmd_args_template(["e", "my_alias", "x", "--release"], [("my_alias", "echo $1 $2")], Ok(()), [("my_alias", "echo $1 $2")]);
+    test_cmd_args_template(["e", "--detach", "my_alias"], [("my_alias", "echo")], Ok(()), [("my_alias", "echo")]);
+    test_cmd_args_template(["e", "--wait", "my_alias"], [("my_alias", "echo")], Err(UnknownFlag("--wait".to_string(), 2)), [("my_alias", "echo")]);
+    if cfg!(unix) {
+      // Aliases only run once every command was checked, so the remove has happened by the time this one fails.
+      test_cmd_args_template(["e", "my_alias", "remove", "my_alias"], [("my_alias", "false")], Err(AliasFailed("my_alias".to_string(), ChildFailure::ExitCode(1), 2)), []);
+      test_cmd_args_template(["e", "my_alias"], [("my_alias", "test \"a b\" = 'a b' && exit 3")], Err(AliasFailed("my_alias".to_string(), ChildFailure::ExitCode(3), 2)), [("my_alias", "test \"a b\" = 'a b' && exit 3")]);
+      test_cmd_args_template(["e", "--exec", "my_alias", "a b"], [("my_alias", "test 'a b' =")], Ok(()), [("my_alias", "test 'a b' =")]);
+      test_cmd_args_template(["e", "--exec", "my_alias"], [("my_alias", "test && exit 3")], Err(AliasFailed("my_alias".to_string(), ChildFailure::ExitCode(2), 3)), [("my_alias", "test && exit 3")]);
+    }
+    test_cmd_args_template(["e", "--exec", "my_alias"], [("my_alias", "echo 'a")], Err(InvalidShellWords("my_alias".to_string(), ShellWordsErrorCode::UnterminatedSingleQuote, 3)), [("my_alias", "echo 'a")]);
+    test_cmd_args_template(["e", "my_alias", "x"], [("my_alias", "echo $1 $2")], Err(MissingPlaceholderArgument("my_alias".to_string(), "$2".to_string(), 2)), [("my_alias", "echo $1 $2")]);
+  }
+
+  #[test]
+  fn test_descriptions_and_tags() {
+    let mut cfg = store(&[]);
+    assert_eq!(run(&mut cfg, &["a", "--desc", "Deploys to the second cluster", "--tag", "deploy", "--tag", "k8s", "--tag", "deploy", "dpl2", "kubectl apply"]), Ok(()));
+    assert_eq!(cfg.layers[0].aliases["dpl2"], Alias { description: Some("Deploys to the second cluster".to_string()), tags: vec!["deploy".to_string(), "k8s".to_string()], ..Alias::new("kubectl apply".to_string()) });
+    assert_eq!(run(&mut cfg, &["a", "--desc", " ", "x", "ls"]), Ok(()));
+    assert_eq!(cfg.layers[0].aliases["x"], Alias::new("ls".to_string()));
+    assert_eq!(run(&mut cfg, &["a", "--tag", "two words", "y", "ls"]), Err(InvalidFlagValue("--tag".to_string(), "two words".to_string(), 4)));
+    assert_eq!(run(&mut cfg, &["a", "--desc"]), Err(MissingFlagValue("--desc".to_string(), 3)));
+    assert_eq!(run(&mut cfg, &["l", "--tag", "deploy", "help", "dpl2", "help"]), Ok(()));
+    assert_eq!(run(&mut cfg, &["help", "nope"]), Err(CannotShowNonExistentAlias("nope".to_string(), 3)));
+  }
+
+  #[test]
+  fn test_suggestions() {
+    test_cmd_args_template(["alia", "ad", "x", "1"], [("add_all", "git add -A")], Err(InvalidCommand("ad".to_string(), vec!["add".to_string(), "e add_all".to_string()], 2)), [("add_all", "git add -A")]);
+    test_cmd_args_template(["alia", "biuld"], [("build", "cargo build"), ("bind", "ls")], Err(InvalidCommand("biuld".to_string(), vec!["e build".to_string()], 2)), [("bind", "ls"), ("build", "cargo build")]);
+    test_cmd_args_template(["alia", "e", "bild"], [("build", "cargo build"), ("bold", "ls")], Err(InvalidAliasName("bild".to_string(), vec!["bold".to_string(), "build".to_string()], 3)), [("bold", "ls"), ("build", "cargo build")]);
+    let mut cfg = store(&[("build", "cargo build")]);
+    cfg.layers[0].settings.autocorrect = true;
+    assert_eq!(run(&mut cfg, &["ad", "x", "1", "e", "buidl", "--release"]), Ok(()));
+    assert_eq!(cfg.layers[0].aliases["x"], Alias::new("1".to_string()));
+    assert_eq!(cfg.executions[0].program, Program::Shell(Interpreter::Sh, "cargo build --release".to_string()));
+    assert_eq!(run(&mut cfg, &["buil"]), Ok(()));
+    assert_eq!(cfg.executions[1].name, "build");
+    // Only a single match that is a character off is run.
+    assert_eq!(run(&mut cfg, &["hstroy"]), Err(InvalidCommand("hstroy".to_string(), vec!["history".to_string()], 2)));
+    // The corrections are reported once the commands have been checked for the last time.
+    assert_eq!(cfg.notices, "There is no command ad, so add is run instead.\nThere is no alias buidl, so build is executed instead.\nThere is no command buil, so the alias build is executed instead.\n");
+  }
+
+  #[test]
+  fn test_commands_that_change_aliases() {
+    // Each command is given every combination of sample args. One that changes an alias file with any of them has to
+    // be marked as changing aliases in COMMANDS, and one that is marked has to change it with at least one of them.
+    let dir = std::env::temp_dir().join(format!("alia-test-changes-aliases-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cfg.alia");
+    std::fs::write(&path, "version = 1\n[aliases.x]\ncommand = \"echo x\"\n").unwrap();
+    create_backup(&path, 10, 0).unwrap();
+    std::fs::write(&path, "version = 1\n[aliases.x]\ncommand = \"echo x\"\n[aliases.y]\ncommand = \"echo y\"\n").unwrap();
+    let mut changed = AliasStore::new(read_from_config_file(&path, LayerKind::Global, true).unwrap());
+    assert_eq!(run(&mut changed, &["c", "y", "echo z"]), Ok(()));
+    changed.journal.commit(0);
+    changed.layers[0].modified = false;
+    let mut undone = changed.clone();
+    assert_eq!(run(&mut undone, &["undo"]), Ok(()));
+    undone.journal.commit(0);
+    undone.layers[0].modified = false;
+    let samples = |arg: &Arg| match arg {
+      Arg::Alias => vec!["x"],
+      Arg::Text => vec!["1", "19700101-000000"],
+      Arg::OneOf(values) => values.to_vec(),
+      Arg::Tag => vec!["t"],
+    };
+    for info in COMMANDS {
+      let mut invocations = vec![vec![info.names[0]]];
+      for arg in info.args {
+        invocations = invocations.into_iter().flat_map(|invocation| samples(arg).into_iter().map(move |sample| [invocation.clone(), vec![sample]].concat())).collect();
+      }
+      let changes_aliases = invocations.iter().any(|args| [&changed, &undone].into_iter().any(|base| {
+        let mut cfg = base.clone();
+        run(&mut cfg, args).is_ok() && (cfg.layers.iter().any(|layer| layer.modified) || cfg.pending_edit.is_some())
+      }));
+      assert_eq!(info.changes_aliases, changes_aliases, "{}", info.names[0]);
+      assert_eq!(is_mutating_command(info.names[0]), changes_aliases);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_batches() {
+    let mut cfg = store(&[("y", "echo $1")]);
+    let res = run(&mut cfg, &["a", "x", "echo x", "e", "y", "it's", "execute", "--detach", "--exec", "x", "remove", "z"]);
+    assert_eq!(res, Err(CannotRemoveNonExistentValue("z".to_string(), 13)));
+    // Nothing has run yet when a later command fails.
+    assert_eq!(cfg.executions, [
+      Execution { name: "y".to_string(), program: Program::Shell(Interpreter::Sh, "echo 'it'\\''s'".to_string()), cwd: None, env: BTreeMap::new(), unset_env: Vec::new(), detach: false, arg: 6 },
+      Execution { name: "x".to_string(), program: Program::Words(vec!["echo".to_string(), "x".to_string()]), cwd: None, env: BTreeMap::new(), unset_env: Vec::new(), detach: true, arg: 11 },
+    ]);
+    cfg.layers[0].source = "version = 1\n\n[aliases.y]\ncommand = \"echo $1\"\n".to_string();
+    assert_eq!(format_plan(&cfg), "--- cfg.alia\n+++ cfg.alia\n@@ -2,3 +2,6 @@\n \n [aliases.y]\n command = \"echo $1\"\n+\n+[aliases.x]\n+command = \"echo x\"\n\
+Would run y: echo 'it'\\''s'\nWould start x: echo x\n");
+    assert_eq!(format_plan(&AliasStore::default()), "Nothing would be changed or run.\n");
+    // What the commands print is kept until the batch turned out to be valid, and --dry-run shows it with the plan.
+    let mut cfg = store(&[("y", "echo $1")]);
+    assert_eq!(run(&mut cfg, &["expand", "y", "it", "where", "r", "z"]), Err(CannotRemoveNonExistentValue("z".to_string(), 7)));
+    assert_eq!(cfg.output, "echo it\ncfg.alia\n");
+    assert_eq!(format_plan(&cfg), "echo it\ncfg.alia\nNothing would be changed or run.\n");
+  }
+
+  #[test]
+  fn test_namespaces() {
+    let aliases = [("db.migrate", "./migrate.sh"), ("db.seed.dev", "./seed.sh dev"), ("dbx", "x"), ("k8s.logs", "kubectl logs")];
+    let mut cfg = store(&aliases);
+    assert_eq!(run(&mut cfg, &["a", "db..reset", "ls"]), Err(MalformedAliasName("db..reset".to_string(), 3)));
+    assert_eq!(run(&mut cfg, &["a", "db.*", "ls"]), Err(MalformedAliasName("db.*".to_string(), 3)));
+    assert_eq!(run(&mut cfg, &["copy", "dbx", "db."]), Err(MalformedAliasName("db.".to_string(), 4)));
+    assert_eq!(run(&mut cfg, &["a", "db.reset", "./reset.sh", "l", "db", "l", "db.seed"]), Ok(()));
+    // Executing a namespace fails with what is in it instead of running anything.
+    let res = run(&mut cfg, &["e", "db"]);
+    assert!(matches!(&res, Err(AliasIsNamespace(name, tree, 3)) if name == "db" && tree.contains("migrate") && !tree.contains("dbx")), "{res:?}");
+    assert!(cfg.executions.is_empty());
+    assert_eq!(run(&mut cfg, &["r", "--yes", "nope.*"]), Err(CannotRemoveNonExistentValue("nope.*".to_string(), 4)));
+    // Without --yes the removal is only asked about once the whole batch turned out to be valid, and not at all for
+    // --dry-run.
+    let mut unconfirmed = cfg.clone();
+    assert_eq!(run(&mut unconfirmed, &["r", "db.*", "l"]), Ok(()));
+    let names = ["db.migrate", "db.reset", "db.seed.dev"].map(str::to_string).to_vec();
+    assert_eq!(unconfirmed.pending_removals, [PendingRemoval { namespace: "db".to_string(), names, arg: 3 }]);
+    let mut dry_run = cfg.clone();
+    dry_run.dry_run = true;
+    assert_eq!(run(&mut dry_run, &["r", "db.*"]), Ok(()));
+    assert!(dry_run.pending_removals.is_empty());
+    assert!(!dry_run.layers[0].aliases.contains_key("db.migrate"));
+    assert_eq!(run(&mut cfg, &["r", "--yes", "db.*"]), Ok(()));
+    let mut names: Vec<&String> = cfg.layers[0].aliases.keys().collect();
+    names.sort();
+    assert_eq!(names, ["dbx", "k8s.logs"]);
+  }
+
+  #[test]
+  fn test_references() {
+    let aliases = [("build", "cargo build"), ("test", "cargo test $@"), ("a", "@b"), ("b", "echo b && @a")];
+    let mut cfg = store(&aliases);
+    assert_eq!(run(&mut cfg, &["a", "release", "@build && @test", "e", "release", "--quiet", "expand", "release"]), Ok(()));
+    assert_eq!(cfg.executions[0].program, Program::Shell(Interpreter::Sh, "cargo build && cargo test --quiet".to_string()));
+    let cycle = || ReferenceErrorCode::ReferenceCycle(vec!["a".to_string(), "b".to_string(), "a".to_string()]);
+    assert_eq!(run(&mut cfg, &["e", "a"]), Err(InvalidReferences("a".to_string(), cycle(), 3)));
+    assert_eq!(run(&mut cfg, &["expand", "a"]), Err(InvalidReferences("a".to_string(), cycle(), 3)));
+    assert_eq!(run(&mut cfg, &["expand", "nope"]), Err(CannotShowNonExistentAlias("nope".to_string(), 3)));
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn test_execution_settings() {
+    let mut cfg = store(&[]);
+    cfg.layers[0].path = PathBuf::from("/etc/cfg.alia");
+    assert_eq!(run(&mut cfg, &["a", "--env", "=1", "x", "ls"]), Err(InvalidFlagValue("--env".to_string(), "=1".to_string(), 4)));
+    assert_eq!(run(&mut cfg, &["a", "--env", "MODE", "x", "ls"]), Err(InvalidFlagValue("--env".to_string(), "MODE".to_string(), 4)));
+    assert_eq!(run(&mut cfg, &["a", "--shell", "tcsh", "x", "ls"]), Err(InvalidFlagValue("--shell".to_string(), "tcsh".to_string(), 4)));
+    let args = ["a", "--cwd", "..", "--env", "MODE=a=b", "--unset-env", "HOME", "--shell", "bash", "x", "echo $1", "e", "x", "it's"];
+    assert_eq!(run(&mut cfg, &args), Ok(()));
+    let env = BTreeMap::from([("MODE".to_string(), "a=b".to_string())]);
+    let alias = Alias { cwd: Some("..".to_string()), env: env.clone(), unset_env: vec!["HOME".to_string()], shell: Some("bash".to_string()), ..Alias::new("echo $1".to_string()) };
+    assert_eq!(cfg.layers[0].aliases["x"], alias);
+    assert_eq!(cfg.executions, [
+      Execution { name: "x".to_string(), program: Program::Shell(Interpreter::Bash, "echo 'it'\\''s'".to_string()), cwd: Some(PathBuf::from("/etc/..")), env, unset_env: vec!["HOME".to_string()], detach: false, arg: 14 },
+    ]);
+    assert_eq!(format_plan(&cfg).lines().last(), Some("Would run x with bash in /etc/.. without HOME MODE=a=b: echo 'it'\\''s'"));
+    assert_eq!(run(&mut cfg, &["a", "--cwd", "does-not-exist", "y", "ls", "e", "y"]), Err(MissingWorkingDirectory("y".to_string(), "/etc/does-not-exist".to_string(), 8)));
+    // A referenced alias is run by the interpreter of the alias that references it, so it has to run the same way.
+    assert_eq!(run(&mut cfg, &["a", "--cwd", "..", "--env", "MODE=a=b", "--unset-env", "HOME", "--shell", "bash", "same", "@x", "e", "same", "b"]), Ok(()));
+    assert_eq!(cfg.executions.last().unwrap().program, Program::Shell(Interpreter::Bash, "echo b".to_string()));
+    let conflict = ReferenceErrorCode::ConflictingSettings(vec!["both".to_string(), "x".to_string()]);
+    assert_eq!(run(&mut cfg, &["a", "both", "@x", "e", "both"]), Err(InvalidReferences("both".to_string(), conflict.clone(), 6)));
+    assert_eq!(run(&mut cfg, &["expand", "both"]), Err(InvalidReferences("both".to_string(), conflict, 3)));
+  }
+
+  /// Runs the commands in args as a single invocation of Alia, without saving anything or running any alias.
+  fn run(cfg: &mut AliasStore, args: &[&str]) -> Result<(), CommandLineArgumentErrorCode> {
+    parse_command_line_args(std::iter::once("alia").chain(args.iter().copied()).map(str::to_string).collect::<Vec<_>>().into_iter(), cfg)
+  }
+
+  /// A store whose only alias file is cfg.alia with the given aliases in it.
+  fn store(aliases: &[(&str, &str)]) -> AliasStore {
+    AliasStore::new(Layer::new(PathBuf::from("cfg.alia"), LayerKind::Global, aliases.iter().map(|(name, command)| (name.to_string(), Alias::new(command.to_string()))).collect()))
   }
 
   fn test_cmd_args_no_cfg<const NumOfArgs: usize>(args: [&'static str; NumOfArgs], expected_result: Result<(), CommandLineArgumentErrorCode>) {
     println!("Entered test_cmd_args_no_cfg with args : {args:?}");
-    let res = parse_command_line_args(args.into_iter().map(|x| x.to_string()), &mut HashMap::new());
+    let res = parse_command_line_args(args.into_iter().map(|x| x.to_string()), &mut AliasStore::new(Layer::new(PathBuf::new(), LayerKind::Global, HashMap::new())));
     assert_eq!(res, expected_result);
   }
 
   fn test_cmd_args_template<const NumOfArgs: usize, const SizeOfCfg: usize, const SizeOfCfgAfter: usize>(args: [&'static str; NumOfArgs], cfg: [(&'static str, &'static str); SizeOfCfg], expected_result: Result<(), CommandLineArgumentErrorCode>, cfg_after: [(&'static str, &'static str); SizeOfCfgAfter]) {
-    let mut cfg: HashMap<String, String> = cfg.into_iter().map(|x| (x.0.to_string(), x.1.to_string())).collect();
-    let cfg_after: HashMap<String, String> = cfg_after.into_iter().map(|x| (x.0.to_string(), x.1.to_string())).collect();
+    let mut cfg = AliasStore::new(Layer::new(PathBuf::new(), LayerKind::Global, cfg.into_iter().map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).collect()));
+    let cfg_after: HashMap<String, Alias> = cfg_after.into_iter().map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).collect();
     println!("Entered test_cmd_args_template with args : {args:?}");
+    let res = parse_command_line_args(args.into_iter().map(|x| x.to_string()), &mut cfg).and_then(|()| run_executions(&mut cfg));
+    assert_eq!(res, expected_result);
+    assert_eq!(cfg.layers[0].aliases, cfg_after);
+  }
+
+  #[test]
+  fn test_rename_and_copy() {
+    test_cmd_args_template(["rename", "x", "y"], [("x", "echo x")], Ok(()), [("y", "echo x")]);
+    test_cmd_args_template(["copy", "x", "y"], [("x", "echo x")], Ok(()), [("x", "echo x"), ("y", "echo x")]);
+    test_cmd_args_template(["rename", "x", "x"], [("x", "echo x")], Ok(()), [("x", "echo x")]);
+    test_cmd_args_template(["rename", "x", "y"], [("x", "echo x"), ("y", "echo y")], Err(CannotOverwriteAlias("y".to_string(), 3)), [("x", "echo x"), ("y", "echo y")]);
+    test_cmd_args_template(["copy", "x", "y"], [("x", "echo x"), ("y", "echo y")], Err(CannotOverwriteAlias("y".to_string(), 3)), [("x", "echo x"), ("y", "echo y")]);
+    test_cmd_args_template(["rename", "--force", "x", "y"], [("x", "echo x"), ("y", "echo y")], Ok(()), [("y", "echo x")]);
+    test_cmd_args_template(["copy", "--force", "x", "y"], [("x", "echo x"), ("y", "echo y")], Ok(()), [("x", "echo x"), ("y", "echo x")]);
+    test_cmd_args_template(["rename", "z", "y"], [("x", "echo x")], Err(CannotCopyNonExistentAlias("z".to_string(), 2)), [("x", "echo x")]);
+    test_cmd_args_template(["copy", "x"], [("x", "echo x")], Err(MissingNameArgument(2)), [("x", "echo x")]);
+    test_cmd_args_template(["copy", "--bogus", "x", "y"], [("x", "echo x")], Err(UnknownFlag("--bogus".to_string(), 2)), [("x", "echo x")]);
+    // A -- ends the flags, so aliases whose names start with -- can be named after it.
+    test_cmd_args_template(["a", "--", "--x", "echo x"], [], Ok(()), [("--x", "echo x")]);
+    test_cmd_args_template(["c", "--global", "--", "--x", "ls"], [("--x", "echo x")], Ok(()), [("--x", "ls")]);
+    test_cmd_args_template(["rename", "--force", "--", "--x", "y"], [("--x", "echo x"), ("y", "echo y")], Ok(()), [("y", "echo x")]);
+    test_cmd_args_template(["show", "--", "--x", "r", "--", "--x"], [("--x", "echo x")], Ok(()), []);
+    test_cmd_args_template(["r", "--x"], [("--x", "echo x")], Err(UnknownFlag("--x".to_string(), 2)), [("--x", "echo x")]);
+
+    let mut alias = Alias::new("cargo build".to_string());
+    alias.description = Some("Builds it".to_string());
+    alias.tags = vec!["rust".to_string()];
+    let mut cfg = AliasStore::new(Layer::new(PathBuf::new(), LayerKind::Global, HashMap::from([("b".to_string(), alias.clone())])));
+    cfg.usage.record("b", 100);
+    assert_eq!(parse_command_line_args(["alia", "rename", "b", "build"].into_iter().map(str::to_string), &mut cfg), Ok(()));
+    assert_eq!(cfg.layers[0].aliases, HashMap::from([("build".to_string(), alias)]));
+    assert_eq!(cfg.usage.get("b"), None);
+    assert_eq!(cfg.usage.get("build").map(|usage| usage.count), Some(1));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_edit() {
+    let dir = std::env::temp_dir().join(format!("alia-test-edit-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cfg.alia");
+    let source = "version = 1\n\n# Mine\n[aliases.x]\ncommand = \"echo old\"\ndescription = \"Says old\"\n";
+    std::fs::write(&path, source).unwrap();
+    let read = || AliasStore::new(read_from_config_file(&path, LayerKind::Global, true).unwrap());
+    // Editors that work the same on any POSIX system, unlike sed -i, which BSD sed reads a backup suffix after.
+    let sed = |expression: &str| format!("sh -c 'sed \"{expression}\" \"$1\" > \"$1.new\" && mv \"$1.new\" \"$1\"' sh");
+    let append = "sh -c 'printf \"echo one\\necho two\\n\" >> \"$1\"' sh";
+    // Each call is a separate invocation of Alia, which opens the editor once its args have been checked.
+    let edit = |cfg: &mut AliasStore, args: &[&str], editor: &str| {
+      cfg.pending_edit = None;
+      run(cfg, args)?;
+      let pending = cfg.pending_edit.clone().unwrap();
+      let original = cfg.layers[pending.layer].source.clone();
+      match open_editor(cfg, &pending, editor)? {
+        Some(edit) => apply_edit(cfg, edit, &original),
+        None => Ok(()),
+      }
+    };
+    let mut alias = Alias::new("echo old".to_string());
+    alias.description = Some("Says old".to_string());
+    let mut cfg = read();
+    assert_eq!(edit(&mut cfg, &["edit", "x"], &sed("s/old/new/")), Ok(()));
+    assert_eq!(cfg.layers[0].aliases["x"], Alias { command: "echo new".to_string(), ..alias.clone() });
+    assert!(cfg.layers[0].modified);
+    let mut cfg = read();
+    assert_eq!(edit(&mut cfg, &["edit", "x"], "true"), Ok(()));
+    assert!(!cfg.layers[0].modified);
+    assert_eq!(edit(&mut cfg, &["edit", "y"], append), Ok(()));
+    assert_eq!(cfg.layers[0].aliases["y"], Alias::new("echo one\necho two".to_string()));
+    let mut cfg = read();
+    assert_eq!(edit(&mut cfg, &["edit", "z"], "true"), Ok(()));
+    assert!(!cfg.layers[0].aliases.contains_key("z"));
+    assert!(matches!(edit(&mut cfg, &["edit", "x"], "false"), Err(EditorFailed(_, _, 3))));
+
+    let mut cfg = read();
+    assert_eq!(edit(&mut cfg, &["edit"], &sed("s/x]/renamed]/")), Ok(()));
+    assert_eq!(cfg.layers[0].aliases, HashMap::from([("renamed".to_string(), alias)]));
+    assert_eq!(config_to_string(&cfg.layers[0].source, &cfg.layers[0].aliases), "version = 1\n\n# Mine\n[aliases.renamed]\ncommand = \"echo old\"\ndescription = \"Says old\"\n");
+
+    // The editor is only opened once the invocation has been checked, and not at all for --dry-run, so edit cannot be
+    // given along with other commands.
+    assert_eq!(run(&mut read(), &["edit", "x", "l"]), Err(EditNotAlone(2)));
+    assert_eq!(run(&mut read(), &["l", "edit", "x"]), Err(EditNotAlone(3)));
+    let mut cfg = read();
+    cfg.dry_run = true;
+    assert_eq!(run(&mut cfg, &["edit", "x"]), Ok(()));
+    assert_eq!(format_plan(&cfg), format!("Would open x from {} in the editor\n", path.display()));
+
+    // A change another process made while the editor was open is not overwritten, and the edit is kept.
+    let mut cfg = read();
+    run(&mut cfg, &["edit", "x"]).unwrap();
+    let pending = cfg.pending_edit.clone().unwrap();
+    let edited = open_editor(&cfg, &pending, &sed("s/old/new/")).unwrap().unwrap();
+    let edit_path = edited.path.clone();
+    std::fs::write(&path, "version = 1\n").unwrap();
+    assert_eq!(apply_edit(&mut cfg, edited, source), Err(EditConflict(path.display().to_string(), edit_path.display().to_string(), 3)));
+    assert_eq!(std::fs::read_to_string(&edit_path).unwrap(), "echo new\n");
+    assert!(!cfg.layers[0].modified);
+    std::fs::remove_file(&edit_path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_backup_restore() {
+    let dir = std::env::temp_dir().join(format!("alia-test-backup-restore-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cfg.alia");
+    let original = "version = 1\n\n# Mine\n[aliases.x]\ncommand = \"echo x\"\n";
+    std::fs::write(&path, original).unwrap();
+    create_backup(&path, 10, 0).unwrap();
+    std::fs::write(&path, "version = 1\n[aliases.y]\ncommand = \"echo y\"\n").unwrap();
+    let mut cfg = AliasStore::new(read_from_config_file(&path, LayerKind::Global, true).unwrap());
+    assert_eq!(run(&mut cfg, &["backup", "diff", "19700101-000000"]), Ok(()));
+    assert_eq!(run(&mut cfg, &["backup", "restore", "19700101-000001"]), Err(BackupDoesNotExist("19700101-000001".to_string(), 4)));
+    assert_eq!(run(&mut cfg, &["backup", "restore"]), Err(MissingBackupId(3)));
+    assert_eq!(run(&mut cfg, &["backup", "delete"]), Err(InvalidBackupCommand("delete".to_string(), 3)));
+    assert_eq!(run(&mut cfg, &["backup", "restore", "19700101-000000"]), Ok(()));
+    assert_eq!(cfg.layers[0].aliases, HashMap::from([("x".to_string(), Alias::new("echo x".to_string()))]));
+    assert!(cfg.layers[0].modified);
+    assert_eq!(config_to_string(&cfg.layers[0].source, &cfg.layers[0].aliases), original);
+    // A backup that cannot be read without problems is not restored.
+    std::fs::write(dir.join("backups").join("cfg.alia.19700101-000000"), "version = 1\n[aliases.x]\ncomand = \"echo x\"\n").unwrap();
+    let mut cfg = AliasStore::new(read_from_config_file(&path, LayerKind::Global, true).unwrap());
+    assert_eq!(run(&mut cfg, &["backup", "restore", "19700101-000000"]), Err(InvalidBackup("19700101-000000".to_string(), 1, 4)));
+    assert!(!cfg.layers[0].modified);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_undo_and_redo() {
+    let mut cfg = store(&[("y", "echo y")]);
+    // Each call is a separate invocation of Alia, which commits its changes to the journal at the end.
+    let mut run = |args: &[&str]| {
+      let res = parse_command_line_args(std::iter::once("alia").chain(args.iter().copied()).map(str::to_string).collect::<Vec<_>>().into_iter(), &mut cfg);
+      cfg.journal.commit(0);
+      let mut aliases: Vec<String> = cfg.layers[0].aliases.iter().map(|(name, alias)| format!("{name}={}", alias.command)).collect();
+      aliases.sort();
+      (res, aliases)
+    };
+    assert_eq!(run(&["a", "x", "1", "r", "y"]), (Ok(()), vec!["x=1".to_string()]));
+    assert_eq!(run(&["c", "x", "2"]), (Ok(()), vec!["x=2".to_string()]));
+    assert_eq!(run(&["undo"]), (Ok(()), vec!["x=1".to_string()]));
+    assert_eq!(run(&["undo"]), (Ok(()), vec!["y=echo y".to_string()]));
+    assert_eq!(run(&["undo"]), (Err(NothingToUndo(2)), vec!["y=echo y".to_string()]));
+    assert_eq!(run(&["redo", "2"]), (Ok(()), vec!["x=2".to_string()]));
+    assert_eq!(run(&["redo"]), (Err(NothingToRedo(2)), vec!["x=2".to_string()]));
+    // Changes made earlier in the same invocation are undone first.
+    assert_eq!(run(&["a", "q", "1", "undo"]), (Ok(()), vec!["x=2".to_string()]));
+    // Making a change after undoing means what was undone cannot be redone any more.
+    assert_eq!(run(&["undo", "a", "z", "3"]), (Ok(()), vec!["x=1".to_string(), "z=3".to_string()]));
+    assert_eq!(run(&["redo"]), (Err(NothingToRedo(2)), vec!["x=1".to_string(), "z=3".to_string()]));
+    assert_eq!(run(&["history"]).0, Ok(()));
+    assert_eq!(run(&["undo", "2"]), (Ok(()), vec!["y=echo y".to_string()]));
+    assert_eq!(run(&["undo"]), (Err(NothingToUndo(2)), vec!["y=echo y".to_string()]));
+  }
+
+  #[test]
+  fn test_undo_conflict() {
+    let mut cfg = store(&[]);
+    assert_eq!(parse_command_line_args(["alia", "a", "x", "1"].into_iter().map(str::to_string), &mut cfg), Ok(()));
+    cfg.journal.commit(0);
+    cfg.layers[0].aliases.insert("x".to_string(), Alias::new("changed by hand".to_string()));
+    assert!(matches!(parse_command_line_args(["alia", "undo"].into_iter().map(str::to_string), &mut cfg), Err(HistoryConflict(1, name, _, 2)) if name == "x"));
+    assert_eq!(cfg.layers[0].aliases["x"], Alias::new("changed by hand".to_string()));
+  }
+
+  fn layered_store<const SizeOfGlobal: usize, const SizeOfLocal: usize>(global: [(&'static str, &'static str); SizeOfGlobal], local: [(&'static str, &'static str); SizeOfLocal]) -> AliasStore {
+    let mut cfg = AliasStore::new(Layer::new(PathBuf::from("global.alia"), LayerKind::Global, global.into_iter().map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).collect()));
+    cfg.push_layer(Layer::new(PathBuf::from(".alia"), LayerKind::Local, local.into_iter().map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).collect()));
+    cfg
+  }
+
+  fn test_cmd_args_layers_template<const NumOfArgs: usize, const SizeOfGlobal: usize, const SizeOfLocal: usize>(args: [&'static str; NumOfArgs], mut cfg: AliasStore, expected_result: Result<(), CommandLineArgumentErrorCode>, global_after: [(&'static str, &'static str); SizeOfGlobal], local_after: [(&'static str, &'static str); SizeOfLocal]) {
+    println!("Entered test_cmd_args_layers_template with args : {args:?}");
     let res = parse_command_line_args(args.into_iter().map(|x| x.to_string()), &mut cfg);
     assert_eq!(res, expected_result);
-    assert_eq!(cfg, cfg_after);
+    assert_eq!(cfg.layers[0].aliases, HashMap::from(global_after.map(|x| (x.0.to_string(), Alias::new(x.1.to_string())))));
+    assert_eq!(cfg.layers[1].aliases, HashMap::from(local_after.map(|x| (x.0.to_string(), Alias::new(x.1.to_string())))));
+  }
+
+  #[test]
+  fn test_layered_commands() {
+    test_cmd_args_layers_template(["a", "x", "1"], layered_store([], []), Ok(()), [("x", "1")], []);
+    test_cmd_args_layers_template(["a", "--local", "x", "1"], layered_store([], []), Ok(()), [], [("x", "1")]);
+    test_cmd_args_layers_template(["a", "--local", "x", "1"], layered_store([("x", "0")], []), Ok(()), [("x", "0")], [("x", "1")]);
+    test_cmd_args_layers_template(["a", "--global", "x", "1"], layered_store([("x", "0")], []), Err(AliasAlreadyExists("x".to_string(), 3)), [("x", "0")], []);
+    test_cmd_args_layers_template(["c", "x", "2"], layered_store([("x", "0")], [("x", "1")]), Ok(()), [("x", "0")], [("x", "2")]);
+    test_cmd_args_layers_template(["c", "--global", "x", "2"], layered_store([("x", "0")], [("x", "1")]), Ok(()), [("x", "2")], [("x", "1")]);
+    test_cmd_args_layers_template(["c", "--local", "x", "2"], layered_store([("x", "0")], []), Err(AliasDoesNotExist("x".to_string(), 3)), [("x", "0")], []);
+    test_cmd_args_layers_template(["r", "x"], layered_store([("x", "0")], [("x", "1")]), Ok(()), [("x", "0")], []);
+    test_cmd_args_layers_template(["r", "x", "r", "x"], layered_store([("x", "0")], [("x", "1")]), Ok(()), [], []);
+    test_cmd_args_layers_template(["r", "--local", "x"], layered_store([("x", "0")], []), Err(CannotRemoveNonExistentValue("x".to_string(), 3)), [("x", "0")], []);
+    test_cmd_args_layers_template(["r", "--bogus", "x"], layered_store([("x", "0")], []), Err(UnknownFlag("--bogus".to_string(), 2)), [("x", "0")], []);
+    test_cmd_args_layers_template(["rename", "x", "y"], layered_store([("x", "0")], [("x", "1")]), Ok(()), [("x", "0")], [("y", "1")]);
+    test_cmd_args_layers_template(["copy", "--global", "x", "y"], layered_store([("x", "0")], [("x", "1")]), Ok(()), [("x", "0"), ("y", "0")], [("x", "1")]);
+    test_cmd_args_layers_template(["rename", "--local", "x", "y"], layered_store([("x", "0")], []), Err(CannotCopyNonExistentAlias("x".to_string(), 3)), [("x", "0")], []);
+    test_cmd_args_layers_template(["a", "--file"], layered_store([], []), Err(MissingFlagValue("--file".to_string(), 2)), [], []);
+  }
+
+  #[test]
+  fn test_init() {
+    test_cmd_args_template(["init", "bash"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["init", "fish", "__fingerprint"], [], Ok(()), []);
+    test_cmd_args_template(["init", "tcsh"], [], Err(UnsupportedShell("tcsh".to_string(), 2)), []);
+    test_cmd_args_template(["init"], [], Err(MissingShellArgument(1)), []);
+    test_cmd_args_template(["init", "elvish"], [], Err(UnsupportedShell("elvish".to_string(), 2)), []);
+  }
+
+  #[test]
+  fn test_completions() {
+    test_cmd_args_template(["completions", "elvish", "completions", "zsh"], [], Ok(()), []);
+    test_cmd_args_template(["completions", "tcsh"], [], Err(UnsupportedShell("tcsh".to_string(), 2)), []);
+    test_cmd_args_template(["__complete", "--", "r", "my"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["__complete", "--position", "1", "--", "e"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["__complete", "--position", "x", "--", "e"], [], Err(InvalidFlagValue("--position".to_string(), "x".to_string(), 3)), []);
+  }
+
+  #[test]
+  fn test_command_table_in_sync() {
+    for command in COMMANDS {
+      for name in command.names {
+        assert!(is_command(name), "{name} is in the command table but parse_arg does not know it");
+      }
+    }
+    for name in ["a", "add", "r", "remove", "e", "execute", "c", "change", "rename", "copy", "edit", "undo", "redo", "history", "backup", "l", "list", "show", "expand", "where", "doctor", "init", "completions", "h", "help", "__fingerprint", "__complete"] {
+      assert!(COMMANDS.iter().any(|command| command.names.contains(&name)), "{name} is missing from the command table");
+    }
+    for shell in INIT_SHELLS.iter().chain(COMPLETION_SHELLS) {
+      assert!(Shell::parse(shell).is_some());
+    }
+  }
+
+  #[test]
+  fn test_list_and_show() {
+    test_cmd_args_template(["l"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["list", "my_*", "--sort", "usage", "--json", "r", "my_alias"], [("my_alias", "echo test")], Ok(()), []);
+    test_cmd_args_template(["list", "--regex", "^my", "--origin"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["list", "--sort", "size"], [], Err(InvalidFlagValue("--sort".to_string(), "size".to_string(), 3)), []);
+    test_cmd_args_template(["list", "--sort"], [], Err(MissingFlagValue("--sort".to_string(), 2)), []);
+    assert!(matches!(run(&mut store(&[]), &["list", "--regex", "("]), Err(InvalidRegex(pattern, _, 4)) if pattern == "("));
+    test_cmd_args_template(["show", "my_alias"], [("my_alias", "echo test")], Ok(()), [("my_alias", "echo test")]);
+    test_cmd_args_template(["show", "--json", "my_alias", "r", "my_alias"], [("my_alias", "echo test")], Ok(()), []);
+    test_cmd_args_template(["show", "missing"], [], Err(CannotShowNonExistentAlias("missing".to_string(), 2)), []);
+    test_cmd_args_template(["show"], [], Err(MissingNameArgument(1)), []);
+
+    let mut cfg = store(&[("my_alias", "echo test"), ("my_other", "ls"), ("build", "cargo build")]);
+    cfg.usage.record("my_other", 5);
+    let list = |cfg: &AliasStore, args: &[&str]| format_listing(&mut args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter().peekable(), &mut 2, cfg);
+    let lines = |lines: &[&str]| lines.iter().map(|line| line.to_string() + END_OF_LINE_SEQUENCE).collect::<String>();
+    assert_eq!(list(&cfg, &[]), Ok(lines(&["NAME      VALUE", "build     cargo build", "my_alias  echo test", "my_other  ls"])));
+    assert_eq!(list(&cfg, &["my_*", "--sort", "usage"]), Ok(lines(&["NAME      VALUE", "my_other  ls", "my_alias  echo test"])));
+    assert_eq!(list(&cfg, &["--regex", "^b", "--origin"]), Ok(lines(&["NAME   VALUE        ORIGIN", "build  cargo build  cfg.alia (global)"])));
+    let json = list(&cfg, &["--json", "--regex", "other"]).unwrap();
+    assert_eq!(json, lines(&["[", "  {\"name\": \"my_other\", \"value\": \"ls\", \"description\": null, \"tags\": [], \"origin\": \"cfg.alia\", \"layer\": \"global\", \"uses\": 1, \"last_used\": 5}", "]"]));
+    assert_eq!(list(&cfg, &["nothing*"]), Ok(String::new()));
+  }
+
+  #[test]
+  fn test_parse_global_options() {
+    test_global_options_template(["alia", "a", "x", "y"], Ok(GlobalOptions::default()), ["alia", "a", "x", "y"]);
+    test_global_options_template(["alia", "--config", "./my.alia", "where"], Ok(GlobalOptions { config_path: Some("./my.alia".to_string()), ..Default::default() }), ["alia", "where"]);
+    test_global_options_template(["alia", "--config"], Err(MissingFlagValue("--config".to_string(), 1)), ["alia"]);
+    test_global_options_template(["alia", "a", "--config", "y"], Ok(GlobalOptions::default()), ["alia", "a", "--config", "y"]);
+    test_global_options_template(["alia", "--lock-timeout", "2.5", "--config", "x", "l"], Ok(GlobalOptions { config_path: Some("x".to_string()), lock_timeout: Some(Duration::from_millis(2500)), ..Default::default() }), ["alia", "l"]);
+    test_global_options_template(["alia", "--strict", "l"], Ok(GlobalOptions { strict: true, ..Default::default() }), ["alia", "l"]);
+    test_global_options_template(["alia", "--dry-run", "r", "x"], Ok(GlobalOptions { dry_run: true, ..Default::default() }), ["alia", "r", "x"]);
+    test_global_options_template(["alia", "--lock-timeout", "never", "l"], Err(InvalidFlagValue("--lock-timeout".to_string(), "never".to_string(), 2)), ["alia", "l"]);
+  }
+
+  fn test_global_options_template<const NumOfArgs: usize, const NumOfArgsAfter: usize>(args: [&'static str; NumOfArgs], expected_result: Result<GlobalOptions, CommandLineArgumentErrorCode>, args_after: [&'static str; NumOfArgsAfter]) {
+    let mut args: Vec<String> = args.into_iter().map(|x| x.to_string()).collect();
+    let res = parse_global_options(&mut args);
+    assert_eq!(res, expected_result);
+    assert_eq!(args, args_after);
   }
 
   #[test]
   fn test_parse_cfg() {
     test_parse_cfg_template(["\"my_alias\" = \"echo test\"", "\"test\" = \"echo benis\""], Ok([("my_alias", "echo test"), ("test", "echo benis")]));
-    test_parse_cfg_template::<1, 1>(["my_alias = echo test"], Err(InvalidAlias(StringWithoutOpeningQuote, 1)));
-    test_parse_cfg_template::<1, 1>(["\"my_alias = echo test\""], Err(MissingAliasValue(1)));
-    test_parse_cfg_template::<1, 1>(["\"my_alias\"\"ttt\""], Err(MissingEqualSign(1)));
+    test_parse_cfg_template::<1, 1>(["my_alias = echo test"], Err(InvalidAlias(StringWithoutOpeningQuote, Span::new(0, 8))));
+    test_parse_cfg_template::<1, 1>(["\"my_alias = echo test\""], Err(MissingAliasValue(Span::new(22, 22))));
+    test_parse_cfg_template::<1, 1>(["\"my_alias\"\"ttt\""], Err(MissingEqualSign(Span::new(10, 11))));
     test_parse_cfg_template(["\"my_alias\"=\"t\"\"2\"=\"1\""], Ok([("my_alias", "t"), ("2", "1")]));
+    test_parse_cfg_template(["# My aliases", "", "\"my_alias\" = \"echo # test\" # trailing", "  # indented", "\"test\" = \"x\"", "# the end"], Ok([("my_alias", "echo # test"), ("test", "x")]));
+    test_parse_cfg_template::<1, 1>(["\"my_alias\" # = \"echo test\""], Err(MissingEqualSign(Span::new(11, 12))));
+    test_parse_cfg_template(["\"db.migrate\" = \"./migrate.sh\""], Ok([("db.migrate", "./migrate.sh")]));
+    test_parse_cfg_template::<1, 1>(["\"db..migrate\" = \"./migrate.sh\""], Err(InvalidAlias(InvalidNameSegment("db..migrate".to_string()), Span::new(0, 13))));
+    test_parse_cfg_template::<1, 1>(["\"db.\" = \"x\""], Err(InvalidAlias(InvalidNameSegment("db.".to_string()), Span::new(0, 5))));
+  }
+
+  #[test]
+  fn test_config_parse_error_location() {
+    let contents = "\"a\" = \"b\"\n\n\"c\" \"d\"\n\"e\" = \"\\u{zz}\"\n";
+    let locate = |contents: &str| parse_legacy_config_strict(contents).map_err(|e| config_parse_error_diagnostic(&e, Path::new("cfg.alia"), contents).to_string());
+    assert_eq!(locate(contents), Err("error: Expected an equal sign after the alias name.\n --> cfg.alia:3:5\n  |\n3 | \"c\" \"d\"\n  |     ^\n  = help: names and values are separated by an equal sign, as in \"name\" = \"value\"".to_string()));
+    assert_eq!(locate("\"e\" = \"\\u{zz}\""), Err("error: The alias value could not be parsed into a string. Your string contained \\u{zz}, which is not a valid Unicode escape. It should look like \\u{1F600}.\n --> cfg.alia:1:8\n  |\n1 | \"e\" = \"\\u{zz}\"\n  |        ^^^^^^\n  = help: write Unicode escapes as \\u{1F600}, or write \\\\ for a backslash".to_string()));
+    assert_eq!(locate("build = \"x\""), Err("error: The alias name could not be parsed into a string. Your string did not contain an opening quote.\n --> cfg.alia:1:1\n  |\n1 | build = \"x\"\n  | ^^^^^\n  = help: did you forget to quote the alias name? Names and values are written as \"name\" = \"value\"".to_string()));
+  }
+
+  #[test]
+  fn test_parse_legacy_config() {
+    let config = format!("# one{END_OF_LINE_SEQUENCE}# two{END_OF_LINE_SEQUENCE}\"a\" = \"1\"  # trailing {END_OF_LINE_SEQUENCE}\"b\" = \"2\"{END_OF_LINE_SEQUENCE}# end");
+    let LegacyConfig { entries, trailing_comments, errors } = parse_legacy_config(&config);
+    assert_eq!(errors, vec![]);
+    assert_eq!(entries, vec![
+      LegacyEntry { comments: vec!["# one".to_string(), "# two".to_string()], name: "a".to_string(), value: "1".to_string(), trailing_comment: Some("# trailing".to_string()) },
+      LegacyEntry { comments: vec![], name: "b".to_string(), value: "2".to_string(), trailing_comment: None },
+    ]);
+    assert_eq!(trailing_comments, vec!["# end".to_string()]);
+  }
+
+  #[test]
+  fn test_parse_legacy_config_recovers() {
+    let config = "\"a\" = \"1\"\nb = \"2\"\n\"c\" \"3\"\n\"d\" = \"4\"\n\"e\" = 5\n\"f\" = \"6\"\n";
+    let parsed = parse_legacy_config(config);
+    let names: Vec<&str> = parsed.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["a", "d", "f"]);
+    assert_eq!(parsed.errors, vec![InvalidAlias(StringWithoutOpeningQuote, Span::new(10, 11)), MissingEqualSign(Span::new(22, 23)), InvalidValue(StringWithoutOpeningQuote, Span::new(42, 43))]);
+  }
+
+  /// Parses config like parse_legacy_config does, but fails at the first problem.
+  fn parse_legacy_config_strict(config: &str) -> Result<LegacyConfig, ConfigParseErrorCode> {
+    let parsed = parse_legacy_config(config);
+    match parsed.errors.first() {
+      Some(error) => Err(error.clone()),
+      None => Ok(parsed),
+    }
   }
 
   fn test_parse_cfg_template<const SizeOfCfg: usize, const SizeOfParsedCfg: usize>(cfg: [&'static str; SizeOfCfg], expected_result: Result<[(&'static str, &'static str); SizeOfParsedCfg], ConfigParseErrorCode>) {
@@ -540,19 +2635,54 @@ mod tests {
       }
       temp
     };
-    let res = parse_config(cfg);
+    let res = parse_legacy_config_strict(&cfg).map(|parsed| parsed.entries.into_iter().map(|entry| (entry.name, entry.value)).collect::<HashMap<_, _>>());
     let expected_result = expected_result.map(|x| HashMap::from(x.map(|y| (y.0.to_string(), y.1.to_string()))));
     assert_eq!(res, expected_result);
   }
 
   #[test]
   fn test_cfg_to_string() {
-    test_cfg_to_string_template([("my_alias", "echo test")], [format!("\"my_alias\" = \"echo test\"{END_OF_LINE_SEQUENCE}")]);
-    test_cfg_to_string_template([("my_alias", "echo test"), ("1", "2")], [format!("\"my_alias\" = \"echo test\"{END_OF_LINE_SEQUENCE}\"1\" = \"2\"{END_OF_LINE_SEQUENCE}"), format!("\"1\" = \"2\"{END_OF_LINE_SEQUENCE}\"my_alias\" = \"echo test\"{END_OF_LINE_SEQUENCE}")]);
+    test_cfg_to_string_template([("my_alias", "echo test")], ["version = 1\n\n[aliases.my_alias]\ncommand = \"echo test\"\n".to_string()]);
+    test_cfg_to_string_template([("my_alias", "echo \"test\""), ("1", "2")], ["version = 1\n\n[aliases.1]\ncommand = \"2\"\n\n[aliases.my_alias]\ncommand = 'echo \"test\"'\n".to_string()]);
+    test_cfg_to_string_template([], ["version = 1\n".to_string()]);
+  }
+
+  #[test]
+  fn test_read_from_config_file() {
+    let dir = std::env::temp_dir().join(format!("alia_test_migrate_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cfg.alia");
+    let missing = dir.join("new").join("cfg.alia");
+    let mut layer = read_from_config_file(&missing, LayerKind::Global, true).unwrap();
+    assert_eq!(layer.aliases, HashMap::new());
+    assert!(!missing.exists());
+    layer.aliases.insert("a".to_string(), Alias::new("ls".to_string()));
+    write_to_config_file(&layer).unwrap();
+    assert_eq!(read_from_config_file(&missing, LayerKind::Global, true).map(|layer| layer.aliases), Ok(layer.aliases));
+    let legacy = format!("\"my_alias\" = \"echo test\"{END_OF_LINE_SEQUENCE}");
+    std::fs::write(&path, &legacy).unwrap();
+    let expected = HashMap::from([("my_alias".to_string(), Alias::new("echo test".to_string()))]);
+    let read = |path: &PathBuf| read_from_config_file(path, LayerKind::Global, true).map(|layer| layer.aliases);
+    assert_eq!(read(&path), Ok(expected.clone()));
+    assert_eq!(std::fs::read_to_string(dir.join("cfg.alia.v0.bak")).unwrap(), legacy);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), config_to_string("", &expected));
+    assert_eq!(read(&path), Ok(expected));
+    std::fs::write(&path, "version = 7").unwrap();
+    let expected_error = format!("error: Version 7 of the alias file format is not supported by this version of Alia, which supports version 1.\n --> {}:1:11\n  |\n1 | version = 7\n  |           ^\n  = help: the file may have been written by a newer version of Alia, so try updating Alia", path.display());
+    assert_eq!(read(&path).map_err(|e| e.to_string()), Err(expected_error.clone()));
+    let layer = read_from_config_file(&path, LayerKind::Global, false).unwrap();
+    assert!(layer.broken);
+    assert_eq!(layer.problems.iter().map(|problem| problem.to_string()).collect::<Vec<_>>(), [expected_error]);
+    std::fs::write(&path, format!("# Mine{END_OF_LINE_SEQUENCE}{legacy}# The end{END_OF_LINE_SEQUENCE}")).unwrap();
+    let layer = read_from_config_file(&path, LayerKind::Global, true).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "version = 1\n\n# Mine\n[aliases.my_alias]\ncommand = \"echo test\"\n\n# The end\n");
+    assert_eq!(layer.source, std::fs::read_to_string(&path).unwrap());
+    assert!(dir.join("cfg.alia.v0.bak.1").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
   }
 
   fn test_cfg_to_string_template<const SizeOfCfg: usize, const NumOfAcceptableResults: usize>(cfg: [(&'static str, &'static str); SizeOfCfg], expected_result: [String; NumOfAcceptableResults]) {
-    let res = config_to_string(&HashMap::from(cfg.map(|x| (x.0.into(), x.1.into()))));
+    let res = config_to_string("", &HashMap::from(cfg.map(|x| (x.0.into(), Alias::new(x.1.into())))));
     if expected_result.iter().any(|x| x == &res) {
       return;
     }
@@ -0,0 +1,95 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Environment variable that, when set, overrides every other way of locating the alias store.
+pub const CONFIG_ENV_VAR: &str = "ALIA_CONFIG";
+
+const CONFIG_DIRECTORY_NAME: &str = "alia";
+const CONFIG_FILE_NAME: &str = "cfg.alia";
+
+/// Works out where the alias store lives. In order of precedence this is the `ALIA_CONFIG` environment variable,
+/// the `--config <path>` flag, `$XDG_CONFIG_HOME/alia/cfg.alia` and finally `~/.config/alia/cfg.alia`
+/// (`%APPDATA%\alia\cfg.alia` on Windows). Returns None if none of these could be determined.
+pub fn resolve_config_path(config_flag: Option<&str>) -> Option<PathBuf> {
+  resolve_config_path_with(config_flag, |name| env::var_os(name))
+}
+
+fn resolve_config_path_with<F: Fn(&str) -> Option<OsString>>(config_flag: Option<&str>, get_env: F) -> Option<PathBuf> {
+  let get_env = |name: &str| get_env(name).filter(|value| !value.is_empty());
+  if let Some(path) = get_env(CONFIG_ENV_VAR) {
+    return Some(path.into());
+  }
+  if let Some(path) = config_flag {
+    return Some(path.into());
+  }
+  let config_directory = if cfg!(windows) {
+    PathBuf::from(get_env("APPDATA")?)
+  } else {
+    // The XDG base directory spec says relative paths in XDG_CONFIG_HOME are invalid and should be ignored.
+    match get_env("XDG_CONFIG_HOME").map(PathBuf::from).filter(|path| path.is_absolute()) {
+      Some(path) => path,
+      None => PathBuf::from(get_env("HOME")?).join(".config"),
+    }
+  };
+  Some(config_directory.join(CONFIG_DIRECTORY_NAME).join(CONFIG_FILE_NAME))
+}
+
+/// Works out the directory an alias with the given cwd setting runs in. A leading `~` stands for the home directory,
+/// and a relative path is relative to the directory of alias_file, the file the alias was defined in.
+pub fn resolve_working_directory(cwd: &str, alias_file: &Path) -> PathBuf {
+  resolve_working_directory_with(cwd, alias_file, |name| env::var_os(name))
+}
+
+fn resolve_working_directory_with<F: Fn(&str) -> Option<OsString>>(cwd: &str, alias_file: &Path, get_env: F) -> PathBuf {
+  let home = get_env(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).filter(|value| !value.is_empty());
+  let path = match (cwd.strip_prefix('~'), home) {
+    (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => PathBuf::from(home).join(rest.trim_start_matches(['/', '\\'])),
+    _ => PathBuf::from(cwd),
+  };
+  if path.is_absolute() {
+    path
+  } else {
+    alias_file.parent().unwrap_or(Path::new("")).join(path)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use std::ffi::OsString;
+  use std::path::PathBuf;
+  use std::path::Path;
+  use super::{resolve_config_path_with, resolve_working_directory_with};
+
+  fn resolve<const NUM_OF_VARS: usize>(config_flag: Option<&str>, vars: [(&'static str, &'static str); NUM_OF_VARS]) -> Option<PathBuf> {
+    let vars: HashMap<&str, &str> = HashMap::from(vars);
+    resolve_config_path_with(config_flag, |name| vars.get(name).map(OsString::from))
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn test_resolve_config_path() {
+    assert_eq!(resolve(None, []), None);
+    assert_eq!(resolve(None, [("HOME", "/home/me")]), Some(PathBuf::from("/home/me/.config/alia/cfg.alia")));
+    assert_eq!(resolve(None, [("HOME", "/home/me"), ("XDG_CONFIG_HOME", "/xdg")]), Some(PathBuf::from("/xdg/alia/cfg.alia")));
+    assert_eq!(resolve(None, [("HOME", "/home/me"), ("XDG_CONFIG_HOME", "relative")]), Some(PathBuf::from("/home/me/.config/alia/cfg.alia")));
+    assert_eq!(resolve(Some("./flag.alia"), [("HOME", "/home/me"), ("XDG_CONFIG_HOME", "/xdg")]), Some(PathBuf::from("./flag.alia")));
+    assert_eq!(resolve(Some("./flag.alia"), [("ALIA_CONFIG", "/env.alia")]), Some(PathBuf::from("/env.alia")));
+    assert_eq!(resolve(None, [("ALIA_CONFIG", ""), ("HOME", "/home/me")]), Some(PathBuf::from("/home/me/.config/alia/cfg.alia")));
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn test_resolve_working_directory() {
+    let resolve = |cwd: &str, home: Option<&'static str>| resolve_working_directory_with(cwd, Path::new("/project/.alia"), |_| home.map(OsString::from));
+    assert_eq!(resolve("/srv/app", Some("/home/me")), PathBuf::from("/srv/app"));
+    assert_eq!(resolve("web", Some("/home/me")), PathBuf::from("/project/web"));
+    assert_eq!(resolve("../shared", None), PathBuf::from("/project/../shared"));
+    assert_eq!(resolve("~", Some("/home/me")), PathBuf::from("/home/me"));
+    assert_eq!(resolve("~/src", Some("/home/me")), PathBuf::from("/home/me/src"));
+    assert_eq!(resolve("~/src", None), PathBuf::from("/project/~/src"));
+    assert_eq!(resolve("~user", Some("/home/me")), PathBuf::from("/project/~user"));
+    assert_eq!(resolve_working_directory_with("web", Path::new("cfg.alia"), |_| None), PathBuf::from("web"));
+  }
+}
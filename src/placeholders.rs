@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub enum PlaceholderErrorCode {
+  /// The placeholder, e.g. `$2`, refers to an argument that was not passed.
+  MissingPositionalArgument(String),
+}
+
+use PlaceholderErrorCode::*;
+
+impl Display for PlaceholderErrorCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MissingPositionalArgument(placeholder) => write!(f, "No argument was passed for the placeholder {placeholder}."),
+    }
+  }
+}
+
+/// Expands the positional placeholders `$1`…`$9`, `$@`, `${1}` and `${1:-default}` in the value of an alias, as well as
+/// the named placeholders `${name}` and `${name:-default}`. Each argument is quoted with quote so whatever runs the
+/// result sees it as a single word. An argument of the form `name=value` fills in the named placeholders called name
+/// instead of a positional one, but only if there are any. A named placeholder that is not filled in is left for the
+/// shell, which falls back to its default. If the value contains no positional placeholders at all, the positional
+/// arguments are appended to the end of it instead. Anything else that starts with `$`, as well as a `$` that is
+/// preceded by a backslash, is left alone for the shell to deal with.
+pub fn expand_placeholders(value: &str, args: &[String], quote: fn(&str) -> String) -> Result<String, PlaceholderErrorCode> {
+  let placeholders = find_placeholders(value);
+  let named: HashMap<&str, &str> = args.iter().filter_map(|arg| named_argument(arg, &placeholders)).collect();
+  let positional: Vec<&String> = args.iter().filter(|arg| named_argument(arg, &placeholders).is_none()).collect();
+  let mut result = String::with_capacity(value.len());
+  let mut used_positional = false;
+  let mut copied = 0;
+  for placeholder in &placeholders {
+    result.push_str(&value[copied..placeholder.start]);
+    copied = placeholder.start + placeholder.len;
+    let text = &value[placeholder.start..copied];
+    match placeholder.position {
+      Position::All => {
+        used_positional = true;
+        result.push_str(&join_args(&positional, quote));
+      }
+      Position::Nth(n) => {
+        used_positional = true;
+        match (positional.get(n - 1), placeholder.default) {
+          (Some(arg), _) => result.push_str(&quote(arg)),
+          (None, Some(default)) => result.push_str(default),
+          (None, None) => return Err(MissingPositionalArgument(text.to_string())),
+        }
+      }
+      Position::Named(name) => match named.get(name) {
+        Some(arg) => result.push_str(&quote(arg)),
+        None => result.push_str(text),
+      },
+    }
+  }
+  result.push_str(&value[copied..]);
+  if !used_positional && !positional.is_empty() {
+    result.push(' ');
+    result.push_str(&join_args(&positional, quote));
+  }
+  Ok(result)
+}
+
+#[derive(PartialEq)]
+enum Position<'a> {
+  All,
+  Nth(usize),
+  Named(&'a str),
+}
+
+struct Placeholder<'a> {
+  /// Where the placeholder starts in the value, in bytes.
+  start: usize,
+  len: usize,
+  position: Position<'a>,
+  default: Option<&'a str>,
+}
+
+/// Splits arg into a name and a value if it is of the form `name=value` and there are named placeholders called name.
+fn named_argument<'a>(arg: &'a str, placeholders: &[Placeholder]) -> Option<(&'a str, &'a str)> {
+  arg.split_once('=').filter(|(name, _)| placeholders.iter().any(|placeholder| placeholder.position == Position::Named(name)))
+}
+
+/// Finds the placeholders in value, in order, skipping the ones preceded by a backslash.
+fn find_placeholders(value: &str) -> Vec<Placeholder<'_>> {
+  let mut result = Vec::new();
+  let mut search = 0;
+  while let Some(found) = value[search..].find('$') {
+    let start = search + found;
+    search = start + 1;
+    if value[..start].ends_with('\\') {
+      continue;
+    }
+    if let Some((len, position, default)) = parse_placeholder(&value[start..]) {
+      result.push(Placeholder { start, len, position, default });
+      search = start + len;
+    }
+  }
+  result
+}
+
+/// Parses the placeholder at the start of slice, which starts with `$`. Returns its length in bytes, which argument it
+/// refers to and its default value, or None if slice does not start with a placeholder.
+fn parse_placeholder(slice: &str) -> Option<(usize, Position<'_>, Option<&str>)> {
+  let bytes = slice.as_bytes();
+  match bytes.get(1)? {
+    b'@' => Some((2, Position::All, None)),
+    c @ b'1'..=b'9' => Some((2, Position::Nth((c - b'0') as usize), None)),
+    b'{' => {
+      let end = slice.find('}')?;
+      let inner = &slice[2..end];
+      let (name, default) = match inner.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (inner, None),
+      };
+      let position = match name {
+        "@" => Position::All,
+        _ if is_placeholder_name(name) => Position::Named(name),
+        _ => match name.parse::<usize>() {
+          Ok(n) if (1..=9).contains(&n) && name.len() == 1 => Position::Nth(n),
+          _ => return None,
+        },
+      };
+      Some((end + 1, position, default))
+    }
+    _ => None,
+  }
+}
+
+/// Whether name can be the name of a named placeholder, which is what a shell variable can be called.
+fn is_placeholder_name(name: &str) -> bool {
+  name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn join_args(args: &[&String], quote: fn(&str) -> String) -> String {
+  args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::shell_words;
+  use super::{expand_placeholders, PlaceholderErrorCode};
+  use PlaceholderErrorCode::*;
+
+  fn expand<const NUM_OF_ARGS: usize>(value: &str, args: [&str; NUM_OF_ARGS]) -> Result<String, PlaceholderErrorCode> {
+    expand_placeholders(value, &args.map(|x| x.to_string()), shell_words::quote)
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn test_expand_placeholders() {
+    assert_eq!(expand("cargo build", []), Ok("cargo build".to_string()));
+    assert_eq!(expand("cargo build", ["--release"]), Ok("cargo build --release".to_string()));
+    assert_eq!(expand("git commit -m $1", ["fix"]), Ok("git commit -m fix".to_string()));
+    assert_eq!(expand("cp $2 $1", ["a", "b"]), Ok("cp b a".to_string()));
+    assert_eq!(expand("echo $@", ["a", "b c"]), Ok("echo a 'b c'".to_string()));
+    assert_eq!(expand("echo $@", []), Ok("echo ".to_string()));
+    assert_eq!(expand("git push origin ${1:-main}", []), Ok("git push origin main".to_string()));
+    assert_eq!(expand("git push origin ${1:-main}", ["dev"]), Ok("git push origin dev".to_string()));
+    assert_eq!(expand("echo ${1}x", ["a"]), Ok("echo ax".to_string()));
+    assert_eq!(expand("echo $HOME ${HOME:-/} \\$1 $$", ["a"]), Ok("echo $HOME ${HOME:-/} \\$1 $$ a".to_string()));
+    assert_eq!(expand("echo $1 $3", ["a"]), Err(MissingPositionalArgument("$3".to_string())));
+    assert_eq!(expand("echo ${2}", ["a"]), Err(MissingPositionalArgument("${2}".to_string())));
+    assert_eq!(expand("echo $1", ["it's"]), Ok("echo 'it'\\''s'".to_string()));
+    // A named placeholder is filled in by a name=value argument, and otherwise left for the shell to apply its default.
+    assert_eq!(expand("git push ${remote:-origin} ${branch}", ["branch=my fix"]), Ok("git push ${remote:-origin} 'my fix'".to_string()));
+    assert_eq!(expand("git push ${remote:-origin} $1", ["x", "remote=up"]), Ok("git push up x".to_string()));
+    assert_eq!(expand("make ${target:-all}", ["CC=clang", "target=test"]), Ok("make test CC=clang".to_string()));
+    assert_eq!(expand("echo ${x/a/b} ${#x} \\${name}", ["name=a"]), Ok("echo ${x/a/b} ${#x} \\${name} name=a".to_string()));
+  }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
+use crate::shell_words;
+use crate::shells::Shell;
+
+/// What runs the command of an alias, as chosen by its `shell` field.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Interpreter {
+  Sh,
+  Cmd,
+  Bash,
+  Zsh,
+  Fish,
+  Pwsh,
+  Python,
+  /// No interpreter at all: the command is split into words like a POSIX shell would and the first one is run, as with
+  /// `--exec`.
+  Exec,
+}
+
+impl Interpreter {
+  /// The names the `shell` field of an alias accepts.
+  pub const NAMES: &'static [&'static str] = &["sh", "cmd", "bash", "zsh", "fish", "pwsh", "powershell", "python", "exec"];
+
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "sh" => Some(Interpreter::Sh),
+      "cmd" => Some(Interpreter::Cmd),
+      "bash" => Some(Interpreter::Bash),
+      "zsh" => Some(Interpreter::Zsh),
+      "fish" => Some(Interpreter::Fish),
+      "pwsh" | "powershell" => Some(Interpreter::Pwsh),
+      "python" => Some(Interpreter::Python),
+      "exec" => Some(Interpreter::Exec),
+      _ => None,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Interpreter::Sh => "sh",
+      Interpreter::Cmd => "cmd",
+      Interpreter::Bash => "bash",
+      Interpreter::Zsh => "zsh",
+      Interpreter::Fish => "fish",
+      Interpreter::Pwsh => "pwsh",
+      Interpreter::Python => "python",
+      Interpreter::Exec => "exec",
+    }
+  }
+
+  /// The interpreter of aliases that do not choose one.
+  pub fn system() -> Self {
+    if cfg!(windows) { Interpreter::Cmd } else { Interpreter::Sh }
+  }
+
+  /// Quotes an argument that fills in a placeholder so that this interpreter reads it back as a single word, or as a
+  /// single string literal in the case of Python.
+  pub fn quote(self) -> fn(&str) -> String {
+    match self {
+      Interpreter::Sh | Interpreter::Bash | Interpreter::Zsh | Interpreter::Exec => shell_words::quote,
+      Interpreter::Cmd => |arg| format!("\"{}\"", arg.replace('"', "\"\"")),
+      Interpreter::Fish => |arg| Shell::Fish.quote(arg),
+      Interpreter::Pwsh => |arg| Shell::Powershell.quote(arg),
+      Interpreter::Python => python_quote,
+    }
+  }
+
+  /// Builds a command that has this interpreter run script. The script is passed as a single argument, so quotes,
+  /// pipes and redirects in it work as they would when typed into the interpreter. Exec has no interpreter to pass the
+  /// script to, so its script is run by the system shell.
+  pub fn command(self, script: &str) -> Command {
+    let (program, args): (&str, &[&str]) = match self {
+      Interpreter::Sh => ("sh", &["-c"]),
+      Interpreter::Cmd => ("cmd", &["/C"]),
+      Interpreter::Bash => ("bash", &["-c"]),
+      Interpreter::Zsh => ("zsh", &["-c"]),
+      Interpreter::Fish => ("fish", &["-c"]),
+      Interpreter::Pwsh => ("pwsh", &["-NoProfile", "-Command"]),
+      Interpreter::Python => (if cfg!(windows) { "python" } else { "python3" }, &["-c"]),
+      Interpreter::Exec => return Interpreter::system().command(script),
+    };
+    let mut command = Command::new(program);
+    command.args(args);
+    // cmd does its own parsing of everything after /C, so Rust's quoting for the usual argv conventions would get in the way.
+    #[cfg(windows)]
+    if self == Interpreter::Cmd {
+      std::os::windows::process::CommandExt::raw_arg(&mut command, script);
+      return command;
+    }
+    command.arg(script);
+    command
+  }
+}
+
+/// Quotes s as a Python string literal.
+fn python_quote(s: &str) -> String {
+  let mut result = String::with_capacity(s.len() + 2);
+  result.push('\'');
+  for c in s.chars() {
+    match c {
+      '\\' => result.push_str("\\\\"),
+      '\'' => result.push_str("\\'"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+      c => result.push(c),
+    }
+  }
+  result.push('\'');
+  result
+}
+
+/// What an executed alias runs.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Program {
+  /// A script for the interpreter of the alias, with the placeholders of the alias filled in.
+  Shell(Interpreter, String),
+  /// The words the command of the alias was split into, as with `--exec`, the first of which is the program.
+  Words(Vec<String>),
+}
+
+/// An alias that was asked to be executed. Aliases are only run once every command of the invocation has been checked
+/// and the changes the others made have been saved.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Execution {
+  pub name: String,
+  pub program: Program,
+  /// The directory the alias runs in, or None for the current one.
+  pub cwd: Option<PathBuf>,
+  /// Environment variables that are set for the alias, on top of the ones Alia was started with.
+  pub env: BTreeMap<String, String>,
+  /// Environment variables that are removed for the alias.
+  pub unset_env: Vec<String>,
+  /// Whether Alia goes on without waiting for the alias to finish.
+  pub detach: bool,
+  /// The number of the argument the alias was named at, for error messages.
+  pub arg: usize,
+}
+
+impl Execution {
+  /// Builds the command that runs the alias, in its directory and with its environment.
+  pub fn command(&self) -> Command {
+    let mut command = match &self.program {
+      Program::Shell(interpreter, script) => interpreter.command(script),
+      Program::Words(words) => {
+        let mut command = Command::new(&words[0]);
+        command.args(&words[1..]);
+        command
+      }
+    };
+    if let Some(cwd) = &self.cwd {
+      command.current_dir(cwd);
+    }
+    for variable in &self.unset_env {
+      command.env_remove(variable);
+    }
+    command.envs(&self.env);
+    command
+  }
+}
+
+/// How a child process that did not exit successfully ended.
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub enum ChildFailure {
+  ExitCode(i32),
+  Signal(i32),
+}
+
+use ChildFailure::*;
+
+impl ChildFailure {
+  /// The status Alia itself should exit with, following the shell convention of 128 + n for a death by signal n.
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      ExitCode(code) => *code,
+      Signal(signal) => 128 + signal,
+    }
+  }
+}
+
+impl Display for ChildFailure {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ExitCode(code) => write!(f, "exited with status code {code}"),
+      Signal(signal) => write!(f, "was killed by signal {signal}"),
+    }
+  }
+}
+
+/// Waits for child to finish. While waiting, SIGINT and SIGTERM sent to Alia are passed on to the child instead of
+/// killing Alia. Returns the outer error if waiting failed and the inner one if the child did not exit successfully.
+pub fn wait_for_child(mut child: Child) -> io::Result<Result<(), ChildFailure>> {
+  #[cfg(unix)]
+  let _relay = relay::SignalRelay::install(child.id());
+  let status = child.wait()?;
+  Ok(status_to_result(status))
+}
+
+fn status_to_result(status: ExitStatus) -> Result<(), ChildFailure> {
+  if status.success() {
+    return Ok(());
+  }
+  #[cfg(unix)]
+  if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+    return Err(Signal(signal));
+  }
+  Err(ExitCode(status.code().unwrap_or(1)))
+}
+
+#[cfg(unix)]
+mod relay {
+  use std::mem::MaybeUninit;
+  use std::ptr;
+  use std::sync::atomic::{AtomicI32, Ordering};
+  use libc::{c_int, c_void, sigaction, siginfo_t};
+
+  /// The si_code of a signal that was sent by another process through kill().
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  const SI_USER: c_int = 0;
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  const SI_USER: c_int = 0x10001;
+
+  const RELAYED_SIGNALS: [c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+  static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+  extern "C" fn relay_signal(signal: c_int, info: *mut siginfo_t, _context: *mut c_void) {
+    // Signals typed at the terminal already reach the child, since it is in our process group. Only the ones another
+    // process sent to Alia directly have to be passed on.
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    if pid > 0 && !info.is_null() && unsafe { (*info).si_code } == SI_USER {
+      unsafe { libc::kill(pid, signal) };
+    }
+  }
+
+  /// Relays signals to the child for as long as it is alive and restores the previous handlers when dropped.
+  pub struct SignalRelay {
+    previous: Vec<(c_int, sigaction)>,
+  }
+
+  impl SignalRelay {
+    pub fn install(child_pid: u32) -> Self {
+      CHILD_PID.store(child_pid as i32, Ordering::SeqCst);
+      let mut previous = Vec::with_capacity(RELAYED_SIGNALS.len());
+      for signal in RELAYED_SIGNALS {
+        unsafe {
+          let mut action: sigaction = MaybeUninit::zeroed().assume_init();
+          action.sa_sigaction = relay_signal as *const () as usize;
+          action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+          libc::sigemptyset(&mut action.sa_mask);
+          let mut old: sigaction = MaybeUninit::zeroed().assume_init();
+          if libc::sigaction(signal, &action, &mut old) == 0 {
+            previous.push((signal, old));
+          }
+        }
+      }
+      Self { previous }
+    }
+  }
+
+  impl Drop for SignalRelay {
+    fn drop(&mut self) {
+      for (signal, old) in &self.previous {
+        unsafe { libc::sigaction(*signal, old, ptr::null_mut()) };
+      }
+      CHILD_PID.store(0, Ordering::SeqCst);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+  use std::process::Command;
+  use super::{ChildFailure, Execution, Interpreter, Program, python_quote, wait_for_child};
+  use ChildFailure::*;
+
+  fn run(script: &str) -> Result<(), ChildFailure> {
+    wait_for_child(Command::new("sh").args(["-c", script]).spawn().unwrap()).unwrap()
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn test_wait_for_child() {
+    assert_eq!(run("exit 0"), Ok(()));
+    assert_eq!(run("exit 3"), Err(ExitCode(3)));
+    assert_eq!(run("kill -TERM $$"), Err(Signal(15)));
+    assert_eq!(Signal(2).exit_code(), 130);
+    assert_eq!(ExitCode(3).exit_code(), 3);
+  }
+
+  #[test]
+  fn test_interpreters() {
+    for name in Interpreter::NAMES {
+      assert!(Interpreter::parse(name).is_some(), "{name}");
+    }
+    assert_eq!(Interpreter::parse("powershell"), Some(Interpreter::Pwsh));
+    assert_eq!(Interpreter::parse("perl"), None);
+    assert_eq!(Interpreter::Sh.quote()("it's"), "'it'\\''s'");
+    assert_eq!(Interpreter::Fish.quote()("it's"), "'it\\'s'");
+    assert_eq!(Interpreter::Pwsh.quote()("it's"), "'it''s'");
+    assert_eq!(Interpreter::Cmd.quote()("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(python_quote("it's\\\n\u{7}é"), "'it\\'s\\\\\\n\\u0007é'");
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn test_execution_environment() {
+    let directory = std::env::temp_dir();
+    let execution = Execution {
+      name: "x".to_string(),
+      program: Program::Shell(Interpreter::Sh, "test \"$(pwd -P)\" = \"$(cd \"$1\" && pwd -P)\" && test \"$ALIA_TEST_SET\" = 1 && test -z \"$HOME\"".to_string()),
+      cwd: Some(directory.clone()),
+      env: BTreeMap::from([("ALIA_TEST_SET".to_string(), "1".to_string())]),
+      unset_env: vec!["HOME".to_string()],
+      detach: false,
+      arg: 2,
+    };
+    let mut command = execution.command();
+    command.arg("sh").arg(&directory);
+    assert_eq!(wait_for_child(command.spawn().unwrap()).unwrap(), Ok(()));
+  }
+}
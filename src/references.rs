@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+
+/// How many aliases deep references may go, counting the alias that was run, before expanding them is given up on.
+pub const MAX_REFERENCE_DEPTH: usize = 16;
+
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub enum ReferenceErrorCode {
+  /// The aliases reference each other in a circle. Holds their names in the order they reference each other, starting
+  /// and ending with the same one.
+  ReferenceCycle(Vec<String>),
+  /// The references go more than MAX_REFERENCE_DEPTH aliases deep. Holds the names of the aliases up to that point.
+  ReferencesTooDeep(Vec<String>),
+  /// An alias references one that runs with a different interpreter, in a different directory or with a different
+  /// environment than the alias that was run, so its command cannot be run as part of it. Holds the names of the
+  /// aliases from the one that was run to that one.
+  ConflictingSettings(Vec<String>),
+}
+
+use ReferenceErrorCode::*;
+
+impl Display for ReferenceErrorCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ReferenceCycle(path) => write!(f, "The aliases reference each other in a cycle: {}.", path.join(" -> ")),
+      ReferencesTooDeep(path) => write!(f, "The references go more than {MAX_REFERENCE_DEPTH} aliases deep: {}.", path.join(" -> ")),
+      ConflictingSettings(path) => write!(f, "{} runs with a different shell, directory or environment than {}, so it cannot be referenced from it: {}. Run it with an alia e of its own instead.", path[path.len() - 1], path[0], path.join(" -> ")),
+    }
+  }
+}
+
+/// Replaces every `@name` in command, the command of the alias called name, with the command of the alias it refers
+/// to, whose own references are expanded first. lookup returns the command of an alias. A reference is only expanded
+/// if it starts a word and an alias with its name exists, so `user@host`, `\@name` and `npm i @types/node` are left
+/// alone. A command made of several commands is put in parentheses, so `@build && @test` runs all of build first.
+/// compatible tells whether an alias runs the same way as name apart from its command, which every alias it references
+/// has to.
+pub fn expand_references<'a>(name: &str, command: &str, lookup: impl Fn(&str) -> Option<&'a str>, compatible: impl Fn(&str) -> bool) -> Result<String, ReferenceErrorCode> {
+  expand(command, &mut vec![name.to_string()], &lookup, &compatible)
+}
+
+/// Expands the references in command, which path is the chain of aliases that led to.
+fn expand<'a>(command: &str, path: &mut Vec<String>, lookup: &impl Fn(&str) -> Option<&'a str>, compatible: &impl Fn(&str) -> bool) -> Result<String, ReferenceErrorCode> {
+  let mut result = String::with_capacity(command.len());
+  // Everything before copied has been dealt with, and everything before search has been searched for references.
+  let mut copied = 0;
+  let mut search = 0;
+  while let Some(found) = command[search..].find('@') {
+    let at = search + found;
+    search = at + 1;
+    let starts_word = command[..at].chars().next_back().is_none_or(|c| !is_name_char(c) && !matches!(c, '\\' | '@' | '$'));
+    let name = reference_name(&command[at + 1..]);
+    let Some(referenced) = Some(name).filter(|name| starts_word && !name.is_empty()).and_then(lookup) else {
+      continue;
+    };
+    if let Some(start) = path.iter().position(|earlier| earlier == name) {
+      let mut cycle = path[start..].to_vec();
+      cycle.push(name.to_string());
+      return Err(ReferenceCycle(cycle));
+    }
+    if path.len() == MAX_REFERENCE_DEPTH {
+      let mut too_deep = path.clone();
+      too_deep.push(name.to_string());
+      return Err(ReferencesTooDeep(too_deep));
+    }
+    path.push(name.to_string());
+    if !compatible(name) {
+      return Err(ConflictingSettings(path.clone()));
+    }
+    let expanded = expand(referenced, path, lookup, compatible)?;
+    path.pop();
+    result.push_str(&command[copied..at]);
+    result.push_str(&group(referenced, &expanded));
+    copied = at + 1 + name.len();
+    search = copied;
+  }
+  result.push_str(&command[copied..]);
+  Ok(result)
+}
+
+fn is_name_char(c: char) -> bool {
+  c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// The name a reference refers to, from the text right after its `@`. A dot at the end ends a sentence rather than
+/// being part of the name.
+fn reference_name(slice: &str) -> &str {
+  slice[..slice.find(|c| !is_name_char(c)).unwrap_or(slice.len())].trim_end_matches('.')
+}
+
+/// Puts expanded, the expanded form of command, in parentheses if command is made of several commands, so the operators
+/// around a reference apply to all of it. A command that is a single reference is grouped by the expansion of that one
+/// already. A command that spans several lines or has a comment in it gets the closing parenthesis on a line of its own.
+fn group(command: &str, expanded: &str) -> String {
+  let expanded = expanded.trim();
+  if !command.trim().contains([';', '&', '|', '\n', '#']) {
+    expanded.to_string()
+  } else if expanded.contains(['\n', '#']) {
+    format!("(\n{expanded}\n)")
+  } else {
+    format!("({expanded})")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use super::{expand_references, MAX_REFERENCE_DEPTH, ReferenceErrorCode};
+  use ReferenceErrorCode::*;
+
+  fn expand<const SIZE_OF_ALIASES: usize>(name: &str, aliases: [(&str, &str); SIZE_OF_ALIASES]) -> Result<String, ReferenceErrorCode> {
+    let aliases: HashMap<&str, &str> = HashMap::from(aliases);
+    expand_references(name, aliases[name], |name| aliases.get(name).copied(), |_| true)
+  }
+
+  fn path(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+
+  #[test]
+  fn test_expand_references() {
+    let aliases = [("release", "@build && @test && @publish"), ("build", "cargo build --release"), ("test", "cargo test; ./e2e.sh"), ("publish", "@db.migrate"), ("db.migrate", "# migrate\n./migrate.sh")];
+    assert_eq!(expand("release", aliases), Ok("cargo build --release && (cargo test; ./e2e.sh) && (\n# migrate\n./migrate.sh\n)".to_string()));
+    assert_eq!(expand("build", aliases), Ok("cargo build --release".to_string()));
+    let aliases = [("a", "ssh me@build \\@build $@build @missing @build. @build"), ("build", "make")];
+    assert_eq!(expand("a", aliases), Ok("ssh me@build \\@build $@build @missing make. make".to_string()));
+  }
+
+  #[test]
+  fn test_reference_errors() {
+    assert_eq!(expand("a", [("a", "@b"), ("b", "x && @c"), ("c", "@a")]), Err(ReferenceCycle(path(&["a", "b", "c", "a"]))));
+    assert_eq!(expand("a", [("a", "@b"), ("b", "@b")]), Err(ReferenceCycle(path(&["b", "b"]))));
+    let names: Vec<String> = (0..=MAX_REFERENCE_DEPTH).map(|n| format!("a{n}")).collect();
+    let commands: Vec<String> = (0..=MAX_REFERENCE_DEPTH).map(|n| format!("@a{}", n + 1)).collect();
+    let aliases: HashMap<&str, &str> = names.iter().map(String::as_str).zip(commands.iter().map(String::as_str)).collect();
+    let result = expand_references("a0", aliases["a0"], |name| aliases.get(name).copied(), |_| true);
+    assert_eq!(result, Err(ReferencesTooDeep(names[..=MAX_REFERENCE_DEPTH].to_vec())));
+    let aliases = HashMap::from([("release", "@build && @test"), ("build", "make"), ("test", "@check"), ("check", "import this")]);
+    let result = expand_references("release", aliases["release"], |name| aliases.get(name).copied(), |name| name != "check");
+    assert_eq!(result, Err(ConflictingSettings(path(&["release", "test", "check"]))));
+  }
+}
@@ -0,0 +1,673 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use toml_edit::{Array, Document, DocumentMut, InlineTable, Item, Table, TableLike, Value, value};
+use crate::diagnostics::Span;
+use crate::process::Interpreter;
+use crate::store::{Alias, is_valid_alias_name, Settings};
+
+/// The version of the alias file format this build reads and writes.
+pub const CURRENT_VERSION: i64 = 1;
+
+const ALIAS_FIELDS: &[&str] = &["command", "description", "tags", "cwd", "env", "unset_env", "shell"];
+
+const SETTINGS: &[&str] = &["backups", "autocorrect"];
+
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub enum SchemaErrorCode {
+  InvalidToml(String),
+  MissingVersion,
+  UnsupportedVersion(String),
+  AliasesNotATable,
+  AliasNotATable(String),
+  /// The name of the alias has a namespace segment that is empty, as in `db..migrate`.
+  InvalidAliasName(String),
+  /// The alias has no command but holds the table of another alias, which happens when the name of an alias in a
+  /// namespace is written without quotes, as in `[aliases.db.migrate]`. Holds the name and the field.
+  UnquotedNamespace(String, String),
+  MissingCommand(String),
+  /// The field of the alias has the wrong type. Holds the name of the alias, the field and the type it should have.
+  InvalidFieldType(String, String, &'static str),
+  UnknownField(String, String),
+  /// The shell field of the alias names an interpreter Alia does not know. Holds the name of the alias and the shell.
+  UnknownShell(String, String),
+  SettingsNotATable,
+  /// The setting has the wrong type. Holds the name of the setting and the type it should have.
+  InvalidSetting(String, &'static str),
+  UnknownSetting(String),
+}
+
+use SchemaErrorCode::*;
+
+impl Display for SchemaErrorCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      InvalidToml(error) => write!(f, "The file is not valid TOML: {error}"),
+      MissingVersion => write!(f, "The file has no version field. Add `version = {CURRENT_VERSION}` at the top."),
+      UnsupportedVersion(version) => write!(f, "Version {version} of the alias file format is not supported by this version of Alia, which supports version {CURRENT_VERSION}."),
+      AliasesNotATable => write!(f, "The aliases field has to be a table."),
+      AliasNotATable(name) => write!(f, "The alias {name} has to be a table."),
+      InvalidAliasName(name) => write!(f, "{name} cannot be the name of an alias, as the parts it is split into by dots must not be empty and the last one cannot be *."),
+      UnquotedNamespace(name, field) => write!(f, "The alias {name} has no command but a table called {field}, so {name}.{field} was probably meant to be the name of an alias."),
+      MissingCommand(name) => write!(f, "The alias {name} has no command, or its command is empty."),
+      InvalidFieldType(name, field, expected) => write!(f, "The field {field} of the alias {name} has to be {expected}."),
+      UnknownField(name, field) => write!(f, "The alias {name} has a field called {field}, which Alia does not know."),
+      UnknownShell(name, shell) => write!(f, "The alias {name} is meant to be run by {shell}, which Alia does not know."),
+      SettingsNotATable => write!(f, "The settings field has to be a table."),
+      InvalidSetting(name, expected) => write!(f, "The setting {name} has to be {expected}."),
+      UnknownSetting(name) => write!(f, "There is a setting called {name}, which Alia does not know."),
+    }
+  }
+}
+
+impl SchemaErrorCode {
+  /// A suggestion for fixing the error, if there is anything more to say than the message does.
+  pub fn help(&self) -> Option<String> {
+    match self {
+      MissingVersion => Some(format!("add `version = {CURRENT_VERSION}` at the top of the file")),
+      UnsupportedVersion(_) => Some("the file may have been written by a newer version of Alia, so try updating Alia".to_string()),
+      AliasesNotATable | AliasNotATable(_) => Some("write every alias as a table, such as [aliases.build] followed by command = \"cargo build\"".to_string()),
+      InvalidAliasName(_) => Some("dots separate namespaces, as in db.migrate, so a name cannot start or end with one or have two in a row".to_string()),
+      UnquotedNamespace(name, field) => Some(format!("quote names that contain dots, as in [aliases.\"{name}.{field}\"]")),
+      MissingCommand(_) => Some("add a line such as command = \"echo hello\" to the alias".to_string()),
+      UnknownField(..) => Some(format!("the fields an alias can have are {}", ALIAS_FIELDS.join(", "))),
+      UnknownShell(..) => Some(format!("the shells Alia can run aliases with are {}", Interpreter::NAMES.join(", "))),
+      SettingsNotATable => Some("write the settings as a table, such as [settings] followed by backups = 10".to_string()),
+      UnknownSetting(_) => Some(format!("the settings Alia knows are {}", SETTINGS.join(", "))),
+      InvalidToml(_) | InvalidFieldType(..) | InvalidSetting(..) => None,
+    }
+  }
+}
+
+/// A SchemaErrorCode along with the part of the file it is about, if it is about a particular part.
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub struct SchemaError {
+  pub code: SchemaErrorCode,
+  pub span: Option<Span>,
+}
+
+impl SchemaError {
+  fn new(code: SchemaErrorCode, span: Option<Range<usize>>) -> Self {
+    Self { code, span: span.map(Span::from) }
+  }
+}
+
+impl Display for SchemaError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.code)
+  }
+}
+
+/// Whether contents is in the format Alia used before alias files were versioned, where every line is a quoted name,
+/// an equal sign and a quoted value. Leaving comments aside, files in that format start with such a line and have no
+/// `version` key, while versioned ones start with a key, which may be quoted too, or a table header.
+pub fn is_legacy_format(contents: &str) -> bool {
+  let mut lines = contents.lines().map(str::trim_start).filter(|line| !line.is_empty() && !line.starts_with('#'));
+  lines.next().is_some_and(is_legacy_entry) && !contents.lines().any(is_version_key)
+}
+
+/// Whether line starts like an entry of the legacy format: a quoted name, an equal sign and the quote a value starts with.
+fn is_legacy_entry(line: &str) -> bool {
+  let Some(rest) = line.strip_prefix('"') else {
+    return false;
+  };
+  let mut chars = rest.char_indices();
+  while let Some((index, c)) = chars.next() {
+    match c {
+      '\\' => { chars.next(); }
+      '"' => return rest[index + 1..].trim_start().strip_prefix('=').is_some_and(|value| value.trim_start().starts_with('"')),
+      _ => {}
+    }
+  }
+  false
+}
+
+/// Whether line sets the version of a versioned alias file. An alias of the legacy format called version has a quoted
+/// value instead of a number.
+fn is_version_key(line: &str) -> bool {
+  let line = line.trim_start();
+  let rest = line.strip_prefix("\"version\"").or_else(|| line.strip_prefix("version"));
+  rest.and_then(|rest| rest.trim_start().strip_prefix('=')).is_some_and(|value| value.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// What parse_document could make of an alias file.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct ParsedDocument {
+  pub aliases: HashMap<String, Alias>,
+  /// Every problem that was found, in the order they were found in.
+  pub errors: Vec<SchemaError>,
+  /// Whether parts of the file other than single aliases could not be understood. Writing the file back would lose
+  /// those parts, while update_document keeps aliases that were skipped as they are.
+  pub broken: bool,
+  pub settings: Settings,
+}
+
+/// Parses an alias file in the versioned TOML format, which looks like this:
+///
+/// ```toml
+/// version = 1
+///
+/// [aliases.build]
+/// command = "cargo build"
+/// description = "Builds the project"
+/// tags = ["rust"]
+/// cwd = "~/projects/alia"
+/// shell = "bash"
+/// env = { RUST_LOG = "debug" }
+///
+/// [settings]
+/// backups = 10
+/// autocorrect = true
+/// ```
+///
+/// Whatever cannot be made sense of is skipped rather than stopping there. A line that is not valid TOML is left out
+/// and parsing starts over without it, and an alias that is not valid is left out while the others are still loaded.
+pub fn parse_document(contents: &str) -> ParsedDocument {
+  let mut result = ParsedDocument::default();
+  if contents.trim().is_empty() {
+    return result;
+  }
+  let mut text = contents.to_string();
+  // Unlike DocumentMut, Document remembers where in contents everything came from, so errors can point there.
+  let document = loop {
+    match Document::parse(text.clone()) {
+      Ok(document) => break document,
+      Err(e) => {
+        result.broken = true;
+        result.errors.push(SchemaError::new(InvalidToml(e.message().trim_end().to_string()), e.span()));
+        if !e.span().is_some_and(|span| blank_line(&mut text, span.start)) {
+          return result;
+        }
+      }
+    }
+  };
+  let Some(version) = document.get("version") else {
+    result.broken = true;
+    result.errors.push(SchemaError::new(MissingVersion, None));
+    return result;
+  };
+  if version.as_integer() != Some(CURRENT_VERSION) {
+    result.broken = true;
+    result.errors.push(SchemaError::new(UnsupportedVersion(version.to_string().trim().to_string()), version.span()));
+    return result;
+  }
+  if let Some(settings) = document.get("settings") {
+    parse_settings(settings, &mut result);
+  }
+  let Some(aliases) = document.get("aliases") else {
+    return result;
+  };
+  let Some(aliases_table) = aliases.as_table_like() else {
+    result.broken = true;
+    result.errors.push(SchemaError::new(AliasesNotATable, aliases.span()));
+    return result;
+  };
+  for (name, item) in aliases_table.iter() {
+    if !is_valid_alias_name(name) {
+      let key_span = aliases_table.get_key_value(name).and_then(|(key, _)| key.span());
+      result.errors.push(SchemaError::new(InvalidAliasName(name.to_string()), key_span));
+      continue;
+    }
+    let alias = item.as_table_like().ok_or(SchemaError::new(AliasNotATable(name.to_string()), item.span())).and_then(|table| parse_alias(name, table, item.span()));
+    match alias {
+      Ok(alias) => { result.aliases.insert(name.to_string(), alias); }
+      Err(error) => result.errors.push(error),
+    }
+  }
+  result
+}
+
+/// Reads the `[settings]` table into result. Settings that are not valid are left at their defaults.
+fn parse_settings(settings: &Item, result: &mut ParsedDocument) {
+  let Some(table) = settings.as_table_like() else {
+    result.errors.push(SchemaError::new(SettingsNotATable, settings.span()));
+    return;
+  };
+  for (name, item) in table.iter() {
+    match name {
+      "backups" => match item.as_integer().and_then(|count| usize::try_from(count).ok()) {
+        Some(count) => result.settings.backups = Some(count),
+        None => result.errors.push(SchemaError::new(InvalidSetting(name.to_string(), "a number that is not negative"), item.span())),
+      },
+      "autocorrect" => match item.as_bool() {
+        Some(autocorrect) => result.settings.autocorrect = autocorrect,
+        None => result.errors.push(SchemaError::new(InvalidSetting(name.to_string(), "true or false"), item.span())),
+      },
+      _ => {
+        let key_span = table.get_key_value(name).and_then(|(key, _)| key.span());
+        result.errors.push(SchemaError::new(UnknownSetting(name.to_string()), key_span));
+      }
+    }
+  }
+}
+
+/// Replaces the line of text that offset is on with spaces, so the rest of text can be parsed without it while
+/// everything keeps its position. Returns false if the line holds nothing but whitespace already.
+fn blank_line(text: &mut String, offset: usize) -> bool {
+  let mut offset = offset.min(text.len());
+  while !text.is_char_boundary(offset) {
+    offset -= 1;
+  }
+  let start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+  let end = text[offset..].find('\n').map_or(text.len(), |index| offset + index);
+  if text[start..end].trim().is_empty() {
+    return false;
+  }
+  let blank = " ".repeat(end - start);
+  text.replace_range(start..end, &blank);
+  true
+}
+
+/// Parses the table of the alias called name. span is where the table starts, which errors about fields that are
+/// missing point at.
+fn parse_alias(name: &str, table: &dyn TableLike, span: Option<Range<usize>>) -> Result<Alias, SchemaError> {
+  let invalid_type = |field: &str, expected: &'static str, span: Option<Range<usize>>| SchemaError::new(InvalidFieldType(name.to_string(), field.to_string(), expected), span);
+  let string_field = |field: &str| match table.get(field) {
+    None => Ok(None),
+    Some(item) => item.as_str().map(|s| Some(s.to_string())).ok_or(invalid_type(field, "a string", item.span())),
+  };
+  let command = string_field("command")?.filter(|command| !command.is_empty());
+  if command.is_none() {
+    if let Some((field, _)) = table.iter().find(|(field, item)| *field != "env" && item.is_table_like()) {
+      let key_span = table.get_key_value(field).and_then(|(key, _)| key.span());
+      return Err(SchemaError::new(UnquotedNamespace(name.to_string(), field.to_string()), key_span.or(span)));
+    }
+  }
+  let mut alias = Alias::new(command.ok_or(SchemaError::new(MissingCommand(name.to_string()), table.get("command").and_then(Item::span).or(span)))?);
+  alias.description = string_field("description")?;
+  alias.cwd = string_field("cwd")?;
+  alias.shell = string_field("shell")?;
+  if let Some(shell) = alias.shell.as_deref().filter(|shell| Interpreter::parse(shell).is_none()) {
+    return Err(SchemaError::new(UnknownShell(name.to_string(), shell.to_string()), table.get("shell").and_then(Item::span)));
+  }
+  if let Some(tags) = table.get("tags") {
+    let tags = tags.as_array().ok_or(invalid_type("tags", "an array of strings", tags.span()))?;
+    for tag in tags {
+      alias.tags.push(tag.as_str().ok_or(invalid_type("tags", "an array of strings", tag.span()))?.to_string());
+    }
+  }
+  if let Some(unset_env) = table.get("unset_env") {
+    let unset_env = unset_env.as_array().ok_or(invalid_type("unset_env", "an array of strings", unset_env.span()))?;
+    for variable in unset_env {
+      alias.unset_env.push(variable.as_str().ok_or(invalid_type("unset_env", "an array of strings", variable.span()))?.to_string());
+    }
+  }
+  if let Some(env) = table.get("env") {
+    let env = env.as_table_like().ok_or(invalid_type("env", "a table of strings", env.span()))?;
+    for (variable, item) in env.iter() {
+      alias.env.insert(variable.to_string(), item.as_str().ok_or(invalid_type("env", "a table of strings", item.span()))?.to_string());
+    }
+  }
+  for (field, _) in table.iter() {
+    if !ALIAS_FIELDS.contains(&field) {
+      let key_span = table.get_key_value(field).and_then(|(key, _)| key.span());
+      return Err(SchemaError::new(UnknownField(name.to_string(), field.to_string()), key_span));
+    }
+  }
+  Ok(alias)
+}
+
+/// An entry of an alias file in the legacy format, along with the comments that belong to it.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct LegacyEntry {
+  /// The comment lines right above the entry, including their `#`.
+  pub comments: Vec<String>,
+  pub name: String,
+  pub value: String,
+  /// A comment on the same line as the entry.
+  pub trailing_comment: Option<String>,
+}
+
+/// Renders the entries of a legacy alias file as a versioned TOML document. The entries keep their order and their
+/// comments, and comments at the very end of the legacy file end up at the end of the document.
+pub fn legacy_to_document(entries: &[LegacyEntry], trailing_comments: &[String]) -> DocumentMut {
+  let mut document = DocumentMut::new();
+  document["version"] = value(CURRENT_VERSION);
+  let mut table = Table::new();
+  table.set_implicit(true);
+  for entry in entries {
+    let mut alias_table = alias_to_table(&Alias::new(entry.value.clone()));
+    let comments: String = entry.comments.iter().map(|comment| format!("{comment}\n")).collect();
+    alias_table.decor_mut().set_prefix(format!("\n{comments}"));
+    if let (Some(comment), Some(command)) = (&entry.trailing_comment, alias_table["command"].as_value_mut()) {
+      command.decor_mut().set_suffix(format!(" {comment}"));
+    }
+    table.insert(&entry.name, Item::Table(alias_table));
+  }
+  document["aliases"] = Item::Table(table);
+  if !trailing_comments.is_empty() {
+    let comments: String = trailing_comments.iter().map(|comment| format!("{comment}\n")).collect();
+    document.set_trailing(format!("\n{comments}"));
+  }
+  document
+}
+
+/// Applies aliases to the alias file whose contents are source. Entries that did not change are left exactly as they
+/// were, changed entries are edited in place and new ones are added at the end, so comments, ordering and spacing
+/// survive the write. If source is empty or not a versioned document, a fresh document is rendered instead.
+pub fn update_document(source: &str, aliases: &HashMap<String, Alias>) -> String {
+  let mut document = match source.parse::<DocumentMut>() {
+    Ok(document) if !source.trim().is_empty() && !is_legacy_format(source) => document,
+    _ => return aliases_to_document(aliases).to_string(),
+  };
+  if document.get("version").is_none() {
+    document["version"] = value(CURRENT_VERSION);
+  }
+  if !document.get("aliases").is_some_and(|item| item.is_table_like()) {
+    let mut table = Table::new();
+    table.set_implicit(true);
+    document["aliases"] = Item::Table(table);
+  }
+  let aliases_item = &mut document["aliases"];
+  let inline = aliases_item.is_inline_table();
+  let Some(table) = aliases_item.as_table_like_mut() else {
+    return aliases_to_document(aliases).to_string();
+  };
+  // Entries that are not valid aliases were skipped when the file was loaded rather than removed, so they are kept.
+  let is_valid_alias = |name: &str, item: &Item| item.as_table_like().is_some_and(|table| parse_alias(name, table, None).is_ok());
+  let removed: Vec<String> = table.iter().filter(|(name, item)| !aliases.contains_key(*name) && is_valid_alias(name, item)).map(|(name, _)| name.to_string()).collect();
+  for name in removed {
+    table.remove(&name);
+  }
+  let mut names: Vec<&String> = aliases.keys().collect();
+  names.sort_unstable();
+  for name in names {
+    let alias = &aliases[name];
+    let new_item = || if inline { Item::Value(Value::InlineTable(alias_to_table(alias).into_inline_table())) } else { Item::Table(alias_to_table(alias)) };
+    match table.get_mut(name) {
+      Some(item) => update_alias_item(name, item, alias, new_item),
+      None => { table.insert(name, new_item()); }
+    }
+  }
+  document.to_string()
+}
+
+/// Makes the alias entry item describe alias, changing only the fields that differ.
+fn update_alias_item<F: Fn() -> Item>(name: &str, item: &mut Item, alias: &Alias, new_item: F) {
+  let old = item.as_table_like().and_then(|table| parse_alias(name, table, None).ok());
+  let (Some(old), Some(table)) = (old, item.as_table_like_mut()) else {
+    *item = new_item();
+    return;
+  };
+  if old == *alias {
+    return;
+  }
+  if old.command != alias.command {
+    set_field(table, "command", Some(Value::from(alias.command.as_str())));
+  }
+  if old.description != alias.description {
+    set_field(table, "description", alias.description.as_deref().map(Value::from));
+  }
+  if old.tags != alias.tags {
+    set_field(table, "tags", Some(alias.tags.iter().collect::<Array>()).filter(|tags| !tags.is_empty()).map(Value::Array));
+  }
+  if old.cwd != alias.cwd {
+    set_field(table, "cwd", alias.cwd.as_deref().map(Value::from));
+  }
+  if old.shell != alias.shell {
+    set_field(table, "shell", alias.shell.as_deref().map(Value::from));
+  }
+  if old.env != alias.env {
+    set_field(table, "env", Some(&alias.env).filter(|env| !env.is_empty()).map(|env| Value::InlineTable(env_to_inline_table(env))));
+  }
+  if old.unset_env != alias.unset_env {
+    set_field(table, "unset_env", Some(alias.unset_env.iter().collect::<Array>()).filter(|unset_env| !unset_env.is_empty()).map(Value::Array));
+  }
+}
+
+/// Sets or, if new is None, removes the field key of table. A value that is replaced keeps the comments and spacing
+/// around it.
+fn set_field(table: &mut dyn TableLike, key: &str, new: Option<Value>) {
+  let Some(mut new) = new else {
+    table.remove(key);
+    return;
+  };
+  match table.get_mut(key).and_then(|item| item.as_value_mut()) {
+    Some(existing) => {
+      *new.decor_mut() = existing.decor().clone();
+      *existing = new;
+    }
+    None => { table.insert(key, Item::Value(new)); }
+  }
+}
+
+/// Renders aliases as a versioned TOML document, with the aliases sorted by name.
+pub fn aliases_to_document(aliases: &HashMap<String, Alias>) -> DocumentMut {
+  let mut document = DocumentMut::new();
+  document["version"] = value(CURRENT_VERSION);
+  let mut table = Table::new();
+  table.set_implicit(true);
+  let mut names: Vec<&String> = aliases.keys().collect();
+  names.sort_unstable();
+  for name in names {
+    table.insert(name, Item::Table(alias_to_table(&aliases[name])));
+  }
+  document["aliases"] = Item::Table(table);
+  document
+}
+
+/// Renders alias as an inline table, which fits on a single line.
+pub fn alias_to_inline_table(alias: &Alias) -> InlineTable {
+  alias_to_table(alias).into_inline_table()
+}
+
+/// Parses an alias that was rendered by alias_to_inline_table.
+pub fn parse_inline_alias(name: &str, table: &InlineTable) -> Result<Alias, SchemaError> {
+  parse_alias(name, table, None)
+}
+
+fn alias_to_table(alias: &Alias) -> Table {
+  let mut table = Table::new();
+  // Puts an empty line between aliases.
+  table.decor_mut().set_prefix("\n");
+  table["command"] = value(alias.command.as_str());
+  if let Some(description) = &alias.description {
+    table["description"] = value(description.as_str());
+  }
+  if !alias.tags.is_empty() {
+    table["tags"] = value(alias.tags.iter().collect::<Array>());
+  }
+  if let Some(cwd) = &alias.cwd {
+    table["cwd"] = value(cwd.as_str());
+  }
+  if let Some(shell) = &alias.shell {
+    table["shell"] = value(shell.as_str());
+  }
+  if !alias.env.is_empty() {
+    table["env"] = value(env_to_inline_table(&alias.env));
+  }
+  if !alias.unset_env.is_empty() {
+    table["unset_env"] = value(alias.unset_env.iter().collect::<Array>());
+  }
+  table
+}
+
+fn env_to_inline_table(env: &BTreeMap<String, String>) -> InlineTable {
+  env.iter().map(|(variable, value)| (variable.as_str(), value.as_str())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::{BTreeMap, HashMap};
+  use quickcheck::quickcheck;
+  use crate::diagnostics::Span;
+  use crate::process::Interpreter;
+  use crate::store::{Alias, is_valid_alias_name};
+  use super::{aliases_to_document, SchemaError, is_legacy_format, legacy_to_document, LegacyEntry, parse_document, SchemaErrorCode, update_document};
+  use SchemaErrorCode::*;
+
+  /// Parses contents, failing at the first problem.
+  fn parse_strict(contents: &str) -> Result<HashMap<String, Alias>, SchemaError> {
+    let parsed = parse_document(contents);
+    match parsed.errors.into_iter().next() {
+      Some(error) => Err(error),
+      None => Ok(parsed.aliases),
+    }
+  }
+
+  fn parse(contents: &str) -> Result<HashMap<String, Alias>, SchemaErrorCode> {
+    parse_strict(contents).map_err(|e| e.code)
+  }
+
+  fn full_alias() -> Alias {
+    Alias {
+      command: "cargo build \"--release\"".to_string(),
+      description: Some("Builds it".to_string()),
+      tags: vec!["rust".to_string(), "build".to_string()],
+      cwd: Some("~/projects".to_string()),
+      env: BTreeMap::from([("RUST_LOG".to_string(), "debug".to_string())]),
+      unset_env: vec!["CARGO_HOME".to_string()],
+      shell: Some("bash".to_string()),
+    }
+  }
+
+  #[test]
+  fn test_parse_document() {
+    let contents = "version = 1\n\n[aliases.build]\ncommand = 'cargo build'\ntags = [\"rust\"]\n\n[aliases.\"with space\"]\ncommand = \"ls\"\nenv = { A = \"1\" }\n";
+    let mut build = Alias::new("cargo build".to_string());
+    build.tags.push("rust".to_string());
+    let mut with_space = Alias::new("ls".to_string());
+    with_space.env.insert("A".to_string(), "1".to_string());
+    assert_eq!(parse_strict(contents), Ok(HashMap::from([("build".to_string(), build), ("with space".to_string(), with_space)])));
+    assert_eq!(parse(""), Ok(HashMap::new()));
+    assert_eq!(parse("version = 1\naliases = { x = { command = \"ls\" } }"), Ok(HashMap::from([("x".to_string(), Alias::new("ls".to_string()))])));
+    assert_eq!(parse("[aliases.x]\ncommand = \"ls\""), Err(MissingVersion));
+    assert_eq!(parse("version = 2"), Err(UnsupportedVersion("2".to_string())));
+    assert_eq!(parse("version = 1\naliases = 3"), Err(AliasesNotATable));
+    assert_eq!(parse("version = 1\n[aliases]\nx = 3"), Err(AliasNotATable("x".to_string())));
+    assert_eq!(parse("version = 1\n[aliases.x]\ncommand = \"\""), Err(MissingCommand("x".to_string())));
+    assert_eq!(parse("version = 1\n[aliases.x]\ncommand = 1"), Err(InvalidFieldType("x".to_string(), "command".to_string(), "a string")));
+    assert_eq!(parse("version = 1\n[aliases.x]\ncommand = \"ls\"\ntags = [1]"), Err(InvalidFieldType("x".to_string(), "tags".to_string(), "an array of strings")));
+    assert_eq!(parse("version = 1\n[aliases.x]\ncommand = \"ls\"\ncomand = \"ls\""), Err(UnknownField("x".to_string(), "comand".to_string())));
+    assert!(matches!(parse("version = "), Err(InvalidToml(_))));
+    assert_eq!(parse("version = 1\n[aliases.\"db.migrate\"]\ncommand = \"ls\""), Ok(HashMap::from([("db.migrate".to_string(), Alias::new("ls".to_string()))])));
+    assert_eq!(parse("version = 1\n[aliases.\"db..migrate\"]\ncommand = \"ls\""), Err(InvalidAliasName("db..migrate".to_string())));
+    assert_eq!(parse("version = 1\n[aliases.db.migrate]\ncommand = \"ls\""), Err(UnquotedNamespace("db".to_string(), "migrate".to_string())));
+    let span = |contents: &str| parse_strict(contents).unwrap_err().span;
+    assert_eq!(span("version = 1\n[aliases.x]\ncommand = \"ls\"\ncomand = \"ls\""), Some(Span::new(39, 45)));
+    assert_eq!(span("version = 1\n[aliases.x]\ncomand = \"ls\""), Some(Span::new(12, 23)));
+    assert_eq!(span("version = 1\n[aliases.x]\ncommand = 1"), Some(Span::new(34, 35)));
+    assert_eq!(span("version = 1\n[aliases.x]\ncommand = "), Some(Span::new(34, 34)));
+    assert_eq!(span("version = 3"), Some(Span::new(10, 11)));
+    assert_eq!(span("[aliases.x]"), None);
+    assert_eq!(span("version = 1\n[aliases.\".x\"]\ncommand = \"ls\""), Some(Span::new(21, 25)));
+  }
+
+  #[test]
+  fn test_parse_document_recovers() {
+    let source = "version = 1\n[aliases.a]\ncommand = \"echo a\"\n[aliases.bad]\ncommand = 1\n[aliases.b]\ncommand = \"echo b\" oops\ntags = [\"x\"]\n[aliases.c]\ncommand = \"echo c\"\n";
+    let parsed = parse_document(source);
+    let mut names: Vec<&String> = parsed.aliases.keys().collect();
+    names.sort();
+    assert_eq!(names, ["a", "c"]);
+    // A file that is not valid TOML cannot be edited in place, so it is marked as broken.
+    assert!(parsed.broken);
+    let codes: Vec<&SchemaErrorCode> = parsed.errors.iter().map(|error| &error.code).collect();
+    assert!(matches!(codes[..], [InvalidToml(_), InvalidFieldType(..), MissingCommand(_)]), "{codes:?}");
+    let source = "version = 1\n[aliases.a]\ncommand = \"echo a\"\n[aliases.bad]\ncommand = 1\n";
+    let parsed = parse_document(source);
+    assert!(!parsed.broken);
+    assert_eq!(parsed.errors.len(), 1);
+    // Entries that were skipped on load stay in the file when it is written back.
+    assert_eq!(update_document(source, &parsed.aliases), source);
+    let broken = parse_document("[aliases.a]\ncommand = \"ls\"\n");
+    assert!(broken.broken);
+    assert!(broken.aliases.is_empty());
+  }
+
+  #[test]
+  fn test_parse_settings() {
+    assert_eq!(parse_document("version = 1\n[settings]\nbackups = 3\n").settings.backups, Some(3));
+    assert_eq!(parse_document("version = 1\n").settings.backups, None);
+    assert_eq!(parse("version = 1\nsettings = 3"), Err(SettingsNotATable));
+    assert_eq!(parse("version = 1\n[settings]\nbackups = -1"), Err(InvalidSetting("backups".to_string(), "a number that is not negative")));
+    assert_eq!(parse("version = 1\n[settings]\nbackup = 3"), Err(UnknownSetting("backup".to_string())));
+    assert!(parse_document("version = 1\n[settings]\nautocorrect = true\n").settings.autocorrect);
+    assert_eq!(parse("version = 1\n[settings]\nautocorrect = 1"), Err(InvalidSetting("autocorrect".to_string(), "true or false")));
+    // A setting that is not valid does not keep the aliases from being read.
+    let parsed = parse_document("version = 1\n[settings]\nbackups = \"many\"\n[aliases.x]\ncommand = \"ls\"\n");
+    assert_eq!(parsed.settings.backups, None);
+    assert_eq!(parsed.aliases.len(), 1);
+    assert!(!parsed.broken);
+  }
+
+  #[test]
+  fn test_aliases_to_document() {
+    let aliases = HashMap::from([("b".to_string(), full_alias()), ("a".to_string(), Alias::new("ls".to_string()))]);
+    let expected = "version = 1\n\n[aliases.a]\ncommand = \"ls\"\n\n[aliases.b]\ncommand = 'cargo build \"--release\"'\ndescription = \"Builds it\"\ntags = [\"rust\", \"build\"]\ncwd = \"~/projects\"\nshell = \"bash\"\nenv = { RUST_LOG = \"debug\" }\nunset_env = [\"CARGO_HOME\"]\n";
+    let document = aliases_to_document(&aliases).to_string();
+    assert_eq!(document, expected);
+    assert_eq!(parse_strict(&document), Ok(aliases));
+  }
+
+  type RoundTrip = fn(String, String, Option<String>, Vec<String>, Option<String>, (BTreeMap<String, String>, Vec<String>), Option<usize>) -> bool;
+
+  /// Whether an alias made up of the given strings survives being written to a document and read back, both in a
+  /// new document and in an existing one with comments. The environment is made of the variables that are set and the
+  /// ones that are removed, and the shell is picked from the ones Alia knows by its index.
+  fn round_trips(name: String, command: String, description: Option<String>, tags: Vec<String>, cwd: Option<String>, (env, unset_env): (BTreeMap<String, String>, Vec<String>), shell: Option<usize>) -> bool {
+    // An empty command is rejected on purpose, as every alias has to run something, and so are malformed names.
+    if command.is_empty() || !is_valid_alias_name(&name) {
+      return true;
+    }
+    let shell = shell.map(|index| Interpreter::NAMES[index % Interpreter::NAMES.len()].to_string());
+    let aliases = HashMap::from([(name, Alias { command, description, tags, cwd, env, unset_env, shell })]);
+    let existing = "# Mine\nversion = 1\n\n[aliases.other]\ncommand = \"ls\" # list\n";
+    let mut with_existing = aliases.clone();
+    with_existing.entry("other".to_string()).or_insert_with(|| Alias::new("ls".to_string()));
+    parse_strict(&aliases_to_document(&aliases).to_string()) == Ok(aliases) && parse_strict(&update_document(existing, &with_existing)) == Ok(with_existing)
+  }
+
+  #[test]
+  fn test_round_trip() {
+    quickcheck(round_trips as RoundTrip);
+    assert!(round_trips("quote\"d \\ name".to_string(), "echo \"$1\" \\n '''x'''".to_string(), Some("\u{0}\u{7f}\r\n\t".to_string()), vec!["".to_string()], None, (BTreeMap::from([("=".to_string(), "\u{1F600}".to_string())]), vec!["\n".to_string()]), Some(6)));
+  }
+
+  #[test]
+  fn test_update_document() {
+    let source = "# My aliases\nversion = 1\n\n# Build things\n[aliases.build]\ncommand = \"make\" # for now\ntags = [\"c\"]\n\n[aliases.old]\ncommand = \"ls\"\n\n[aliases.a]\ncommand   =   \"echo a\"\n";
+    let mut aliases = parse_strict(source).unwrap();
+    assert_eq!(update_document(source, &aliases), source);
+    aliases.remove("old");
+    aliases.get_mut("build").unwrap().command = "cargo build".to_string();
+    aliases.get_mut("build").unwrap().tags.clear();
+    aliases.get_mut("build").unwrap().description = Some("Builds it".to_string());
+    aliases.insert("new".to_string(), Alias::new("echo new".to_string()));
+    let expected = "# My aliases\nversion = 1\n\n# Build things\n[aliases.build]\ncommand = \"cargo build\" # for now\ndescription = \"Builds it\"\n\n[aliases.a]\ncommand   =   \"echo a\"\n\n[aliases.new]\ncommand = \"echo new\"\n";
+    assert_eq!(update_document(source, &aliases), expected);
+    assert_eq!(parse_strict(expected), Ok(aliases.clone()));
+    let inline = "version = 1\naliases = { x = { command = \"ls\" } }\n";
+    aliases.retain(|name, _| name == "new");
+    assert_eq!(update_document(inline, &aliases), "version = 1\naliases = { new = { command = \"echo new\" } }\n");
+    assert_eq!(update_document("", &aliases), aliases_to_document(&aliases).to_string());
+  }
+
+  #[test]
+  fn test_legacy_to_document() {
+    let entries = [
+      LegacyEntry { comments: vec!["# first".to_string()], name: "b".to_string(), value: "echo b".to_string(), trailing_comment: Some("# note".to_string()) },
+      LegacyEntry { comments: vec![], name: "a".to_string(), value: "echo a".to_string(), trailing_comment: None },
+    ];
+    let expected = "version = 1\n\n# first\n[aliases.b]\ncommand = \"echo b\" # note\n\n[aliases.a]\ncommand = \"echo a\"\n\n# the end\n";
+    assert_eq!(legacy_to_document(&entries, &["# the end".to_string()]).to_string(), expected);
+  }
+
+  #[test]
+  fn test_is_legacy_format() {
+    assert!(is_legacy_format("\"a\" = \"b\""));
+    assert!(is_legacy_format("\n  \"a\"=\"b\""));
+    assert!(!is_legacy_format("version = 1"));
+    assert!(!is_legacy_format(""));
+    assert!(is_legacy_format("# \"version\" = 1\n\n\"a\" = \"b\""));
+    assert!(!is_legacy_format("# \"a\" = \"b\"\nversion = 1"));
+    assert!(is_legacy_format("\"say \\\"hi\\\"\" = \"echo hi\"\n\"version\" = \"cat VERSION\""));
+    // Versioned files may quote their keys as well.
+    assert!(!is_legacy_format("\"version\" = 1\n\n[aliases.a]\ncommand = \"ls\""));
+    assert!(!is_legacy_format("\"aliases\".a.command = \"ls\"\nversion = 1"));
+    assert!(!is_legacy_format("\"a\" = \"b\"\nversion = 1"));
+    let parsed = parse_document("\"version\" = 1\n\n[aliases.a]\ncommand = \"ls\"\n");
+    assert_eq!((parsed.errors, parsed.broken), (Vec::new(), false));
+    assert_eq!(parsed.aliases, HashMap::from([("a".to_string(), Alias::new("ls".to_string()))]));
+  }
+}
@@ -0,0 +1,139 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub enum ShellWordsErrorCode {
+  UnterminatedSingleQuote,
+  UnterminatedDoubleQuote,
+  TrailingBackslash,
+  NoWords,
+}
+
+use ShellWordsErrorCode::*;
+
+impl Display for ShellWordsErrorCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", match self {
+      UnterminatedSingleQuote => "A single quote was opened but never closed.",
+      UnterminatedDoubleQuote => "A double quote was opened but never closed.",
+      TrailingBackslash => "The command ended with a backslash that does not escape anything.",
+      NoWords => "The command was empty.",
+    })
+  }
+}
+
+/// Splits s into words the way a POSIX shell would, honouring single quotes, double quotes, backslash escapes and
+/// `#` comments. Nothing is expanded: variables, globs, pipes and redirects are passed on as plain text.
+pub fn split(s: &str) -> Result<Vec<String>, ShellWordsErrorCode> {
+  let mut words = Vec::new();
+  let mut word = String::new();
+  let mut in_word = false;
+  let mut chars = s.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      ' ' | '\t' | '\n' => {
+        if in_word {
+          words.push(std::mem::take(&mut word));
+          in_word = false;
+        }
+      }
+      '#' if !in_word => {
+        for c in chars.by_ref() {
+          if c == '\n' {
+            break;
+          }
+        }
+      }
+      '\'' => {
+        in_word = true;
+        loop {
+          match chars.next() {
+            Some('\'') => break,
+            Some(c) => word.push(c),
+            None => return Err(UnterminatedSingleQuote),
+          }
+        }
+      }
+      '"' => {
+        in_word = true;
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+              Some('\n') => {}
+              Some(c @ ('$' | '`' | '"' | '\\')) => word.push(c),
+              Some(c) => {
+                word.push('\\');
+                word.push(c);
+              }
+              None => return Err(UnterminatedDoubleQuote),
+            },
+            Some(c) => word.push(c),
+            None => return Err(UnterminatedDoubleQuote),
+          }
+        }
+      }
+      '\\' => match chars.next() {
+        // A backslash before a newline continues the line.
+        Some('\n') => {}
+        Some(c) => {
+          in_word = true;
+          word.push(c);
+        }
+        None => return Err(TrailingBackslash),
+      },
+      c => {
+        in_word = true;
+        word.push(c);
+      }
+    }
+  }
+  if in_word {
+    words.push(word);
+  }
+  if words.is_empty() {
+    return Err(NoWords);
+  }
+  Ok(words)
+}
+
+/// Quotes s so that split turns it back into a single word.
+pub fn quote(s: &str) -> String {
+  let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+  if !s.is_empty() && s.chars().all(is_safe) {
+    return s.to_string();
+  }
+  format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{quote, ShellWordsErrorCode, split};
+  use ShellWordsErrorCode::*;
+
+  fn words<const NUM_OF_WORDS: usize>(words: [&str; NUM_OF_WORDS]) -> Result<Vec<String>, ShellWordsErrorCode> {
+    Ok(words.map(|x| x.to_string()).to_vec())
+  }
+
+  #[test]
+  fn test_split() {
+    assert_eq!(split("echo hello world"), words(["echo", "hello", "world"]));
+    assert_eq!(split("  echo\t 'hello world'  "), words(["echo", "hello world"]));
+    assert_eq!(split("echo \"a \\\"b\\\" \\$c \\d\""), words(["echo", "a \"b\" $c \\d"]));
+    assert_eq!(split("echo a\\ b \\'c"), words(["echo", "a b", "'c"]));
+    assert_eq!(split("echo '' x\"\"y"), words(["echo", "", "xy"]));
+    assert_eq!(split("echo a#b # comment\nls"), words(["echo", "a#b", "ls"]));
+    assert_eq!(split("echo a \\\n b"), words(["echo", "a", "b"]));
+    assert_eq!(split("echo $HOME | wc"), words(["echo", "$HOME", "|", "wc"]));
+    assert_eq!(split("echo 'a"), Err(UnterminatedSingleQuote));
+    assert_eq!(split("echo \"a"), Err(UnterminatedDoubleQuote));
+    assert_eq!(split("echo a\\"), Err(TrailingBackslash));
+    assert_eq!(split(" # nothing"), Err(NoWords));
+  }
+
+  #[test]
+  fn test_quote() {
+    for s in ["plain", "", "with space", "it's", "\"double\"", "back\\slash", "$HOME", "new\nline"] {
+      assert_eq!(split(&format!("cmd {}", quote(s))), words(["cmd", s]));
+    }
+  }
+}
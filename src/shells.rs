@@ -0,0 +1,113 @@
+use crate::shell_words;
+
+/// A shell Alia can integrate with.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Shell {
+  Bash,
+  Zsh,
+  Fish,
+  Elvish,
+  Powershell,
+}
+
+impl Shell {
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "bash" => Some(Shell::Bash),
+      "zsh" => Some(Shell::Zsh),
+      "fish" => Some(Shell::Fish),
+      "elvish" => Some(Shell::Elvish),
+      "powershell" | "pwsh" => Some(Shell::Powershell),
+      _ => None,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Shell::Bash => "bash",
+      Shell::Zsh => "zsh",
+      Shell::Fish => "fish",
+      Shell::Elvish => "elvish",
+      Shell::Powershell => "powershell",
+    }
+  }
+
+  /// Quotes s so that this shell reads it back as a single word with no expansion of any kind.
+  pub fn quote(self, s: &str) -> String {
+    match self {
+      Shell::Bash | Shell::Zsh => shell_words::quote(s),
+      // Inside single quotes fish only treats `\'` and `\\` as escapes.
+      Shell::Fish => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+      Shell::Elvish => format!("'{}'", s.replace('\'', "''")),
+      // PowerShell accepts the typographic quotes as well, and each of them is escaped by doubling it.
+      Shell::Powershell => {
+        let mut result = String::with_capacity(s.len() + 2);
+        result.push('\'');
+        for c in s.chars() {
+          if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            result.push(c);
+          }
+          result.push(c);
+        }
+        result.push('\'');
+        result
+      }
+    }
+  }
+
+  fn reserved_words(self) -> &'static [&'static str] {
+    match self {
+      Shell::Bash | Shell::Zsh => &["case", "coproc", "do", "done", "elif", "else", "esac", "fi", "for", "foreach", "function", "if", "in", "repeat", "select", "then", "time", "until", "while"],
+      Shell::Elvish => &["and", "coalesce", "del", "fn", "for", "if", "or", "pragma", "set", "try", "use", "var", "while"],
+      Shell::Fish => &["and", "begin", "break", "builtin", "case", "command", "continue", "else", "end", "exec", "for", "function", "if", "not", "or", "return", "switch", "time", "while"],
+      Shell::Powershell => &["begin", "break", "catch", "class", "continue", "data", "do", "dynamicparam", "else", "elseif", "end", "enum", "exit", "filter", "finally", "for", "foreach", "function", "if", "in", "param", "process", "return", "switch", "throw", "trap", "try", "until", "using", "while"],
+    }
+  }
+
+  /// Whether name can be used as the name of a function in this shell without any quoting. Aliases whose names
+  /// fail this check are left out of the shell integration and have to be run with `alia e`.
+  pub fn is_valid_function_name(self, name: &str) -> bool {
+    let is_valid_char = |c: char| c.is_ascii_alphanumeric() || match self {
+      Shell::Bash | Shell::Zsh | Shell::Fish | Shell::Elvish => "_-.".contains(c),
+      // A `.` would be taken for a file extension when PowerShell looks up the command.
+      Shell::Powershell => "_-".contains(c),
+    };
+    !name.is_empty() && !name.starts_with('-') && name.chars().all(is_valid_char) && !self.reserved_words().contains(&name.to_ascii_lowercase().as_str())
+  }
+}
+
+/// Joins the lines of a script Alia prints for a shell to evaluate. The script is run by the shell rather than shown to
+/// the user, so it always uses plain line feeds.
+pub fn script_from_lines<S: AsRef<str>>(lines: &[S]) -> String {
+  lines.iter().map(|line| line.as_ref().to_string() + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::shell_words::split;
+  use super::Shell;
+
+  #[test]
+  fn test_quote() {
+    for s in ["plain", "", "with space", "it's", "\"double\"", "back\\slash", "$HOME", "new\nline"] {
+      for shell in [Shell::Bash, Shell::Zsh] {
+        assert_eq!(split(&format!("cmd {}", shell.quote(s))), Ok(vec!["cmd".to_string(), s.to_string()]));
+      }
+    }
+    assert_eq!(Shell::Fish.quote("it's a \\ $x"), "'it\\'s a \\\\ $x'");
+    assert_eq!(Shell::Elvish.quote("it's \\ $x"), "'it''s \\ $x'");
+    assert_eq!(Shell::Powershell.quote("it's $x \u{2019}"), "'it''s $x \u{2019}\u{2019}'");
+  }
+
+  #[test]
+  fn test_is_valid_function_name() {
+    assert!(Shell::Bash.is_valid_function_name("db.migrate"));
+    assert!(Shell::Fish.is_valid_function_name("run-tests_2"));
+    assert!(!Shell::Powershell.is_valid_function_name("db.migrate"));
+    assert!(!Shell::Bash.is_valid_function_name("-x"));
+    assert!(!Shell::Zsh.is_valid_function_name("has space"));
+    assert!(!Shell::Bash.is_valid_function_name(""));
+    assert!(!Shell::Fish.is_valid_function_name("end"));
+    assert!(Shell::Bash.is_valid_function_name("end"));
+  }
+}
@@ -0,0 +1,229 @@
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions, Permissions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use std::{process, thread};
+
+/// Environment variable holding how many seconds to wait for another Alia process to finish with an alias file.
+pub const LOCK_TIMEOUT_ENV_VAR: &str = "ALIA_LOCK_TIMEOUT";
+
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to wait for a lock.
+static LOCK_TIMEOUT: Mutex<Duration> = Mutex::new(DEFAULT_LOCK_TIMEOUT);
+
+/// Whether alias files are locked as they are read.
+static LOCK_ON_READ: AtomicBool = AtomicBool::new(false);
+
+/// The locks this process holds, along with the path of the alias file each of them guards. A lock is released when
+/// its file is closed, which at the latest happens when the process exits.
+static HELD_LOCKS: Mutex<Vec<(PathBuf, File)>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Clone)]
+pub enum LockErrorCode {
+  /// Another process held the lock at the path for longer than the timeout.
+  Busy(PathBuf, Duration),
+  CouldNotLock(PathBuf, String),
+}
+
+impl Display for LockErrorCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LockErrorCode::Busy(path, timeout) => write!(f, "Another Alia process is changing your aliases and kept {} locked for more than {} seconds. Try again once it is done, or wait longer by passing --lock-timeout <seconds> or setting {LOCK_TIMEOUT_ENV_VAR}.", path.display(), timeout.as_secs_f64()),
+      LockErrorCode::CouldNotLock(path, error) => write!(f, "The lock file {} could not be opened. Here is the error the OS reported:\n{error}", path.display()),
+    }
+  }
+}
+
+/// Parses a timeout given as a non-negative number of seconds, such as `10` or `0.5`.
+pub fn parse_lock_timeout(s: &str) -> Option<Duration> {
+  s.trim().parse::<f64>().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
+/// Sets how long to wait for another Alia process to release a lock.
+pub fn set_lock_timeout(timeout: Duration) {
+  *LOCK_TIMEOUT.lock().unwrap_or_else(|e| e.into_inner()) = timeout;
+}
+
+fn lock_timeout() -> Duration {
+  *LOCK_TIMEOUT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Makes every later call to lock_store_file take a lock. Alia does this once it knows an invocation is going to write
+/// to the alias files, before reading them again. Every write replaces the file in one step, so reading them the first
+/// time needs no lock.
+pub fn enable_locking() {
+  LOCK_ON_READ.store(true, Ordering::Relaxed);
+}
+
+/// The lock file guarding the alias file at path. It sits next to the alias file rather than being the alias file
+/// itself, since writes replace the alias file with a new one.
+pub fn lock_path(path: &Path) -> PathBuf {
+  let mut lock_path = path.as_os_str().to_owned();
+  lock_path.push(".lock");
+  lock_path.into()
+}
+
+/// Locks the alias file at path until release_locks is called or this process exits, so no other Alia process can change it between this one
+/// reading it and writing it back. Does nothing if locking is not enabled or the file is already locked.
+pub fn lock_store_file(path: &Path) -> Result<(), LockErrorCode> {
+  if !LOCK_ON_READ.load(Ordering::Relaxed) {
+    return Ok(());
+  }
+  let mut held_locks = HELD_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+  if held_locks.iter().any(|(held_path, _)| held_path == path) {
+    return Ok(());
+  }
+  let file = lock_file(path)?;
+  held_locks.push((path.to_path_buf(), file));
+  Ok(())
+}
+
+/// Runs f while holding the lock on the file at path, whether or not locking is enabled, for the writes that are made
+/// without reading the alias files again under their locks. The lock is released once f returns, unless it was
+/// already held before.
+pub fn with_lock<R>(path: &Path, f: impl FnOnce() -> R) -> Result<R, LockErrorCode> {
+  if HELD_LOCKS.lock().unwrap_or_else(|e| e.into_inner()).iter().any(|(held_path, _)| held_path == path) {
+    return Ok(f());
+  }
+  let _lock = lock_file(path)?;
+  Ok(f())
+}
+
+/// Takes the lock on the file at path and returns the open lock file holding it. The directory the file goes in is
+/// created if needed, since the lock file has to sit in it even before the file itself exists.
+fn lock_file(path: &Path) -> Result<File, LockErrorCode> {
+  let lock_path = lock_path(path);
+  if let Some(directory) = lock_path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+    fs::create_dir_all(directory).map_err(|e| LockErrorCode::CouldNotLock(lock_path.clone(), e.to_string()))?;
+  }
+  acquire_lock(&lock_path, lock_timeout())
+}
+
+/// Releases every lock this process holds, once it is done with the alias files. Aliases are run after this, so one
+/// that keeps running for a long time does not keep other Alia processes waiting.
+pub fn release_locks() {
+  HELD_LOCKS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Takes an exclusive lock on the file at lock_path, creating it if needed, and returns the open file holding it.
+fn acquire_lock(lock_path: &Path, timeout: Duration) -> Result<File, LockErrorCode> {
+  let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(lock_path)
+    .map_err(|e| LockErrorCode::CouldNotLock(lock_path.to_path_buf(), e.to_string()))?;
+  let start = Instant::now();
+  loop {
+    match file.try_lock() {
+      Ok(()) => return Ok(file),
+      Err(TryLockError::WouldBlock) if start.elapsed() < timeout => thread::sleep(LOCK_RETRY_INTERVAL),
+      Err(TryLockError::WouldBlock) => return Err(LockErrorCode::Busy(lock_path.to_path_buf(), timeout)),
+      Err(TryLockError::Error(e)) => return Err(LockErrorCode::CouldNotLock(lock_path.to_path_buf(), e.to_string())),
+    }
+  }
+}
+
+/// Replaces the file at path with contents in a way that survives crashes: contents go to a temporary file in the
+/// same directory, which is flushed to disk and then renamed over the original. Anyone reading the file sees either
+/// the old or the new contents, never a mix of them. The permissions of the original file are kept.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+  // A symlink, such as one set up by a dotfile manager, should keep pointing to the file, so the file it points to is
+  // replaced instead of the link.
+  let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+  let directory = path.parent().filter(|directory| !directory.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  let file_name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file", path.display())))?;
+  let mut temp_name = OsString::from(".");
+  temp_name.push(file_name);
+  temp_name.push(format!(".{}.tmp", process::id()));
+  let temp_path = directory.join(temp_name);
+  let permissions = fs::metadata(&path).map(|metadata| metadata.permissions()).ok();
+  let res = write_and_sync(&temp_path, contents, permissions).and_then(|()| fs::rename(&temp_path, &path));
+  if res.is_err() {
+    let _ = fs::remove_file(&temp_path);
+  }
+  res?;
+  sync_directory(directory)
+}
+
+fn write_and_sync(path: &Path, contents: &[u8], permissions: Option<Permissions>) -> io::Result<()> {
+  let mut file = File::create(path)?;
+  file.write_all(contents)?;
+  if let Some(permissions) = permissions {
+    file.set_permissions(permissions)?;
+  }
+  file.sync_all()
+}
+
+/// Flushes the directory entry a rename created to disk, so the new file is still there after a crash.
+#[cfg(unix)]
+fn sync_directory(directory: &Path) -> io::Result<()> {
+  File::open(directory)?.sync_all()
+}
+
+/// Windows has no way of flushing a directory, and its renames are flushed with the file.
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) -> io::Result<()> {
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::path::PathBuf;
+  use std::time::Duration;
+  use super::{acquire_lock, LockErrorCode, parse_lock_timeout, write_atomically};
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("alia_test_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn test_write_atomically() {
+    let dir = temp_dir("write_atomically");
+    let path = dir.join("cfg.alia");
+    write_atomically(&path, b"first").unwrap();
+    write_atomically(&path, b"second").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::{PermissionsExt, symlink};
+      fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+      let link = dir.join("link.alia");
+      symlink(&path, &link).unwrap();
+      write_atomically(&link, b"third").unwrap();
+      assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+      assert_eq!(fs::read_to_string(&path).unwrap(), "third");
+      assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_acquire_lock() {
+    let dir = temp_dir("acquire_lock");
+    let path = dir.join("cfg.alia.lock");
+    let held = acquire_lock(&path, Duration::ZERO).unwrap();
+    assert_eq!(acquire_lock(&path, Duration::from_millis(100)).err(), Some(LockErrorCode::Busy(path.clone(), Duration::from_millis(100))));
+    drop(held);
+    acquire_lock(&path, Duration::ZERO).unwrap();
+    assert!(matches!(acquire_lock(&dir.join("missing").join("x.lock"), Duration::ZERO), Err(LockErrorCode::CouldNotLock(..))));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_parse_lock_timeout() {
+    assert_eq!(parse_lock_timeout("10"), Some(Duration::from_secs(10)));
+    assert_eq!(parse_lock_timeout("0.5"), Some(Duration::from_millis(500)));
+    assert_eq!(parse_lock_timeout("0"), Some(Duration::ZERO));
+    assert_eq!(parse_lock_timeout("-1"), None);
+    assert_eq!(parse_lock_timeout("soon"), None);
+  }
+}
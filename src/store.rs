@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::{env, path};
+use crate::diagnostics::Diagnostic;
+use crate::journal::Journal;
+use crate::process::Execution;
+use crate::usage::UsageStats;
+
+/// Name of the per-project alias files that Alia collects by walking up from the current directory.
+pub const LOCAL_CONFIG_FILE_NAME: &str = ".alia";
+
+/// Separates the namespaces an alias is grouped into from each other and from the rest of its name, as in `db.migrate`.
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum LayerKind {
+  Global,
+  Local,
+  File,
+}
+
+impl Display for LayerKind {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", match self {
+      LayerKind::Global => "global",
+      LayerKind::Local => "local",
+      LayerKind::File => "file",
+    })
+  }
+}
+
+/// An alias along with everything else that is stored about it.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Alias {
+  pub command: String,
+  pub description: Option<String>,
+  pub tags: Vec<String>,
+  /// The directory the alias runs in. A relative path is relative to the directory of the alias file, and a leading `~`
+  /// stands for the home directory.
+  pub cwd: Option<String>,
+  /// Environment variables that are set for the alias.
+  pub env: BTreeMap<String, String>,
+  /// Environment variables that are removed for the alias.
+  pub unset_env: Vec<String>,
+  /// The name of the interpreter that runs the alias instead of the system shell, one of `Interpreter::NAMES`.
+  pub shell: Option<String>,
+}
+
+impl Alias {
+  pub fn new(command: String) -> Self {
+    Self { command, ..Default::default() }
+  }
+}
+
+/// The settings kept in the `[settings]` table of an alias file. Only the ones in the global store take effect.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Settings {
+  /// How many earlier versions of the file to keep in its backups directory.
+  pub backups: Option<usize>,
+  /// Whether a mistyped command or alias name is replaced by the closest match when there is only one that is a
+  /// single character off, instead of stopping with an error.
+  pub autocorrect: bool,
+}
+
+/// A single alias file.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Layer {
+  pub path: PathBuf,
+  pub kind: LayerKind,
+  pub aliases: HashMap<String, Alias>,
+  pub modified: bool,
+  /// The contents of the file as it was loaded, so writing it back can keep its comments and layout.
+  pub source: String,
+  /// Every problem that was found in the file. The entries they are in were skipped.
+  pub problems: Vec<Diagnostic>,
+  /// Whether parts of the file other than single entries could not be parsed. Writing it back would lose those parts.
+  pub broken: bool,
+  pub settings: Settings,
+}
+
+impl Layer {
+  pub fn new(path: PathBuf, kind: LayerKind, aliases: HashMap<String, Alias>) -> Self {
+    Self { path, kind, aliases, modified: false, source: String::new(), problems: Vec::new(), broken: false, settings: Settings::default() }
+  }
+}
+
+/// Which layer a mutating command should write to, as chosen by `--global`, `--local` or `--file <path>`.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub enum LayerSelector {
+  /// No flag was given. Adding goes to the global store, changing and removing go to the layer that defines the alias.
+  #[default]
+  Default,
+  Global,
+  Local,
+  File(PathBuf),
+}
+
+/// Aliases in a namespace that were removed without `--yes`, which has to be confirmed before the alias files are
+/// written.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PendingRemoval {
+  pub namespace: String,
+  /// The names of the removed aliases, sorted.
+  pub names: Vec<String>,
+  /// The number of the argument the namespace was named at, for error messages.
+  pub arg: usize,
+}
+
+/// An alias, or a whole alias file if there is no name, to open in the editor once every command of the invocation has
+/// been checked.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PendingEdit {
+  /// The index of the layer the alias or file is in.
+  pub layer: usize,
+  pub name: Option<String>,
+  /// The number of the argument the edit was asked for at, for error messages.
+  pub arg: usize,
+}
+
+/// Every alias file that is in effect, ordered from the lowest precedence (the global store) to the highest
+/// (the `.alia` file closest to the current directory).
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct AliasStore {
+  pub layers: Vec<Layer>,
+  pub usage: UsageStats,
+  /// Every change made to the alias files, including the ones this invocation makes through insert and remove.
+  pub journal: Journal,
+  /// Whether alias files that are loaded later on are rejected at their first problem, as set by `--strict`.
+  pub strict: bool,
+  /// Whether the invocation only shows what it would do, as set by `--dry-run`, so nothing needs to be confirmed.
+  pub dry_run: bool,
+  /// The namespace removals to confirm once every command of the invocation has been checked.
+  pub pending_removals: Vec<PendingRemoval>,
+  /// What edit asked to open in the editor. The editor is only opened once the invocation has been checked.
+  pub pending_edit: Option<PendingEdit>,
+  /// The aliases to run once the invocation is done with the alias files, in order.
+  pub executions: Vec<Execution>,
+  /// What the commands print, which is only printed once every command of the invocation has been checked.
+  pub output: String,
+  /// What the commands report on stderr, such as a mistyped name they went with the closest match for. It is printed
+  /// once, as the commands may be checked twice.
+  pub notices: String,
+}
+
+impl AliasStore {
+  pub fn new(global: Layer) -> Self {
+    Self { layers: vec![global], usage: UsageStats::default(), journal: Journal::default(), strict: false, dry_run: false, pending_removals: Vec::new(), pending_edit: None, executions: Vec::new(), output: String::new(), notices: String::new() }
+  }
+
+  /// The settings in effect, which are the ones of the global store.
+  pub fn settings(&self) -> &Settings {
+    const DEFAULT: &Settings = &Settings { backups: None, autocorrect: false };
+    self.layers.iter().find(|layer| layer.kind == LayerKind::Global).map_or(DEFAULT, |layer| &layer.settings)
+  }
+
+  /// Returns the index of the layer whose definition of name is in effect.
+  pub fn origin(&self, name: &str) -> Option<usize> {
+    self.layers.iter().rposition(|layer| layer.aliases.contains_key(name))
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Alias> {
+    self.origin(name).and_then(|index| self.layers[index].aliases.get(name))
+  }
+
+  /// Whether any alias in effect is in the namespace name, directly or in a namespace nested in it.
+  pub fn is_namespace(&self, name: &str) -> bool {
+    self.layers.iter().any(|layer| layer.aliases.keys().any(|alias| in_namespace(alias, name)))
+  }
+
+  /// Every alias that is in effect, along with the index of the layer it came from.
+  pub fn merged(&self) -> HashMap<&str, (&Alias, usize)> {
+    let mut result = HashMap::new();
+    for (index, layer) in self.layers.iter().enumerate() {
+      for (name, value) in &layer.aliases {
+        result.insert(name.as_str(), (value, index));
+      }
+    }
+    result
+  }
+
+  pub fn insert(&mut self, layer: usize, name: String, alias: Alias) {
+    let layer = &mut self.layers[layer];
+    let before = layer.aliases.insert(name.clone(), alias.clone());
+    layer.modified = true;
+    self.journal.record(&normalize(&layer.path), &name, before, Some(alias));
+  }
+
+  pub fn remove(&mut self, layer: usize, name: &str) -> Option<Alias> {
+    let layer = &mut self.layers[layer];
+    let res = layer.aliases.remove(name);
+    if res.is_some() {
+      layer.modified = true;
+      self.journal.record(&normalize(&layer.path), name, res.clone(), None);
+    }
+    res
+  }
+
+  /// Replaces every alias of the layer with aliases, recording what changed.
+  pub fn replace_aliases(&mut self, layer: usize, aliases: HashMap<String, Alias>) {
+    let removed: Vec<String> = self.layers[layer].aliases.keys().filter(|name| !aliases.contains_key(*name)).cloned().collect();
+    for name in removed {
+      self.remove(layer, &name);
+    }
+    for (name, alias) in aliases {
+      if self.layers[layer].aliases.get(&name) != Some(&alias) {
+        self.insert(layer, name, alias);
+      }
+    }
+    self.layers[layer].modified = true;
+  }
+
+  /// Returns the index of the layer with the given path, if it is loaded.
+  pub fn find_layer(&self, path: &Path) -> Option<usize> {
+    let path = normalize(path);
+    self.layers.iter().position(|layer| normalize(&layer.path) == path)
+  }
+
+  /// Adds a layer with a higher precedence than every layer loaded so far and returns its index.
+  pub fn push_layer(&mut self, layer: Layer) -> usize {
+    self.layers.push(layer);
+    self.layers.len() - 1
+  }
+}
+
+/// Whether name can be the name of an alias. Every segment between the dots that separate namespaces has to have
+/// something in it other than whitespace, and the last one cannot be `*`, which stands for a whole namespace.
+pub fn is_valid_alias_name(name: &str) -> bool {
+  name.split(NAMESPACE_SEPARATOR).all(|segment| !segment.trim().is_empty()) && !name.ends_with(".*")
+}
+
+/// Whether the alias name is in namespace, directly or in a namespace nested in it.
+pub fn in_namespace(name: &str, namespace: &str) -> bool {
+  name.strip_prefix(namespace).is_some_and(|rest| rest.starts_with(NAMESPACE_SEPARATOR))
+}
+
+/// Makes a path comparable with other paths that point to the same file.
+pub fn normalize(path: &Path) -> PathBuf {
+  path.canonicalize().or_else(|_| path::absolute(path)).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Walks up from start collecting every `.alia` file, ordered from the furthest away to the closest.
+pub fn discover_local_files(start: &Path) -> Vec<PathBuf> {
+  let mut result: Vec<PathBuf> = start.ancestors().map(|dir| dir.join(LOCAL_CONFIG_FILE_NAME)).filter(|path| path.is_file()).collect();
+  result.reverse();
+  result
+}
+
+/// The file `--local` writes to when no `.alia` file exists above the current directory yet.
+pub fn new_local_file_path() -> Option<PathBuf> {
+  env::current_dir().ok().map(|dir| dir.join(LOCAL_CONFIG_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use std::fs;
+  use std::path::PathBuf;
+  use super::{Alias, AliasStore, discover_local_files, in_namespace, is_valid_alias_name, Layer, LayerKind, LOCAL_CONFIG_FILE_NAME};
+
+  fn layer<const SIZE_OF_LAYER: usize>(path: &str, kind: LayerKind, aliases: [(&str, &str); SIZE_OF_LAYER]) -> Layer {
+    Layer::new(PathBuf::from(path), kind, aliases.into_iter().map(|x| (x.0.to_string(), Alias::new(x.1.to_string()))).collect())
+  }
+
+  #[test]
+  fn test_layer_precedence() {
+    let mut store = AliasStore::new(layer("/global", LayerKind::Global, [("build", "make"), ("test", "make test")]));
+    store.push_layer(layer("/project/.alia", LayerKind::Local, [("build", "cargo build")]));
+    assert_eq!(store.get("build"), Some(&Alias::new("cargo build".to_string())));
+    assert_eq!(store.get("test"), Some(&Alias::new("make test".to_string())));
+    assert_eq!(store.origin("build"), Some(1));
+    assert_eq!(store.origin("test"), Some(0));
+    assert_eq!(store.origin("missing"), None);
+    let merged: HashMap<&str, usize> = store.merged().into_iter().map(|(name, (_, index))| (name, index)).collect();
+    assert_eq!(merged, HashMap::from([("build", 1), ("test", 0)]));
+    assert_eq!(store.remove(1, "build"), Some(Alias::new("cargo build".to_string())));
+    assert!(store.layers[1].modified);
+    assert!(!store.layers[0].modified);
+    assert_eq!(store.get("build"), Some(&Alias::new("make".to_string())));
+  }
+
+  #[test]
+  fn test_namespaces() {
+    assert!(is_valid_alias_name("db"));
+    assert!(is_valid_alias_name("db.migrate"));
+    assert!(is_valid_alias_name("k8s.logs.tail"));
+    assert!(is_valid_alias_name("*"));
+    assert!(!is_valid_alias_name(""));
+    assert!(!is_valid_alias_name(".db"));
+    assert!(!is_valid_alias_name("db."));
+    assert!(!is_valid_alias_name("db..migrate"));
+    assert!(!is_valid_alias_name("db. .migrate"));
+    assert!(!is_valid_alias_name("db.*"));
+    assert!(in_namespace("db.migrate", "db"));
+    assert!(in_namespace("db.seed.dev", "db"));
+    assert!(in_namespace("db.seed.dev", "db.seed"));
+    assert!(!in_namespace("db", "db"));
+    assert!(!in_namespace("dbx.migrate", "db"));
+    let store = AliasStore::new(layer("/global", LayerKind::Global, [("db.migrate", "migrate"), ("build", "make")]));
+    assert!(store.is_namespace("db"));
+    assert!(!store.is_namespace("build"));
+    assert!(!store.is_namespace("db.migrate"));
+  }
+
+  #[test]
+  fn test_discover_local_files() {
+    let root = std::env::temp_dir().join(format!("alia_test_discover_{}", std::process::id()));
+    let nested = root.join("a").join("b");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(root.join(LOCAL_CONFIG_FILE_NAME), "").unwrap();
+    fs::write(nested.join(LOCAL_CONFIG_FILE_NAME), "").unwrap();
+    let found = discover_local_files(&nested);
+    let found: Vec<_> = found.iter().filter(|path| path.starts_with(&root)).cloned().collect();
+    assert_eq!(found, vec![root.join(LOCAL_CONFIG_FILE_NAME), nested.join(LOCAL_CONFIG_FILE_NAME)]);
+    fs::remove_dir_all(&root).unwrap();
+  }
+}
@@ -0,0 +1,118 @@
+/// How many suggestions are offered at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Something that was typed could have meant, along with how far it is from what was typed.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Suggestion<T> {
+  pub name: String,
+  pub value: T,
+  pub distance: usize,
+}
+
+/// The edit distance between a and b: how many characters have to be inserted, removed or replaced, or pairs of
+/// neighbouring characters swapped, to turn one into the other. Swapping is counted as a single edit since it is one of
+/// the most common typos.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+  // distances[i][j] is the distance between the first i characters of a and the first j characters of b.
+  let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+  for (i, row) in distances.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  distances[0] = (0..=b.len()).collect();
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let replace = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+      let mut distance = replace.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        distance = distance.min(distances[i - 2][j - 2] + 1);
+      }
+      distances[i][j] = distance;
+    }
+  }
+  distances[a.len()][b.len()]
+}
+
+/// The candidates that input is most likely a typo of, closest first. A candidate qualifies if it is within a third
+/// of the length of input, and at least 1, or if input is the start of it. Ties are broken by preferring candidates
+/// input is the start of, then by name. Candidates are given as their name and the value to suggest for it.
+pub fn closest_matches<T>(input: &str, candidates: impl IntoIterator<Item = (String, T)>) -> Vec<Suggestion<T>> {
+  let max_distance = (input.chars().count() / 3).max(1);
+  let mut matches: Vec<Suggestion<T>> = candidates.into_iter()
+    .map(|(name, value)| Suggestion { distance: edit_distance(input, &name), name, value })
+    .filter(|suggestion| suggestion.distance <= max_distance || (!input.is_empty() && suggestion.name.starts_with(input)))
+    .collect();
+  matches.sort_by(|a, b| (a.distance, !a.name.starts_with(input), &a.name).cmp(&(b.distance, !b.name.starts_with(input), &b.name)));
+  matches.dedup_by(|a, b| a.name == b.name);
+  matches.truncate(MAX_SUGGESTIONS);
+  matches
+}
+
+/// The suggestion to go with in place of what was typed, if there is exactly one that is at most a single character
+/// off.
+pub fn unambiguous<T>(suggestions: &[Suggestion<T>]) -> Option<&Suggestion<T>> {
+  match suggestions {
+    [first, rest @ ..] if first.distance <= 1 && rest.first().is_none_or(|second| second.distance > first.distance) => Some(first),
+    _ => None,
+  }
+}
+
+/// Turns suggestions into a question to add to an error message, such as ` Did you mean add or undo?`. Empty if there
+/// are none.
+pub fn did_you_mean(suggestions: &[String]) -> String {
+  match suggestions {
+    [] => String::new(),
+    [only] => format!(" Did you mean {only}?"),
+    [rest @ .., last] => format!(" Did you mean {} or {last}?", rest.join(", ")),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{closest_matches, did_you_mean, edit_distance, unambiguous};
+
+  fn names(input: &str, candidates: &[&str]) -> Vec<String> {
+    closest_matches(input, candidates.iter().map(|name| (name.to_string(), ()))).into_iter().map(|suggestion| suggestion.name).collect()
+  }
+
+  #[test]
+  fn test_edit_distance() {
+    assert_eq!(edit_distance("", ""), 0);
+    assert_eq!(edit_distance("add", "add"), 0);
+    assert_eq!(edit_distance("ad", "add"), 1);
+    assert_eq!(edit_distance("remvoe", "remove"), 1);
+    assert_eq!(edit_distance("biuld", "build"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "list"), 4);
+    assert_eq!(edit_distance("héllo", "hello"), 1);
+  }
+
+  #[test]
+  fn test_closest_matches() {
+    let commands = ["add", "remove", "rename", "redo", "undo", "list", "history"];
+    assert_eq!(names("ad", &commands), ["add"]);
+    assert_eq!(names("remvoe", &commands), ["remove"]);
+    assert_eq!(names("re", &commands), ["redo", "remove", "rename"]);
+    assert_eq!(names("hist", &commands), ["list", "history"]);
+    assert_eq!(names("xyz", &commands), Vec::<String>::new());
+    assert_eq!(names("ad", &["add", "add"]), ["add"]);
+  }
+
+  #[test]
+  fn test_unambiguous() {
+    let first = |input: &str, candidates: &[&str]| unambiguous(&closest_matches(input, candidates.iter().map(|name| (name.to_string(), ())))).map(|suggestion| suggestion.name.clone());
+    assert_eq!(first("ad", &["add", "list"]), Some("add".to_string()));
+    assert_eq!(first("bild", &["build", "bold"]), None);
+    assert_eq!(first("remvoe", &["remove"]), Some("remove".to_string()));
+    assert_eq!(first("rmeve", &["remove"]), None);
+    assert_eq!(first("b", &[]), None);
+  }
+
+  #[test]
+  fn test_did_you_mean() {
+    assert_eq!(did_you_mean(&[]), "");
+    assert_eq!(did_you_mean(&["add".to_string()]), " Did you mean add?");
+    assert_eq!(did_you_mean(&["a".to_string(), "b".to_string(), "c".to_string()]), " Did you mean a, b or c?");
+  }
+}
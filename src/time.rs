@@ -0,0 +1,48 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time, e.g. `2022-03-01 13:37:00 UTC`.
+pub fn format_timestamp(timestamp: u64) -> String {
+  let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+  let seconds_of_day = timestamp % 86400;
+  format!("{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC", seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60)
+}
+
+/// Formats seconds since the Unix epoch as a compact UTC date and time that sorts in order and can be part of a file
+/// name, e.g. `20220301-133700`.
+pub fn format_compact_timestamp(timestamp: u64) -> String {
+  let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+  let seconds_of_day = timestamp % 86400;
+  format!("{year:04}{month:02}{day:02}-{:02}{:02}{:02}", seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60)
+}
+
+/// Turns a number of days since 1970-01-01 into a (year, month, day) date, using Howard Hinnant's algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days.rem_euclid(146097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+  let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{format_compact_timestamp, format_timestamp};
+
+  #[test]
+  fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+    assert_eq!(format_timestamp(1646141820), "2022-03-01 13:37:00 UTC");
+    assert_eq!(format_compact_timestamp(1646141820), "20220301-133700");
+  }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::storage::{with_lock, write_atomically};
+
+/// How often and when an alias was last executed.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct Usage {
+  pub count: u64,
+  pub last_used: u64,
+}
+
+/// A change to the usage statistics that has not been saved yet.
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum UsageChange {
+  /// The alias with the name was executed at the timestamp.
+  Executed(String, u64),
+  Renamed(String, String),
+}
+
+/// Usage statistics for every alias, kept in their own file next to the global store so that executing an alias
+/// never rewrites the alias files themselves.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct UsageStats {
+  pub path: Option<PathBuf>,
+  pub entries: HashMap<String, Usage>,
+  /// The changes made since the statistics were loaded or last saved, in order.
+  changes: Vec<UsageChange>,
+}
+
+impl UsageStats {
+  /// The file the usage statistics for the global store at config_path are kept in.
+  pub fn path_for(config_path: &Path) -> PathBuf {
+    let mut path = config_path.as_os_str().to_owned();
+    path.push(".usage");
+    path.into()
+  }
+
+  /// Loads the statistics at path. A missing or unreadable file just means nothing has been recorded yet.
+  pub fn load(path: PathBuf) -> Self {
+    let entries = fs::read_to_string(&path).map(|contents| parse_usage(&contents)).unwrap_or_default();
+    Self { path: Some(path), entries, changes: Vec::new() }
+  }
+
+  /// Whether there are changes that have not been saved yet.
+  pub fn modified(&self) -> bool {
+    !self.changes.is_empty()
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Usage> {
+    self.entries.get(name)
+  }
+
+  pub fn record(&mut self, name: &str, timestamp: u64) {
+    self.change(UsageChange::Executed(name.to_string(), timestamp));
+  }
+
+  /// Moves the statistics of the alias from to the alias to, replacing any that to had.
+  pub fn rename(&mut self, from: &str, to: &str) {
+    self.change(UsageChange::Renamed(from.to_string(), to.to_string()));
+  }
+
+  fn change(&mut self, change: UsageChange) {
+    apply_change(&mut self.entries, &change);
+    self.changes.push(change);
+  }
+
+  /// Saves the changes made since the statistics were loaded. Other Alia processes may have saved theirs in the
+  /// meantime, so the file is read again under its lock and the changes are made to what it holds now.
+  pub fn save(&mut self) -> io::Result<()> {
+    let Some(path) = self.path.as_deref().filter(|_| self.modified()) else {
+      return Ok(());
+    };
+    let entries = with_lock(path, || {
+      let mut entries = fs::read_to_string(path).map(|contents| parse_usage(&contents)).unwrap_or_default();
+      for change in &self.changes {
+        apply_change(&mut entries, change);
+      }
+      write_atomically(path, usage_to_string(&entries).as_bytes()).map(|()| entries)
+    }).map_err(|e| io::Error::other(e.to_string()))??;
+    self.entries = entries;
+    self.changes.clear();
+    Ok(())
+  }
+}
+
+fn apply_change(entries: &mut HashMap<String, Usage>, change: &UsageChange) {
+  match change {
+    UsageChange::Executed(name, timestamp) => {
+      let usage = entries.entry(name.clone()).or_default();
+      usage.count += 1;
+      usage.last_used = usage.last_used.max(*timestamp);
+    }
+    UsageChange::Renamed(from, to) => {
+      if let Some(usage) = entries.remove(from) {
+        entries.insert(to.clone(), usage);
+      }
+    }
+  }
+}
+
+/// Each line holds the name of an alias, the number of times it was executed and when it was last executed, separated
+/// by tabs. Lines that do not fit that format are skipped.
+fn parse_usage(contents: &str) -> HashMap<String, Usage> {
+  let mut result = HashMap::new();
+  for line in contents.lines() {
+    let mut fields = line.rsplitn(3, '\t');
+    let (Some(last_used), Some(count), Some(name)) = (fields.next(), fields.next(), fields.next()) else {
+      continue;
+    };
+    if let (Ok(count), Ok(last_used)) = (count.parse(), last_used.parse()) {
+      result.insert(name.to_string(), Usage { count, last_used });
+    }
+  }
+  result
+}
+
+fn usage_to_string(entries: &HashMap<String, Usage>) -> String {
+  let mut names: Vec<&String> = entries.keys().collect();
+  names.sort_unstable();
+  let mut result_string = String::new();
+  for name in names {
+    let usage = &entries[name];
+    result_string.push_str(&format!("{name}\t{}\t{}\n", usage.count, usage.last_used));
+  }
+  result_string
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use std::fs;
+  use super::{parse_usage, Usage, UsageStats, usage_to_string};
+
+  #[test]
+  fn test_usage_round_trip() {
+    let entries = HashMap::from([("build".to_string(), Usage { count: 3, last_used: 100 }), ("with\ttab".to_string(), Usage { count: 1, last_used: 7 })]);
+    assert_eq!(parse_usage(&usage_to_string(&entries)), entries);
+    assert_eq!(parse_usage("broken\nx\tnot a number\t1\ny\t2\t3\n"), HashMap::from([("y".to_string(), Usage { count: 2, last_used: 3 })]));
+  }
+
+  #[test]
+  fn test_concurrent_saves() {
+    let dir = std::env::temp_dir().join(format!("alia-test-usage-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = UsageStats::path_for(&dir.join("cfg.alia"));
+    // Two processes that loaded the statistics before either saved keep both of their uses.
+    let mut first = UsageStats::load(path.clone());
+    let mut second = UsageStats::load(path.clone());
+    first.record("x", 1);
+    second.record("x", 2);
+    second.record("y", 3);
+    first.save().unwrap();
+    second.save().unwrap();
+    assert!(!second.modified());
+    assert_eq!(second.entries, HashMap::from([("x".to_string(), Usage { count: 2, last_used: 2 }), ("y".to_string(), Usage { count: 1, last_used: 3 })]));
+    let mut renamed = UsageStats::load(path.clone());
+    let mut executed = UsageStats::load(path.clone());
+    renamed.rename("x", "z");
+    executed.record("x", 4);
+    executed.save().unwrap();
+    renamed.save().unwrap();
+    assert_eq!(UsageStats::load(path).entries, HashMap::from([("z".to_string(), Usage { count: 3, last_used: 4 }), ("y".to_string(), Usage { count: 1, last_used: 3 })]));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}